@@ -0,0 +1,265 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use na::Vec2;
+
+use shared::{EntityId, EntityTypes, PlayerId, TickNumber, GameEvent, Tick, NEUTRAL_PLAYER_ID};
+use shared::replay::{ReplayKeyframe, ReplayReader, ReplayRecord};
+
+// Player positions are sampled every this many ticks for the movement heatmap
+const MOVEMENT_SAMPLE_TICKS: TickNumber = 5;
+
+// Entity types that are created by using a weapon
+const SHOT_ENTITY_TYPES: &'static [&'static str] = &["bullet", "frag", "player_ball"];
+
+#[derive(Clone, Debug, Default, RustcEncodable)]
+pub struct SurvivalSummary {
+    // Number of lives that ended in a death
+    pub lives: usize,
+
+    pub mean_s: f32,
+    pub median_s: f32,
+    pub max_s: f32,
+}
+
+/// Statistics of one map over all replays, written as JSON
+#[derive(Clone, Debug, Default, RustcEncodable)]
+pub struct MapSummary {
+    pub map_name: String,
+
+    // Number of replays in which the map was played, and the total time played on it
+    pub replays: usize,
+    pub duration_s: f32,
+
+    pub deaths: usize,
+    pub deaths_by_reason: BTreeMap<String, usize>,
+    pub deaths_by_player: BTreeMap<String, usize>,
+
+    // Deaths that another player was responsible for
+    pub kills_by_player: BTreeMap<String, usize>,
+
+    pub item_pickups: usize,
+    pub items_equipped: BTreeMap<String, usize>,
+    pub shots_fired: BTreeMap<String, usize>,
+    pub projectile_impacts: usize,
+
+    pub survival: SurvivalSummary,
+}
+
+/// Everything collected about one map
+#[derive(Default)]
+pub struct MapData {
+    pub summary: MapSummary,
+
+    pub deaths: Vec<Vec2<f32>>,
+    pub item_pickups: Vec<Vec2<f32>>,
+    pub projectile_impacts: Vec<Vec2<f32>>,
+    pub movement: Vec<Vec2<f32>>,
+
+    survival_times_s: Vec<f32>,
+}
+
+/// What is known while going through the ticks of one replay
+struct ReplayState {
+    map_name: String,
+    ticks_per_second: u32,
+
+    player_names: HashMap<PlayerId, String>,
+
+    // Type name and owner of each net entity
+    entities: HashMap<EntityId, (String, PlayerId)>,
+
+    // Tick in which the current entity of each player was created
+    spawn_ticks: HashMap<PlayerId, TickNumber>,
+}
+
+impl ReplayState {
+    fn new(keyframe: &ReplayKeyframe, entity_types: &EntityTypes) -> ReplayState {
+        // The lives of players that exist at the start are ignored, since we don't know when
+        // they started
+        ReplayState {
+            map_name: keyframe.map_name.clone(),
+            ticks_per_second: keyframe.ticks_per_second,
+            player_names: keyframe.players.iter()
+                                  .map(|&(id, ref info)| (id, info.name.clone()))
+                                  .collect(),
+            entities: keyframe.entities.iter()
+                              .map(|&(id, type_id, owner)|
+                                   (id, (entity_types[type_id as usize].0.clone(), owner)))
+                              .collect(),
+            spawn_ticks: HashMap::new(),
+        }
+    }
+
+    fn player_name(&self, id: PlayerId) -> String {
+        self.player_names.get(&id).cloned().unwrap_or(format!("player{}", id))
+    }
+}
+
+/// Aggregates events and player positions of replays per map
+pub struct Analysis {
+    maps: BTreeMap<String, MapData>,
+}
+
+impl Analysis {
+    pub fn new() -> Analysis {
+        Analysis {
+            maps: BTreeMap::new(),
+        }
+    }
+
+    pub fn add_replay(&mut self, path: &str) -> Result<(), String> {
+        info!("analyzing replay {}", path);
+
+        let mut reader = try!(ReplayReader::open(path));
+        let entity_types = reader.header().game_info.entity_types.clone();
+
+        let mut state: Option<ReplayState> = None;
+        let mut maps_played = HashSet::new();
+
+        while let Some(record) = try!(reader.next_record()) {
+            let tick = match record {
+                ReplayRecord::Keyframe(keyframe) => {
+                    if state.is_none() {
+                        state = Some(ReplayState::new(&keyframe, &entity_types));
+                    }
+                    keyframe.tick
+                }
+                ReplayRecord::Tick(tick) => tick,
+            };
+
+            // Replays always start with a keyframe
+            let state = state.as_mut().unwrap();
+            self.process_tick(state, &entity_types, &tick);
+            maps_played.insert(state.map_name.clone());
+        }
+
+        for map_name in maps_played {
+            self.map(&map_name).summary.replays += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Data per map name, with the summaries completed
+    pub fn finish(mut self) -> BTreeMap<String, MapData> {
+        for data in self.maps.values_mut() {
+            let mut times = data.survival_times_s.clone();
+            times.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+            if !times.is_empty() {
+                data.summary.survival = SurvivalSummary {
+                    lives: times.len(),
+                    mean_s: times.iter().fold(0.0, |a, b| a + b) / times.len() as f32,
+                    median_s: times[times.len() / 2],
+                    max_s: times[times.len() - 1],
+                };
+            }
+        }
+
+        self.maps
+    }
+
+    fn map(&mut self, map_name: &str) -> &mut MapData {
+        self.maps.entry(map_name.to_string()).or_insert_with(|| {
+            let mut data = MapData::default();
+            data.summary.map_name = map_name.to_string();
+            data
+        })
+    }
+
+    fn process_tick(&mut self, state: &mut ReplayState, entity_types: &EntityTypes,
+                    tick: &Tick) {
+        for event in &tick.events {
+            self.process_event(state, entity_types, tick.tick_number, event);
+        }
+
+        let data = self.map(&state.map_name);
+        data.summary.duration_s += 1.0 / state.ticks_per_second as f32;
+
+        if tick.tick_number % MOVEMENT_SAMPLE_TICKS == 0 {
+            for &(id, ref components) in &tick.state.entities {
+                let is_player = state.entities.get(&id)
+                                     .map_or(false, |&(ref type_name, _)| type_name == "player");
+                if let (true, Some(position)) = (is_player, components.position.as_ref()) {
+                    data.movement.push(position.p);
+                }
+            }
+        }
+    }
+
+    fn process_event(&mut self, state: &mut ReplayState, entity_types: &EntityTypes,
+                     tick_number: TickNumber, event: &GameEvent) {
+        match *event {
+            GameEvent::ChangeMap(ref map_name) => {
+                state.map_name = map_name.clone();
+                state.spawn_ticks.clear();
+            }
+            GameEvent::ChangeTickRate(ticks_per_second) => {
+                state.ticks_per_second = ticks_per_second;
+            }
+            GameEvent::InitialPlayerList(ref players) => {
+                for &(id, ref info) in players {
+                    state.player_names.insert(id, info.name.clone());
+                }
+            }
+            GameEvent::PlayerJoin(id, ref info) => {
+                state.player_names.insert(id, info.name.clone());
+            }
+            GameEvent::PlayerLeave(id) => {
+                state.player_names.remove(&id);
+                state.spawn_ticks.remove(&id);
+            }
+            GameEvent::CreateEntity(id, type_id, owner) => {
+                let type_name = entity_types[type_id as usize].0.clone();
+
+                if type_name == "player" {
+                    state.spawn_ticks.insert(owner, tick_number);
+                } else if SHOT_ENTITY_TYPES.contains(&&type_name[..]) {
+                    *self.map(&state.map_name).summary.shots_fired
+                         .entry(type_name.clone()).or_insert(0) += 1;
+                }
+
+                state.entities.insert(id, (type_name, owner));
+            }
+            GameEvent::RemoveEntity(id) => {
+                state.entities.remove(&id);
+            }
+            GameEvent::PlayerDied { player_id, position, responsible_player_id, reason } => {
+                let name = state.player_name(player_id);
+                let responsible_name = state.player_name(responsible_player_id);
+                let survival_s = state.spawn_ticks.remove(&player_id).map(|spawn_tick| {
+                    (tick_number - spawn_tick) as f32 / state.ticks_per_second as f32
+                });
+
+                let data = self.map(&state.map_name);
+                data.deaths.push(position);
+                data.summary.deaths += 1;
+                *data.summary.deaths_by_reason.entry(format!("{:?}", reason))
+                     .or_insert(0) += 1;
+                *data.summary.deaths_by_player.entry(name).or_insert(0) += 1;
+                if responsible_player_id != NEUTRAL_PLAYER_ID &&
+                   responsible_player_id != player_id {
+                    *data.summary.kills_by_player.entry(responsible_name).or_insert(0) += 1;
+                }
+                if let Some(survival_s) = survival_s {
+                    data.survival_times_s.push(survival_s);
+                }
+            }
+            GameEvent::PlayerTakeItem { position, .. } => {
+                let data = self.map(&state.map_name);
+                data.item_pickups.push(position);
+                data.summary.item_pickups += 1;
+            }
+            GameEvent::PlayerEquipItem { ref item, .. } => {
+                *self.map(&state.map_name).summary.items_equipped
+                     .entry(item.name().to_string()).or_insert(0) += 1;
+            }
+            GameEvent::ProjectileImpact { position, .. } => {
+                let data = self.map(&state.map_name);
+                data.projectile_impacts.push(position);
+                data.summary.projectile_impacts += 1;
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use na::Vec2;
+use image::{ImageBuffer, Rgb};
+
+use shared::map::MapLine;
+
+pub type HeatmapImage = ImageBuffer<Rgb<u8>, Vec<u8>>;
+
+const WALL_COLOR: [u8; 3] = [255, 255, 255];
+
+/// Density of positions on a map, rendered to an image of the map scaled by `scale`
+pub struct Heatmap {
+    width: usize,
+    height: usize,
+    scale: f32,
+    values: Vec<f32>,
+}
+
+/// Maps a value in [0, 1] to black, blue, red, yellow and finally white
+fn color(t: f32) -> Rgb<u8> {
+    let stops = [[0.0, 0.0, 0.0], [0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0],
+                 [1.0, 1.0, 1.0]];
+    let x = t.max(0.0).min(1.0) * (stops.len() - 1) as f32;
+    let i = (x.floor() as usize).min(stops.len() - 2);
+    let f = x - i as f32;
+
+    let mut rgb = [0; 3];
+    for c in 0..3 {
+        rgb[c] = ((stops[i][c] * (1.0 - f) + stops[i + 1][c] * f) * 255.0) as u8;
+    }
+    Rgb { data: rgb }
+}
+
+impl Heatmap {
+    pub fn new(width_pixels: usize, height_pixels: usize, scale: f32) -> Heatmap {
+        assert!(scale > 0.0);
+
+        let width = ((width_pixels as f32 * scale).ceil() as usize).max(1);
+        let height = ((height_pixels as f32 * scale).ceil() as usize).max(1);
+
+        Heatmap {
+            width: width,
+            height: height,
+            scale: scale,
+            values: vec![0.0; width * height],
+        }
+    }
+
+    /// Adds a blob of the given radius in map pixels around a position
+    pub fn add(&mut self, p: Vec2<f32>, radius: f32) {
+        let c = p * self.scale;
+        let r = (radius * self.scale).max(1.0);
+
+        let x_min = (c.x - r).floor().max(0.0) as usize;
+        let y_min = (c.y - r).floor().max(0.0) as usize;
+        let x_max = ((c.x + r).ceil().max(0.0) as usize).min(self.width - 1);
+        let y_max = ((c.y + r).ceil().max(0.0) as usize).min(self.height - 1);
+
+        for y in y_min..y_max+1 {
+            for x in x_min..x_max+1 {
+                let dx = x as f32 + 0.5 - c.x;
+                let dy = y as f32 + 0.5 - c.y;
+                let d_sq = (dx * dx + dy * dy) / (r * r);
+                if d_sq < 1.0 {
+                    self.values[y * self.width + x] += 1.0 - d_sq;
+                }
+            }
+        }
+    }
+
+    /// Renders the densities with the walls of the map on top
+    pub fn render(&self, lines: &[MapLine]) -> HeatmapImage {
+        let max = self.values.iter().fold(0.0f32, |a, &b| a.max(b));
+
+        let mut image = ImageBuffer::new(self.width as u32, self.height as u32);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                // The square root makes rarely visited places stand out from nothing
+                let t = if max > 0.0 { (self.values[y * self.width + x] / max).sqrt() }
+                        else { 0.0 };
+                image.put_pixel(x as u32, y as u32, color(t));
+            }
+        }
+
+        for line in lines {
+            self.draw_line(&mut image, line.pos_a * self.scale, line.pos_b * self.scale);
+        }
+
+        image
+    }
+
+    fn draw_line(&self, image: &mut HeatmapImage, a: Vec2<f32>, b: Vec2<f32>) {
+        let d = b - a;
+        let steps = d.x.abs().max(d.y.abs()).ceil().max(1.0) as usize;
+
+        for i in 0..steps+1 {
+            let p = a + d * (i as f32 / steps as f32);
+            if p.x >= 0.0 && p.y >= 0.0 {
+                let (x, y) = (p.x as usize, p.y as usize);
+                if x < self.width && y < self.height {
+                    image.put_pixel(x as u32, y as u32, Rgb { data: WALL_COLOR });
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,112 @@
+#[macro_use] extern crate log;
+extern crate env_logger;
+extern crate getopts;
+extern crate rustc_serialize;
+extern crate nalgebra as na;
+extern crate image;
+
+extern crate catch_shared as shared;
+
+mod analysis;
+mod heatmap;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+use getopts::Options;
+use rustc_serialize::json;
+
+use shared::Map;
+use analysis::{Analysis, MapData, MapSummary};
+use heatmap::Heatmap;
+
+// Radius in map pixels of the blob that each position adds to a heatmap
+const POINT_RADIUS: f32 = 24.0;
+const MOVEMENT_RADIUS: f32 = 8.0;
+
+#[derive(RustcEncodable)]
+struct Summary<'a> {
+    replays: &'a [String],
+    maps: Vec<&'a MapSummary>,
+}
+
+fn write_heatmaps(map_name: &str, data: &MapData, output_dir: &Path, scale: f32)
+                  -> Result<(), String> {
+    let map = try!(Map::load(map_name));
+    let stem = Path::new(map_name).file_stem().and_then(|stem| stem.to_str())
+                                  .unwrap_or("map").to_string();
+
+    let layers = [("deaths", &data.deaths, POINT_RADIUS),
+                  ("item_pickups", &data.item_pickups, POINT_RADIUS),
+                  ("projectile_impacts", &data.projectile_impacts, POINT_RADIUS),
+                  ("movement", &data.movement, MOVEMENT_RADIUS)];
+
+    for &(layer, positions, radius) in layers.iter() {
+        let mut heatmap = Heatmap::new(map.width_pixels(), map.height_pixels(), scale);
+        for &p in positions.iter() {
+            heatmap.add(p, radius);
+        }
+
+        let path = output_dir.join(format!("{}_{}.png", stem, layer));
+        info!("writing {}", path.display());
+        try!(heatmap.render(&map.lines).save(&path).map_err(|e| e.to_string()));
+    }
+
+    Ok(())
+}
+
+fn main() {
+    env_logger::init().unwrap();
+
+    let args: Vec<String> = env::args().collect();
+
+    let mut opts = Options::new();
+    opts.optopt("o", "output", "write heatmaps and the summary to this directory", "DIR");
+    opts.optopt("", "scale", "set size of heatmap pixels relative to map pixels", "FACTOR");
+    let matches = match opts.parse(&args[1..]) {
+        Ok(m) => m,
+        Err(e) => panic!(e.to_string())
+    };
+
+    if matches.free.is_empty() {
+        print!("{}", opts.usage(&format!("Usage: {} [options] REPLAY...", args[0])));
+        return;
+    }
+
+    let output_dir = matches.opt_str("o").unwrap_or("analysis".to_string());
+    let scale: f32 = match matches.opt_str("scale") {
+        Some(scale) => scale.parse().unwrap(),
+        None => 0.5,
+    };
+    if scale <= 0.0 {
+        panic!("scale must be positive");
+    }
+
+    let mut analysis = Analysis::new();
+    for path in &matches.free {
+        if let Err(error) = analysis.add_replay(path) {
+            error!("couldn't analyze replay {}: {}", path, error);
+        }
+    }
+    let maps = analysis.finish();
+
+    let output_dir = Path::new(&output_dir);
+    fs::create_dir_all(output_dir).unwrap();
+
+    for (map_name, data) in maps.iter() {
+        if let Err(error) = write_heatmaps(map_name, data, output_dir, scale) {
+            error!("couldn't write heatmaps of map {}: {}", map_name, error);
+        }
+    }
+
+    let summary = Summary {
+        replays: &matches.free,
+        maps: maps.values().map(|data| &data.summary).collect(),
+    };
+    let path = output_dir.join("summary.json");
+    info!("writing {}", path.display());
+    let mut file = File::create(&path).unwrap();
+    write!(file, "{}", json::as_pretty_json(&summary)).unwrap();
+}
@@ -4,9 +4,10 @@ use ecs::ComponentList;
 use shared::util::PeriodicTimer;
 use shared::components::{HasPosition, HasOrientation, HasLinearVelocity, HasShape, HasPlayerState,
                          HasFullPlayerState, HasWallPosition, HasAngularVelocity, HasWall,
-                         HasProjectile};
+                         HasProjectile, HasZoneState};
 pub use shared::components::{NetEntity, Position, Orientation, LinearVelocity, Shape, PlayerState,
-                             Projectile, FullPlayerState, WallPosition, AngularVelocity, Wall};
+                             Projectile, FullPlayerState, WallPosition, AngularVelocity, Wall,
+                             ZoneState};
 
 pub struct DrawPlayer {
     pub scale_x: f32,
@@ -55,6 +56,9 @@ impl Default for DrawItem {
 #[derive(Default)]
 pub struct DrawWall;
 
+#[derive(Default)]
+pub struct DrawZone;
+
 pub trait Interpolatable {
     fn interpolate(&Self, &Self, t: f32) -> Self; 
 }
@@ -90,6 +94,7 @@ components! {
         #[hot] player_state: PlayerState,
         #[hot] full_player_state: FullPlayerState,
         #[hot] wall_position: WallPosition,
+        #[cold] zone_state: ZoneState,
 
         // Shared, constant components
         #[hot] wall: Wall,
@@ -108,6 +113,7 @@ components! {
         #[cold] draw_item: DrawItem,
         #[cold] draw_projectile: DrawProjectile,
         #[cold] draw_wall: DrawWall,
+        #[cold] draw_zone: DrawZone,
     }
 }
 
@@ -216,3 +222,12 @@ impl HasProjectile for Components {
         &mut self.projectile
     }
 }
+
+impl HasZoneState for Components {
+    fn zone_state(&self) -> &ComponentList<Components, ZoneState> {
+        &self.zone_state
+    }
+    fn zone_state_mut(&mut self) -> &mut ComponentList<Components, ZoneState> {
+        &mut self.zone_state
+    }
+}
@@ -1,6 +1,7 @@
 use ecs::{BuildData};
 
-use components::{Components, DrawPlayer, DrawBouncyEnemy, DrawItem, DrawProjectile, DrawWall};
+use components::{Components, DrawPlayer, DrawBouncyEnemy, DrawItem, DrawProjectile, DrawWall,
+                 DrawZone};
 
 /// Adds client-side components that are not synchronized over the net to an entity
 pub fn build_client(type_name: &str,
@@ -18,5 +19,7 @@ pub fn build_client(type_name: &str,
         data.draw_projectile.add(&entity, DrawProjectile::default());
     } else if type_name == "wall_wood" || type_name == "wall_iron" {
         data.draw_wall.add(&entity, DrawWall::default());
+    } else if type_name == "zone" {
+        data.draw_zone.add(&entity, DrawZone::default());
     }
 }
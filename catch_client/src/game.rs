@@ -14,13 +14,17 @@ use na::{Vec2, Mat4, Norm, OrthoMat3};
 use glium::{self, glutin, Display, Surface};
 use glium_text;
 
-use shared::{NEUTRAL_PLAYER_ID, NUM_ITEM_SLOTS, Item, GameEvent, PlayerId, DeathReason};
-use shared::net::{ClientMessage, TimedPlayerInput};
+use shared::{NEUTRAL_PLAYER_ID, NUM_ITEM_SLOTS, Item, GameEvent, GameInfo, PlayerId, DeathReason,
+             MatchPhase, TickNumber, LifetimeStats};
+use shared::player;
+use shared::net::{ClientMessage, ServerMessage, TimedPlayerInput};
+use shared::console::Console;
 use shared::tick::Tick;
 
 use client::Client;
+use replay::ReplayPlayer;
 use state::GameState;
-use player_input::{PlayerInput, InputMap};
+use player_input::{PlayerInput, InputMap, PlayerInputKey};
 use draw_map::DrawMap;
 use particles::Particles;
 use sounds::Sounds;
@@ -28,6 +32,60 @@ use draw::{FLAG_BLUR, FLAG_NONE, DrawOp, DrawList, DrawDrawList, DrawContext, Po
 
 pub const MAX_DEATH_MESSAGES: usize = 4;
 
+// Speed of the free camera in replays, in pixels per second
+const FREE_CAMERA_SPEED: f32 = 300.0;
+
+/// Where the game gets its ticks from
+pub enum TickSource {
+    Live(Client),
+    Replay(ReplayPlayer),
+}
+
+impl TickSource {
+    pub fn my_id(&self) -> PlayerId {
+        match *self {
+            TickSource::Live(ref client) => client.my_id(),
+            TickSource::Replay(ref replay) => replay.my_id(),
+        }
+    }
+
+    pub fn game_info(&self) -> &GameInfo {
+        match *self {
+            TickSource::Live(ref client) => client.game_info(),
+            TickSource::Replay(ref replay) => replay.game_info(),
+        }
+    }
+
+    pub fn num_ticks(&self) -> usize {
+        match *self {
+            TickSource::Live(ref client) => client.num_ticks(),
+            TickSource::Replay(ref replay) => replay.num_ticks(),
+        }
+    }
+
+    pub fn get_next_tick(&mut self) -> &Tick {
+        match *self {
+            TickSource::Live(ref mut client) => &client.get_next_tick().1,
+            TickSource::Replay(ref replay) => replay.get_next_tick(),
+        }
+    }
+
+    pub fn pop_next_tick(&mut self) -> Tick {
+        match *self {
+            TickSource::Live(ref mut client) => client.pop_next_tick().1,
+            TickSource::Replay(ref mut replay) => replay.pop_next_tick(),
+        }
+    }
+
+    /// Can no more ticks arrive?
+    pub fn is_finished(&self) -> bool {
+        match *self {
+            TickSource::Live(_) => false,
+            TickSource::Replay(ref replay) => replay.is_finished(),
+        }
+    }
+}
+
 struct DrawListsOp<'a, 'b: 'a> {
     draw_draw_list: RefMut<'a, DrawDrawList>,
     draw_list: &'a DrawList,
@@ -52,7 +110,7 @@ impl<'a, 'b> DrawOp for DrawListsOp<'a, 'b> {
 pub struct Game {
     quit: bool,
 
-    client: Client,
+    source: TickSource,
     state: GameState,
 
     player_input_map: InputMap,
@@ -68,6 +126,12 @@ pub struct Game {
     death_messages: VecDeque<(String, (f32, f32, f32))>,
     draw_player_stats: bool,
 
+    // Best players over all matches on the server, if it keeps stats
+    leaderboard: Vec<(String, LifetimeStats)>,
+
+    // Password and stdin reader for sending admin commands, if enabled
+    admin_console: Option<(String, Console)>,
+
     draw_list: DrawList,
     draw_draw_list: RefCell<DrawDrawList>,
     draw_map: DrawMap,
@@ -82,21 +146,25 @@ pub struct Game {
     cam_pos: Vec2<f32>,
     fps: f32,
 
+    // Player that the camera follows in replays. Without one, the camera is moved freely.
+    follow_player: Option<PlayerId>,
+
     print_prof: bool,
 }
 
 impl Game {
-    // The given client is expected to be connected already
-    pub fn new(connected_client: Client,
+    // A live client is expected to be connected already
+    pub fn new(source: TickSource,
                player_input_map: InputMap,
                post_settings: PostSettings,
+               volume: f32,
                display: Display) -> Game {
-        let state = GameState::new(connected_client.my_id(), connected_client.game_info());
+        let state = GameState::new(source.my_id(), source.game_info());
         let draw_draw_list = RefCell::new(DrawDrawList::new(&display).unwrap());
         let draw_map = DrawMap::load(&state.map).unwrap();
         let particles = Particles::new(&display);
         let draw_post = Post::new(post_settings, &display);
-        let sounds = Sounds::load().unwrap();
+        let sounds = Sounds::load(volume).unwrap();
         let text_system = glium_text::TextSystem::new(&display);
         let font_file = File::open(&Path::new("data/ProggyClean.ttf"));
         let font = glium_text::FontTexture::new(&display, font_file.unwrap(), 70).unwrap();
@@ -104,7 +172,7 @@ impl Game {
         Game {
             quit: false,
 
-            client: connected_client,
+            source: source,
 
             state: state,
 
@@ -120,6 +188,8 @@ impl Game {
 
             death_messages: VecDeque::new(),
             draw_player_stats: false,
+            leaderboard: Vec::new(),
+            admin_console: None,
 
             draw_list: DrawList::new(),
             draw_draw_list: draw_draw_list,
@@ -134,6 +204,7 @@ impl Game {
 
             cam_pos: Vec2::new(0.0, 0.0),
             fps: 0.0,
+            follow_player: None,
 
             print_prof: false,
         }
@@ -181,16 +252,89 @@ impl Game {
     fn wait_first_ticks(&mut self) {
         info!("waiting to receive first ticks from server... ");
 
-        while self.client.num_ticks() < self.interpolation_ticks {
+        while self.source.num_ticks() < self.interpolation_ticks {
+            if self.source.is_finished() {
+                error!("not enough ticks to start playing");
+                self.quit = true;
+                return;
+            }
             self.client_service();
         }
 
-        info!("done! have {} ticks", self.client.num_ticks());
+        info!("done! have {} ticks", self.source.num_ticks());
+
+        match self.source {
+            TickSource::Live(_) => {
+                while self.source.num_ticks() >=2 { // catch up
+                    debug!("starting initial tick {}", self.source.get_next_tick().tick_number);
+                    self.start_tick();
+                }
+            }
+            TickSource::Replay(_) => {
+                // Replays always have ticks queued, so start at the beginning
+                self.start_tick();
+            }
+        }
+    }
 
-        while self.client.num_ticks() >=2 { // catch up
-            debug!("starting initial tick {}", self.client.get_next_tick().1.tick_number);
-            self.start_tick();
+    /// Continues a replay at the last keyframe not after the given tick, starting over with
+    /// a fresh game state
+    fn seek_replay(&mut self, tick_number: TickNumber) {
+        if let TickSource::Replay(ref mut replay) = self.source {
+            if let Err(error) = replay.seek(tick_number) {
+                warn!("couldn't seek replay: {}", error);
+                return;
+            }
+        } else {
+            return;
         }
+
+        self.state = GameState::new(self.source.my_id(), self.source.game_info());
+        self.draw_map = DrawMap::load(&self.state.map).unwrap();
+        self.death_messages.clear();
+        self.current_tick = None;
+        self.tick_progress = 0.0;
+
+        self.wait_first_ticks();
+    }
+
+    /// Seeks to the keyframe before or after the current tick
+    fn seek_replay_keyframe(&mut self, forward: bool) {
+        let current = match self.state.tick_number {
+            Some(tick_number) => tick_number,
+            None => return,
+        };
+        let keyframes = match self.source {
+            TickSource::Replay(ref replay) => replay.keyframes(),
+            TickSource::Live(_) => return,
+        };
+
+        let target = if forward {
+            keyframes.iter().find(|&&tick_number| tick_number > current).cloned()
+        } else {
+            // Skip the keyframe that we are just past, so that repeated seeking goes back
+            let ticks_per_second = self.state.game_info.ticks_per_second;
+            keyframes.iter().rev()
+                .find(|&&tick_number| tick_number + ticks_per_second < current).cloned()
+                .or(keyframes.first().cloned())
+        };
+
+        if let Some(target) = target {
+            info!("seeking replay to tick {}", target);
+            self.seek_replay(target);
+        }
+    }
+
+    /// Switches the camera in replays to the next player, or to free movement after the
+    /// last player
+    fn follow_next_player(&mut self) {
+        let mut player_ids = self.state.players().keys().cloned().collect::<Vec<_>>();
+        player_ids.sort();
+
+        self.follow_player = match self.follow_player {
+            None => player_ids.first().cloned(),
+            Some(id) => player_ids.iter().find(|&&other| other > id).cloned(),
+        };
     }
 
     /// Starts the next tick in the queue, loading its state and running its events.
@@ -198,9 +342,9 @@ impl Game {
     fn start_tick(&mut self) {
         let _g = hprof::enter("start tick");
 
-        assert!(self.client.num_ticks() >= 2);
+        assert!(self.source.num_ticks() >= 2);
 
-        let tick = self.client.pop_next_tick().1;
+        let tick = self.source.pop_next_tick();
 
         {
             let _g = hprof::enter("events");
@@ -211,9 +355,20 @@ impl Game {
             }
         }
 
-        let next_tick = &self.client.get_next_tick().1;
-
         self.state.run_tick(&tick);
+
+        // The map might have been changed in this tick
+        let map_changed = tick.events.iter().any(|event| {
+            match *event {
+                GameEvent::ChangeMap(_) => true,
+                _ => false
+            }
+        });
+        if map_changed {
+            self.draw_map = DrawMap::load(&self.state.map).unwrap();
+        }
+
+        let next_tick = self.source.get_next_tick();
         self.state.load_interp_tick_state(&tick, next_tick);
         self.current_tick = Some(tick);
     }
@@ -221,16 +376,53 @@ impl Game {
     fn client_service(&mut self) {
         let _g = hprof::enter("client service");
 
-        if let Err(error) = self.client.service() {
+        let client = match self.source {
+            TickSource::Live(ref mut client) => client,
+            TickSource::Replay(ref mut replay) => {
+                replay.service();
+                return;
+            }
+        };
+
+        if let Err(error) = client.service() {
             warn!("error while servicing: {}", error);
             // TODO: handle disconnect etc.
         }
 
-        while let Some(_message) = self.client.pop_message() {
-            // TODO
+        while let Some(message) = client.pop_message() {
+            match message {
+                ServerMessage::AdminReply(reply) => {
+                    println!("{}", reply);
+                }
+                ServerMessage::Kicked { reason } => {
+                    info!("kicked from the server: {}", reason);
+                    self.quit = true;
+                }
+                ServerMessage::Leaderboard(leaderboard) => {
+                    self.leaderboard = leaderboard;
+                }
+                _ => {
+                    warn!("received unexpected message: {:?}", message);
+                }
+            }
+        }
+
+        // Forward admin commands typed into the terminal to the server
+        if let Some((ref password, ref mut console)) = self.admin_console {
+            while let Some(command) = console.next_line() {
+                client.send(&ClientMessage::AdminCommand {
+                    password: password.clone(),
+                    command: command,
+                });
+            }
         }
     }
 
+    /// Allows sending admin commands to the server by typing them into stdin
+    pub fn enable_admin_console(&mut self, password: String) {
+        self.admin_console = Some((password, Console::start()));
+    }
+
     fn read_input(&mut self) {
         let _g = hprof::enter("read input");
 
@@ -251,6 +443,8 @@ impl Game {
                         } else if key == glutin::VirtualKeyCode::Tab {
                             self.draw_player_stats = true;
                             continue;
+                        } else if self.read_replay_input(key) {
+                            continue;
                         }
                     } else if state == glutin::ElementState::Released {
                         if key == glutin::VirtualKeyCode::Tab {
@@ -266,10 +460,47 @@ impl Game {
         }
     }
 
+    /// Handles keys for controlling replays. Returns true if the key was used.
+    fn read_replay_input(&mut self, key: glutin::VirtualKeyCode) -> bool {
+        {
+            let replay = match self.source {
+                TickSource::Replay(ref mut replay) => replay,
+                TickSource::Live(_) => return false,
+            };
+
+            match key {
+                glutin::VirtualKeyCode::Space => replay.toggle_pause(),
+                glutin::VirtualKeyCode::Add | glutin::VirtualKeyCode::Equals => replay.faster(),
+                glutin::VirtualKeyCode::Subtract | glutin::VirtualKeyCode::Minus =>
+                    replay.slower(),
+                glutin::VirtualKeyCode::PageUp |
+                glutin::VirtualKeyCode::PageDown |
+                glutin::VirtualKeyCode::Home |
+                glutin::VirtualKeyCode::C => {}
+                _ => return false,
+            }
+        }
+
+        match key {
+            glutin::VirtualKeyCode::PageUp => self.seek_replay_keyframe(false),
+            glutin::VirtualKeyCode::PageDown => self.seek_replay_keyframe(true),
+            glutin::VirtualKeyCode::Home => self.seek_replay(0),
+            glutin::VirtualKeyCode::C => self.follow_next_player(),
+            _ => {}
+        }
+
+        true
+    }
+
     fn send_input(&mut self, simulation_time_s: f32) {
         let _g = hprof::enter("send input");
 
-        self.client.send(&ClientMessage::PlayerInput(
+        let client = match self.source {
+            TickSource::Live(ref client) => client,
+            TickSource::Replay(_) => return,
+        };
+
+        client.send(&ClientMessage::PlayerInput(
             TimedPlayerInput {
                 duration_s: simulation_time_s,
                 input: self.player_input.clone(),
@@ -283,34 +514,39 @@ impl Game {
         assert!(self.current_tick.is_some());
 
         if self.tick_progress < 1.0 {
-            self.time_factor = {
-                if self.client.num_ticks() > 2 {
-                    1.05 + (1.0 - (self.client.num_ticks() as f32 / -20.0).exp())
-                } else if self.client.num_ticks() < 2 && self.tick_progress > 0.5 {
-                    0.75 // Is this a stupid idea?
-                } else {
-                    1.0
+            let num_ticks = self.source.num_ticks();
+            self.time_factor = match self.source {
+                // Replays have all ticks available, so they are played at the chosen speed
+                TickSource::Replay(ref replay) => replay.time_factor(),
+                TickSource::Live(_) => {
+                    if num_ticks > 2 {
+                        1.05 + (1.0 - (num_ticks as f32 / -20.0).exp())
+                    } else if num_ticks < 2 && self.tick_progress > 0.5 {
+                        0.75 // Is this a stupid idea?
+                    } else {
+                        1.0
+                    }
                 }
             };
 
             if self.time_factor != 1.0 {
                 debug!("time factor {}, queued {} ticks, progress {}",
-                       self.time_factor, self.client.num_ticks(), self.tick_progress);
+                       self.time_factor, num_ticks, self.tick_progress);
             }
 
             self.tick_progress += self.time_factor * 
                                   simulation_time_s *
-                                  self.client.game_info().ticks_per_second as f32;
+                                  self.state.game_info.ticks_per_second as f32;
         }
 
         while self.tick_progress >= 1.0 {
             // Load the next tick state if we can interpolate into the following tick
-            if self.client.num_ticks() >= 2 {
+            if self.source.num_ticks() >= 2 {
                 self.start_tick();
                 self.tick_progress -= 1.0;
             } else {
                 debug!("waiting to receive next tick (num queued ticks: {})",
-                       self.client.num_ticks());
+                       self.source.num_ticks());
                 break;
             }
         }
@@ -343,6 +579,14 @@ impl Game {
                                               rand::random::<f32>() * 24.0, 1.0);
                 }
             }
+            &GameEvent::PlayerTagged {
+                player_id,
+                position,
+                responsible_player_id,
+            } => {
+                self.add_tag_message(player_id, responsible_player_id);
+                self.sounds.play("take_item", position);
+            }
             &GameEvent::PlayerDash {
                 player_id: _,
                 position,
@@ -414,6 +658,22 @@ impl Game {
                                               rand::random::<f32>() * 5.0, 1.0);
                 }
             }
+            &GameEvent::WallBroken {
+                pos_a,
+                pos_b,
+            } => {
+                // Splinters along the wall
+                let num = ((pos_b - pos_a).norm() * 2.0) as usize;
+                let color = [0.55, 0.35, 0.15];
+                for _ in 0..num {
+                    let position = pos_a + (pos_b - pos_a) * rand::random::<f32>();
+                    self.particles.spawn_cone(0.3 + rand::random::<f32>() * 0.4,
+                                              color, color, 2.0 * rand::random::<f32>() + 1.0,
+                                              position, 0.0, f32::consts::PI * 2.0,
+                                              30.0 + rand::random::<f32>() * 30.0,
+                                              rand::random::<f32>() * 10.0, 1.0);
+                }
+            }
             &GameEvent::ProjectileImpact {
                 position,
                 strength
@@ -460,7 +720,7 @@ impl Game {
             target.clear_color_and_depth((0.1, 0.1, 0.1, 1.0), -1.0);
         }
 
-        self.cam_pos = self.get_my_player_position().unwrap_or(self.cam_pos);
+        self.update_camera(simulation_time_s);
         //self.cam_pos = self.cam_pos + (pos - self.cam_pos) * 0.15;
 
         let (draw_width, draw_height) = target.get_dimensions();
@@ -519,6 +779,8 @@ impl Game {
                 .spawn_particles(&mut self.state.world.data, simulation_time_s,
                                  &mut self.particles);
 
+            self.state.world.systems.draw_zone_system
+                .draw(&mut self.state.world.data, &mut draw_list);
             self.state.world.systems.draw_player_system
                 .draw(&mut self.state.world.data, &mut draw_list);
             self.state.world.systems.draw_wall_system
@@ -568,6 +830,8 @@ impl Game {
             self.draw_player_text(&draw_context.proj_mat, &mut target);
             self.draw_death_messages(&draw_context.proj_mat, &mut target);
             self.draw_player_names(&draw_context.proj_mat, &mut target);
+            self.draw_match_phase(&draw_context.proj_mat, &mut target);
+            self.draw_catcher(&draw_context.proj_mat, &mut target);
             if self.draw_player_stats {
                 self.draw_player_stats(&draw_context.proj_mat, &mut target);
             }
@@ -613,6 +877,19 @@ impl Game {
         self.death_messages.push_back(message);
     }
 
+    fn add_tag_message(&mut self, player_id: PlayerId, responsible_player_id: PlayerId) {
+        if self.death_messages.len() == MAX_DEATH_MESSAGES {
+            self.death_messages.pop_front();
+        }
+
+        let name = self.state.players()[&player_id].name.clone();
+        let responsible_name = self.state.players()[&responsible_player_id].name.clone();
+        let message = (format!("{} tagged {}", responsible_name, name), (0.0, 1.0, 0.0));
+
+        debug!("tag message: {}", message.0);
+        self.death_messages.push_back(message);
+    }
+
     fn draw_death_messages<S: Surface>(&mut self, proj_mat: &Mat4<f32>, target: &mut S) {
         let (w, _) = target.get_dimensions();
 
@@ -655,11 +932,40 @@ impl Game {
         let x2 = x1 + 100.0;
         let x3 = x2 + 100.0;
 
-        let color = (1.0, 1.0, 1.0, 1.0);
+        let white = (1.0, 1.0, 1.0, 1.0);
         let size = 12.0;
 
-        let players = self.state.players().clone();
-        for (_, info) in players.iter() {
+        // In modes with teams, players are listed below the score of their team
+        let team_scores = self.state.team_scores.clone();
+        let mut players = self.state.players().values().cloned().collect::<Vec<_>>();
+        players.sort_by(|a, b| (a.team, b.stats.score).cmp(&(b.team, a.stats.score)));
+
+        let mut current_team = None;
+        for info in players.iter() {
+            if !team_scores.is_empty() && info.team != current_team {
+                current_team = info.team;
+
+                if let Some(team) = info.team {
+                    let (r, g, b) = player::color_to_rgb(player::team_color(team));
+                    let score = team_scores.iter().find(|&&(t, _)| t == team)
+                                           .map_or(0, |&(_, score)| score);
+                    y += 10.0;
+                    self.draw_text((r, g, b, 1.0), x1, y, &format!("team {}", team + 1),
+                                   proj_mat, size, target);
+                    self.draw_text((r, g, b, 1.0), x2, y, &format!("{}", score),
+                                   proj_mat, size, target);
+                    y += 30.0;
+                }
+            }
+
+            let color = match info.team {
+                Some(team) => {
+                    let (r, g, b) = player::color_to_rgb(player::team_color(team));
+                    (r, g, b, 1.0)
+                }
+                None => white,
+            };
+
             self.draw_text(color, x1, y, &info.name, proj_mat, size, target);
             self.draw_text(color, x2, y, &format!("{}", info.stats.score), proj_mat, size,
                            target);
@@ -667,6 +973,91 @@ impl Game {
                           target);
             y += 30.0;
         }
+
+        self.draw_leaderboard(proj_mat, target);
+    }
+
+    /// Lists the best players over all matches next to the scores of the current match
+    fn draw_leaderboard<S: Surface>(&mut self, proj_mat: &Mat4<f32>, target: &mut S) {
+        if self.leaderboard.is_empty() {
+            return;
+        }
+
+        let (w, _) = target.get_dimensions();
+        let x1 = w as f32 / 2.0 + 250.0;
+        let x2 = x1 + 100.0;
+        let x3 = x2 + 60.0;
+        let x4 = x3 + 60.0;
+        let mut y = 100.0;
+
+        let white = (1.0, 1.0, 1.0, 1.0);
+        let gray = (0.6, 0.6, 0.6, 1.0);
+        let size = 12.0;
+
+        self.draw_text(gray, x1, y, "all time", proj_mat, size, target);
+        self.draw_text(gray, x2, y, "kills", proj_mat, size, target);
+        self.draw_text(gray, x3, y, "catches", proj_mat, size, target);
+        self.draw_text(gray, x4, y, "matches", proj_mat, size, target);
+        y += 30.0;
+
+        let leaderboard = self.leaderboard.clone();
+        for &(ref name, ref stats) in leaderboard.iter() {
+            self.draw_text(white, x1, y, name, proj_mat, size, target);
+            self.draw_text(white, x2, y, &format!("{}", stats.total_kills()), proj_mat, size,
+                           target);
+            self.draw_text(white, x3, y, &format!("{}", stats.catches), proj_mat, size, target);
+            self.draw_text(white, x4, y, &format!("{}", stats.matches_played), proj_mat, size,
+                           target);
+            y += 30.0;
+        }
+    }
+
+    fn draw_match_phase<S: Surface>(&mut self, proj_mat: &Mat4<f32>, target: &mut S) {
+        let (phase, _, _) = match self.state.match_phase {
+            Some(match_phase) => match_phase,
+            None => return,
+        };
+        let time_left_s = self.state.match_phase_time_left_s();
+
+        let text = match (phase, time_left_s) {
+            (MatchPhase::Warmup, _) =>
+                "warmup - waiting for players".to_string(),
+            (MatchPhase::Countdown, Some(t)) =>
+                format!("match starts in {}", t.ceil() as u32),
+            (MatchPhase::Playing, Some(t)) =>
+                format!("{}:{:02}", t as u32 / 60, t as u32 % 60),
+            (MatchPhase::Intermission, _) =>
+                match self.state.next_map {
+                    Some(ref map_name) => format!("match over - next map: {}", map_name),
+                    None => "match over".to_string(),
+                },
+            _ => return,
+        };
+
+        let (w, _) = target.get_dimensions();
+        let color = (1.0, 1.0, 1.0, 1.0);
+        self.draw_text(color, w as f32 / 2.0 - 100.0, 10.0, &text, proj_mat, 12.0, target);
+    }
+
+    fn draw_catcher<S: Surface>(&mut self, proj_mat: &Mat4<f32>, target: &mut S) {
+        let (player_id, _) = match self.state.catcher {
+            Some(catcher) => catcher,
+            None => return,
+        };
+        let time_s = self.state.catcher_time_s().unwrap_or(0.0);
+
+        let text = if player_id == self.source.my_id() {
+            format!("you are it! ({}s)", time_s as u32)
+        } else {
+            match self.state.players().get(&player_id) {
+                Some(info) => format!("{} is it ({}s)", info.name, time_s as u32),
+                None => return,
+            }
+        };
+
+        let (w, _) = target.get_dimensions();
+        let color = (0.0, 1.0, 0.0, 1.0);
+        self.draw_text(color, w as f32 / 2.0 - 100.0, 30.0, &text, proj_mat, 12.0, target);
     }
 
     fn draw_debug_text<S: Surface>(&mut self, proj_mat: &Mat4<f32>, target: &mut S) {
@@ -677,7 +1068,7 @@ impl Game {
         let s = &format!("fps: {:.1}", self.fps);
         self.draw_text(color, 10.0, 10.0, s, proj_mat, size, target);
 
-        let s = &format!("queued ticks: {}", self.client.num_ticks());
+        let s = &format!("queued ticks: {}", self.source.num_ticks());
         self.draw_text(color, 10.0, 10.0 + r, s, proj_mat, size, target);
 
         let s = &format!("tick progress: {:.1}", self.tick_progress);
@@ -698,6 +1089,23 @@ impl Game {
             let s = &format!("player speed: {:.1}", speed);
             self.draw_text(color, 10.0, 10.0 + 5.0*r, s, proj_mat, size, target);
         }
+
+        let replay_text = match self.source {
+            TickSource::Replay(ref replay) => {
+                let state = if replay.is_paused() {
+                    "paused".to_string()
+                } else if replay.is_finished() && replay.num_ticks() < 2 {
+                    "finished".to_string()
+                } else {
+                    format!("{}x", replay.speed())
+                };
+                Some(format!("replay tick {}: {}", self.state.tick_number.unwrap_or(0), state))
+            }
+            TickSource::Live(_) => None,
+        };
+        if let Some(s) = replay_text {
+            self.draw_text(color, 10.0, 10.0 + 6.0*r, &s, proj_mat, size, target);
+        }
     }
 
     fn draw_player_text<S: Surface>(&mut self, proj_mat: &Mat4<f32>, target: &mut S) {
@@ -818,16 +1226,45 @@ impl Game {
     }
     
     fn get_my_player_entity(&mut self) -> Option<ecs::Entity> {
-        let my_id = self.client.my_id();
+        let my_id = self.source.my_id();
         self.get_player_entity(my_id)
     }
 
-    fn get_my_player_position(&mut self) -> Option<Vec2<f32>> {
-        self.get_my_player_entity().map(|entity| {
+    fn get_player_position(&mut self, player_id: PlayerId) -> Option<Vec2<f32>> {
+        self.get_player_entity(player_id).map(|entity| {
             self.state.world.with_entity_data(&entity, |e, c| {
                 c.position[e].p
             }).unwrap()
         })
     }
+
+    /// Centers the camera on our player. In replays, the camera follows the chosen player or
+    /// is moved with the arrow keys.
+    fn update_camera(&mut self, simulation_time_s: f32) {
+        let target = match self.source {
+            TickSource::Live(_) => Some(self.source.my_id()),
+            TickSource::Replay(_) => self.follow_player,
+        };
+
+        if let Some(player_id) = target {
+            self.cam_pos = self.get_player_position(player_id).unwrap_or(self.cam_pos);
+            return;
+        }
+
+        let mut direction = Vec2::new(0.0, 0.0);
+        if self.player_input.has(PlayerInputKey::Left) {
+            direction.x -= 1.0;
+        }
+        if self.player_input.has(PlayerInputKey::Right) {
+            direction.x += 1.0;
+        }
+        if self.player_input.has(PlayerInputKey::Forward) {
+            direction.y += 1.0;
+        }
+        if self.player_input.has(PlayerInputKey::Back) {
+            direction.y -= 1.0;
+        }
+        self.cam_pos = self.cam_pos + direction * FREE_CAMERA_SPEED * simulation_time_s;
+    }
 }
 
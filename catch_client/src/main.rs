@@ -32,18 +32,33 @@ mod particles;
 mod sounds;
 mod dummy;
 mod draw;
+mod settings;
+mod replay;
 
 use std::env;
+use std::path::Path;
+use std::str::FromStr;
 
-use getopts::Options;
+use getopts::{Options, Matches};
 
 use glium::DisplayBuild;
 
 use client::Client;
 use player_input::InputMap;
-use game::Game;
+use game::{Game, TickSource};
 use draw::PostSettings;
 use dummy::DummyClient;
+use settings::{Settings, DEFAULT_SETTINGS_FILE};
+use replay::ReplayPlayer;
+
+fn parse_opt<T: FromStr>(matches: &Matches, name: &str) -> Option<T> {
+    matches.opt_str(name).map(|s| {
+        match s.parse() {
+            Ok(value) => value,
+            Err(_) => panic!("invalid value for option {}: {}", name, s)
+        }
+    })
+}
 
 fn main() {
     env_logger::init().unwrap();
@@ -52,7 +67,19 @@ fn main() {
 
     let mut opts = Options::new();
     opts.optopt("c", "connect", "set server address to connect to", "ADDRESS");
+    opts.optopt("p", "port", "set server port to connect to", "PORT");
+    opts.optopt("n", "name", "set player name", "NAME");
+    opts.optopt("s", "settings", "load and store settings in this file", "FILE");
+    opts.optopt("", "width", "set window width", "PIXELS");
+    opts.optopt("", "height", "set window height", "PIXELS");
+    opts.optflag("", "fullscreen", "run in fullscreen mode");
+    opts.optflag("", "windowed", "run in windowed mode");
+    opts.optflag("", "blur", "enable blur post processing");
+    opts.optflag("", "no-blur", "disable blur post processing");
+    opts.optopt("", "volume", "set sound volume", "0..1");
+    opts.optopt("", "rcon", "send admin commands read from stdin with this password", "PASSWORD");
     opts.optflag("", "dummy", "create a dummy client without graphical display");
+    opts.optopt("", "replay", "watch a replay file recorded by the server", "FILE");
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => m,
         Err(e) => panic!(e.to_string())
@@ -62,38 +89,119 @@ fn main() {
         None => "127.0.0.1".to_string()
     };
     let dummy = matches.opt_present("dummy");
+    let replay_file = matches.opt_str("replay");
+    if dummy && replay_file.is_some() {
+        error!("Dummy clients can't watch replays");
+        return;
+    }
+
+    // Load settings, then let the command line options override them for this run only
+    let settings_file = matches.opt_str("s").unwrap_or(DEFAULT_SETTINGS_FILE.to_string());
+    let mut settings = if !dummy {
+        if !Path::new(&settings_file).exists() {
+            // Give the player a file to edit
+            if let Err(error) = Settings::default().save(&settings_file) {
+                warn!("Couldn't save settings: {}", error);
+            }
+        }
+
+        match Settings::load(&settings_file) {
+            Ok(settings) => settings,
+            Err(error) => {
+                warn!("Couldn't load settings from {}, using defaults: {}",
+                      settings_file, error);
+                Settings::default()
+            }
+        }
+    } else {
+        Settings {
+            name: "bot".to_string(),
+            .. Settings::default()
+        }
+    };
+
+    if let Some(name) = matches.opt_str("n") {
+        settings.name = name;
+    }
+    if let Some(port) = parse_opt(&matches, "p") {
+        settings.port = port;
+    }
+    if let Some(width) = parse_opt(&matches, "width") {
+        settings.window_width = width;
+    }
+    if let Some(height) = parse_opt(&matches, "height") {
+        settings.window_height = height;
+    }
+    if matches.opt_present("fullscreen") {
+        settings.fullscreen = true;
+    } else if matches.opt_present("windowed") {
+        settings.fullscreen = false;
+    }
+    if matches.opt_present("blur") {
+        settings.blur = true;
+    } else if matches.opt_present("no-blur") {
+        settings.blur = false;
+    }
+    if let Some(volume) = parse_opt::<f32>(&matches, "volume") {
+        settings.volume = volume.max(0.0).min(1.0);
+    }
+
+    if let Err(error) = shared::player::validate_name(&settings.name) {
+        error!("Invalid player name \"{}\": {}", settings.name, error);
+        return;
+    }
 
     let post_settings = PostSettings {
-        blur: false,
+        blur: settings.blur,
     };
 
-    // Connect
-    enet::initialize().unwrap();
-    let port = 9988;
-    info!("connecting to {}:{}", address, port);
-    let name = if dummy { "bot" } else { "leo" };
-    let mut client = Client::connect(5000,
-                                     address,
-                                     9988,
-                                     name.to_string()).unwrap();
-    client.finish_connecting(5000).unwrap();
+    let source = match replay_file {
+        Some(replay_file) => {
+            match ReplayPlayer::open(&replay_file) {
+                Ok(replay) => TickSource::Replay(replay),
+                Err(error) => {
+                    error!("Couldn't open replay {}: {}", replay_file, error);
+                    return;
+                }
+            }
+        }
+        None => {
+            // Connect
+            enet::initialize().unwrap();
+            info!("connecting to {}:{}", address, settings.port);
+            let mut client = Client::connect(5000,
+                                             address,
+                                             settings.port,
+                                             settings.name.clone()).unwrap();
+            client.finish_connecting(5000).unwrap();
 
-    info!("connected to server! My id: {}", client.my_id());
-    info!("game info: {:?}", client.game_info());
+            info!("connected to server! My id: {}", client.my_id());
+            info!("game info: {:?}", client.game_info());
+
+            TickSource::Live(client)
+        }
+    };
 
     if !dummy {
-        let display = glium::glutin::WindowBuilder::new()
-            .with_dimensions(640, 480)
-            .with_title(format!("Catching game"))
-            .build_glium()
-            .unwrap();
+        let mut window_builder = glium::glutin::WindowBuilder::new()
+            .with_dimensions(settings.window_width, settings.window_height)
+            .with_title(format!("Catching game"));
+        if settings.fullscreen {
+            window_builder =
+                window_builder.with_fullscreen(glium::glutin::get_primary_monitor());
+        }
+        let display = window_builder.build_glium().unwrap();
 
-        let mut game = Game::new(client,
+        let mut game = Game::new(source,
                                  InputMap::new(),
                                  post_settings,
+                                 settings.volume,
                                  display);
+        if let Some(password) = matches.opt_str("rcon") {
+            game.enable_admin_console(password);
+        }
         game.run();
-    } else {
+    } else if let TickSource::Live(client) = source {
         let mut dummy = DummyClient::new(client);
         dummy.run();
     }
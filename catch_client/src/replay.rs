@@ -0,0 +1,185 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::collections::VecDeque;
+
+use shared::{NEUTRAL_PLAYER_ID, GameEvent, GameInfo, PlayerId, Tick, TickNumber};
+use shared::replay::{ReplayHeader, ReplayKeyframe, ReplayReader, ReplayRecord};
+
+// Playback speeds that can be chosen from
+pub const SPEEDS: &'static [f32] = &[0.25, 0.5, 1.0, 2.0, 4.0];
+const NORMAL_SPEED_INDEX: usize = 2;
+
+// Number of ticks that are read in advance, enough for the highest speed
+const READ_AHEAD_TICKS: usize = 8;
+
+/// Plays back a replay file recorded by the server. Offers the same tick queue as `Client`,
+/// so that the game can be driven by either one.
+pub struct ReplayPlayer {
+    reader: ReplayReader<BufReader<File>>,
+
+    // Game info as of the keyframe that playback was started at
+    game_info: GameInfo,
+
+    tick_deque: VecDeque<Tick>,
+
+    // Has the last tick of the replay been read?
+    finished: bool,
+
+    paused: bool,
+    speed_index: usize,
+}
+
+/// Turns a keyframe into a tick that replicates the full state, like the first tick that a
+/// freshly connected client receives
+fn keyframe_start_tick(keyframe: ReplayKeyframe) -> Tick {
+    let mut events = vec![
+        GameEvent::InitialPlayerList(keyframe.players),
+        GameEvent::MatchPhaseChange {
+            phase: keyframe.phase,
+            duration_s: keyframe.phase_duration_s,
+        },
+    ];
+    for (id, type_id, owner) in keyframe.entities {
+        events.push(GameEvent::CreateEntity(id, type_id, owner));
+    }
+
+    Tick {
+        tick_number: keyframe.tick.tick_number,
+        events: events,
+        state: keyframe.tick.state,
+    }
+}
+
+impl ReplayPlayer {
+    pub fn open(path: &str) -> Result<ReplayPlayer, String> {
+        info!("opening replay {}", path);
+
+        let reader = try!(ReplayReader::open(path));
+        let game_info = reader.header().game_info.clone();
+
+        info!("replay header: {:?}", reader.header());
+
+        let mut player = ReplayPlayer {
+            reader: reader,
+            game_info: game_info,
+            tick_deque: VecDeque::new(),
+            finished: false,
+            paused: false,
+            speed_index: NORMAL_SPEED_INDEX,
+        };
+        try!(player.seek(0));
+
+        Ok(player)
+    }
+
+    pub fn header(&self) -> &ReplayHeader {
+        self.reader.header()
+    }
+
+    /// Replays are recorded from the view of a spectator
+    pub fn my_id(&self) -> PlayerId {
+        NEUTRAL_PLAYER_ID
+    }
+
+    pub fn game_info(&self) -> &GameInfo {
+        &self.game_info
+    }
+
+    /// Restarts playback at the last keyframe not after the given tick. The game state needs
+    /// to be recreated afterwards.
+    pub fn seek(&mut self, tick_number: TickNumber) -> Result<(), String> {
+        try!(self.reader.seek(tick_number));
+        self.tick_deque.clear();
+        self.finished = false;
+
+        let keyframe = match try!(self.reader.next_record()) {
+            Some(ReplayRecord::Keyframe(keyframe)) => keyframe,
+            Some(ReplayRecord::Tick(_)) =>
+                return Err("Replay does not start with a keyframe".to_string()),
+            None =>
+                return Err("Replay contains no ticks".to_string()),
+        };
+
+        info!("starting replay at tick {}", keyframe.tick.tick_number);
+
+        self.game_info.map_name = keyframe.map_name.clone();
+        self.game_info.ticks_per_second = keyframe.ticks_per_second;
+        self.tick_deque.push_back(keyframe_start_tick(keyframe));
+
+        self.fill();
+        Ok(())
+    }
+
+    /// Tick numbers of the keyframes that can be seeked to
+    pub fn keyframes(&self) -> Vec<TickNumber> {
+        self.reader.index().iter().map(|entry| entry.tick_number).collect()
+    }
+
+    pub fn service(&mut self) {
+        self.fill();
+    }
+
+    pub fn num_ticks(&self) -> usize {
+        self.tick_deque.len()
+    }
+
+    pub fn get_next_tick(&self) -> &Tick {
+        self.tick_deque.front().unwrap()
+    }
+
+    pub fn pop_next_tick(&mut self) -> Tick {
+        let tick = self.tick_deque.pop_front().unwrap();
+        self.fill();
+        tick
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    pub fn speed(&self) -> f32 {
+        SPEEDS[self.speed_index]
+    }
+
+    pub fn faster(&mut self) {
+        self.speed_index = (self.speed_index + 1).min(SPEEDS.len() - 1);
+    }
+
+    pub fn slower(&mut self) {
+        self.speed_index = self.speed_index.saturating_sub(1);
+    }
+
+    /// Factor by which ticks should be advanced relative to real time
+    pub fn time_factor(&self) -> f32 {
+        if self.paused { 0.0 } else { self.speed() }
+    }
+
+    fn fill(&mut self) {
+        while !self.finished && self.tick_deque.len() < READ_AHEAD_TICKS {
+            match self.reader.next_record() {
+                // Keyframes are full ticks, so in the middle of playback they can be used
+                // like any other tick
+                Ok(Some(ReplayRecord::Keyframe(keyframe))) =>
+                    self.tick_deque.push_back(keyframe.tick),
+                Ok(Some(ReplayRecord::Tick(tick))) =>
+                    self.tick_deque.push_back(tick),
+                Ok(None) => {
+                    info!("reached end of replay");
+                    self.finished = true;
+                }
+                Err(error) => {
+                    warn!("error reading replay, stopping playback: {}", error);
+                    self.finished = true;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use std::io::{Read, Write};
+use std::fs::File;
+use std::path::Path;
+
+use rustc_serialize::json;
+
+pub const DEFAULT_SETTINGS_FILE: &'static str = "settings.json";
+
+/// Client settings that are remembered between runs
+#[derive(Debug, Clone, RustcEncodable, RustcDecodable)]
+pub struct Settings {
+    pub name: String,
+    pub port: u16,
+
+    pub window_width: u32,
+    pub window_height: u32,
+    pub fullscreen: bool,
+
+    pub blur: bool,
+
+    // In range [0,1]
+    pub volume: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            name: "player".to_string(),
+            port: 9988,
+            window_width: 640,
+            window_height: 480,
+            fullscreen: false,
+            blur: false,
+            volume: 1.0,
+        }
+    }
+}
+
+impl Settings {
+    /// Loads settings from a JSON file at the given path. If the file does not exist, the default
+    /// settings are returned.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Settings, String> {
+        if !path.as_ref().exists() {
+            info!("settings file {} does not exist, using defaults",
+                  path.as_ref().to_str().unwrap());
+            return Ok(Settings::default());
+        }
+
+        info!("loading settings from {}", path.as_ref().to_str().unwrap());
+
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(error) => return Err(format!("Failed to open settings file: {}", error))
+        };
+
+        let mut contents = String::new();
+        if let Err(error) = file.read_to_string(&mut contents) {
+            return Err(format!("Failed to read settings file: {}", error));
+        }
+
+        match json::decode(&contents) {
+            Ok(settings) => Ok(settings),
+            Err(error) => Err(format!("Invalid settings file: {}", error))
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let contents = json::as_pretty_json(self).to_string();
+
+        let mut file = match File::create(path) {
+            Ok(file) => file,
+            Err(error) => return Err(format!("Failed to create settings file: {}", error))
+        };
+
+        match file.write_all(contents.as_bytes()) {
+            Ok(()) => Ok(()),
+            Err(error) => Err(format!("Failed to write settings file: {}", error))
+        }
+    }
+}
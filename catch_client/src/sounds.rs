@@ -12,11 +12,14 @@ type Sound = Buffered<Decoder<Cursor<Vec<u8>>>>;
 
 pub struct Sounds {
     endpoint: Endpoint,
-    sounds: HashMap<String, Sound>
+    sounds: HashMap<String, Sound>,
+
+    // In range [0,1]
+    volume: f32,
 }
 
 impl Sounds {
-    pub fn load() -> Result<Sounds, String> {
+    pub fn load(volume: f32) -> Result<Sounds, String> {
         let endpoint = match rodio::get_default_endpoint() {
             Some(endpoint) => endpoint,
             None => return Err("no sound device available".to_string()),
@@ -25,6 +28,7 @@ impl Sounds {
         let mut s = Sounds {
             endpoint: endpoint,
             sounds: HashMap::new(),
+            volume: volume,
         };
 
         try!(s.load_sound("dash", "data/sounds/270553__littlerobotsoundfactory__warpdrive-00.wav"));
@@ -38,7 +42,7 @@ impl Sounds {
         return;
         let sound = &self.sounds[name];
         let sink = Sink::new(&self.endpoint);
-        sink.append(sound.clone().speed(2.0));
+        sink.append(sound.clone().speed(2.0).amplify(self.volume));
         sink.detach();
     }
 
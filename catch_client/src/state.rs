@@ -3,7 +3,8 @@ use std::collections::HashMap;
 use ecs;
 use hprof;
 
-use shared::{GameEvent, GameInfo, TickNumber, PlayerId, PlayerInfo, PlayerInput, Tick, Map};
+use shared::{GameEvent, GameInfo, TickNumber, PlayerId, TeamId, PlayerInfo, PlayerInput, Tick,
+             Map, MatchPhase};
 use systems::{Systems, NetEntitySystem};
 use components::Components;
 
@@ -15,6 +16,19 @@ pub struct GameState {
     pub tick_number: Option<TickNumber>,
 
     players: HashMap<PlayerId, PlayerInfo>,
+
+    // Current phase of the match, with the tick in which it started and its duration
+    pub match_phase: Option<(MatchPhase, TickNumber, Option<f32>)>,
+
+    // Map that will be played after the current match
+    pub next_map: Option<String>,
+
+    // Scores of the teams, if the current game mode has teams
+    pub team_scores: Vec<(TeamId, u32)>,
+
+    // Current catcher, with the tick in which they became catcher. Only sent by modes in which
+    // the catcher role is passed on.
+    pub catcher: Option<(PlayerId, TickNumber)>,
 }
 
 impl GameState {
@@ -30,6 +44,10 @@ impl GameState {
             world: world,
             tick_number: None,
             players: HashMap::new(),
+            match_phase: None,
+            next_map: None,
+            team_scores: Vec::new(),
+            catcher: None,
         }
     }
 
@@ -41,6 +59,28 @@ impl GameState {
         &self.players
     }
 
+    /// Time left until the current match phase ends, if it has a fixed duration
+    pub fn match_phase_time_left_s(&self) -> Option<f32> {
+        match (self.match_phase, self.tick_number) {
+            (Some((_, start_tick, Some(duration_s))), Some(tick_number)) => {
+                let passed_s = (tick_number - start_tick) as f32 /
+                               self.game_info.ticks_per_second as f32;
+                Some((duration_s - passed_s).max(0.0))
+            }
+            _ => None
+        }
+    }
+
+    /// For how long the current catcher has been the catcher
+    pub fn catcher_time_s(&self) -> Option<f32> {
+        match (self.catcher, self.tick_number) {
+            (Some((_, start_tick)), Some(tick_number)) =>
+                Some((tick_number - start_tick) as f32 /
+                     self.game_info.ticks_per_second as f32),
+            _ => None
+        }
+    }
+
     pub fn on_local_player_input(&mut self, _input: &PlayerInput) {
         // TODO: Client-side prediction
     }
@@ -48,6 +88,8 @@ impl GameState {
     pub fn run_tick(&mut self, tick: &Tick) {
         let _g = hprof::enter("run tick");
 
+        self.tick_number = Some(tick.tick_number);
+
         {
             let _g = hprof::enter("entity events");
 
@@ -118,6 +160,41 @@ impl GameState {
                     self.players.get_mut(&id).unwrap().stats = stats;
                 }
             }
+            GameEvent::PlayerChangeTeam(id, team) => {
+                info!("player {} changed to team {:?}", id, team);
+                match self.players.get_mut(&id) {
+                    Some(info) => info.team = team,
+                    None => warn!("team change for unknown player {}", id),
+                }
+            }
+            GameEvent::UpdateTeamScores(team_scores) => {
+                self.team_scores = team_scores;
+            }
+            GameEvent::ChangeTickRate(ticks_per_second) => {
+                info!("changing tick rate to {}", ticks_per_second);
+                self.game_info.ticks_per_second = ticks_per_second;
+            }
+            GameEvent::CatcherChange(player_id) => {
+                self.catcher = player_id.map(|id| (id, self.tick_number.unwrap()));
+            }
+            GameEvent::MatchPhaseChange { phase, duration_s } => {
+                info!("match phase: {:?}", phase);
+                self.match_phase = Some((phase, self.tick_number.unwrap(), duration_s));
+            }
+            GameEvent::NextMap(map_name) => {
+                info!("next map: {}", map_name);
+                self.next_map = Some(map_name);
+            }
+            GameEvent::ChangeMap(map_name) => {
+                info!("changing map to {}", map_name);
+                self.map = Map::load(&map_name).unwrap();
+                self.game_info.map_name = map_name;
+                self.next_map = None;
+
+                // The new map might have a different mode
+                self.team_scores.clear();
+                self.catcher = None;
+            }
             _ => ()
         }
     }
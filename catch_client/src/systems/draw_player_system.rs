@@ -5,6 +5,7 @@ use rand;
 use na::{Vec2, Vec4, Mat2, Mat4, Norm, Inv};
 
 use shared::util::CachedAspect;
+use shared::player::color_to_rgb;
 
 use components::{Components, Shape};
 use services::Services;
@@ -83,6 +84,10 @@ impl DrawPlayerSystem {
                     [1.0f32, 0.65f32 - 0.5 * t, 0.0, 1.0]
                 } else if data.player_state[entity].is_catcher {
                     [0.0, 1.0, 0.0, 1.0]
+                } else if data.player_state[entity].color != 0 {
+                    // Team color
+                    let (r, g, b) = color_to_rgb(data.player_state[entity].color);
+                    [r, g, b, 1.0]
                 } else {
                     [0.0f32, 0.0, 1.0, 1.0]
                 };
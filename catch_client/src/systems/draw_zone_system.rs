@@ -0,0 +1,52 @@
+use ecs::{Aspect, System, DataHelper, Process};
+use na::{Vec2, Vec4};
+
+use shared::util::CachedAspect;
+
+use components::Components;
+use services::Services;
+use draw::{FLAG_NONE, DrawList};
+
+pub struct DrawZoneSystem {
+    aspect: CachedAspect<Components>,
+}
+
+impl DrawZoneSystem {
+    pub fn new(aspect: Aspect<Components>) -> DrawZoneSystem {
+        DrawZoneSystem {
+            aspect: CachedAspect::new(aspect),
+        }
+    }
+
+    pub fn draw(&mut self, data: &mut DataHelper<Components, Services>, draw_list: &mut DrawList) {
+        for entity in self.aspect.iter() {
+            let p = data.position[entity].p;
+            let zone = data.zone_state[entity].clone();
+
+            let color = if zone.contested {
+                Vec4::new(0.5, 0.1, 0.1, 1.0)
+            } else if zone.owner.is_some() {
+                Vec4::new(0.5, 0.45, 0.1, 1.0)
+            } else {
+                Vec4::new(0.15, 0.15, 0.15, 1.0)
+            };
+
+            let center = p + zone.size / 2.0;
+            draw_list.push_rect(FLAG_NONE, color, zone.size.x, zone.size.y, center, 0.0, 0.0);
+
+            // Progress of the owner towards the next point along the bottom edge
+            if zone.owner.is_some() {
+                let a = Vec2::new(p.x, p.y + zone.size.y);
+                let b = Vec2::new(p.x + zone.size.x * zone.progress, p.y + zone.size.y);
+                draw_list.push_line(FLAG_NONE, Vec4::new(1.0, 0.9, 0.2, 1.0), 3.0, a, b, 0.0);
+            }
+        }
+    }
+}
+
+impl_cached_system!(Components, Services, DrawZoneSystem, aspect);
+
+impl Process for DrawZoneSystem {
+    fn process(&mut self, _: &mut DataHelper<Components, Services>) {
+    }
+}
@@ -5,6 +5,7 @@ pub mod draw_bouncy_enemy_system;
 pub mod draw_item_system;
 pub mod draw_projectile_system;
 pub mod draw_wall_system;
+pub mod draw_zone_system;
 
 use ecs::system::LazySystem;
 
@@ -17,6 +18,7 @@ pub use self::draw_bouncy_enemy_system::DrawBouncyEnemySystem;
 pub use self::draw_item_system::DrawItemSystem;
 pub use self::draw_projectile_system::DrawProjectileSystem;
 pub use self::draw_wall_system::DrawWallSystem;
+pub use self::draw_zone_system::DrawZoneSystem;
 
 systems! {
     struct Systems<Components, Services> {
@@ -34,5 +36,7 @@ systems! {
             aspect!(<Components> all: [draw_projectile])),
         draw_wall_system: DrawWallSystem = DrawWallSystem::new(
             aspect!(<Components> all: [draw_wall])),
+        draw_zone_system: DrawZoneSystem = DrawZoneSystem::new(
+            aspect!(<Components> all: [draw_zone])),
     }
 }
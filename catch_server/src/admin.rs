@@ -0,0 +1,86 @@
+use std::str::FromStr;
+
+use na::Vec2;
+
+use shared::PlayerId;
+
+pub const HELP: &'static str = "\
+commands:
+  players                 list players with id, name, ping and score
+  leaderboard             list the best players over all matches
+  kick <id>               disconnect a player
+  ban <id>                disconnect a player and refuse their address from now on
+  map <file>              change to the given map
+  restart                 restart the match on the current map
+  tickrate <n>            set the number of ticks per second
+  items                   toggle spawning of items
+  spawn <type> <x> <y>    create an entity at the given position
+  snapshot [file]         save the game state, by default to the file given by --snapshot
+  quit                    stop the server, finishing the replay if recording
+  help                    show this text";
+
+/// Commands that can be given to the server by admins, either via stdin or
+/// `ClientMessage::AdminCommand`
+#[derive(Debug, Clone)]
+pub enum AdminCommand {
+    ListPlayers,
+    Leaderboard,
+    Kick(PlayerId),
+    Ban(PlayerId),
+    ChangeMap(String),
+    RestartRound,
+    SetTickRate(u32),
+    ToggleItems,
+    Spawn {
+        type_name: String,
+        position: Vec2<f32>,
+    },
+    Snapshot(Option<String>),
+    Quit,
+    Help,
+}
+
+fn parse_arg<T: FromStr>(args: &[&str], i: usize, name: &str) -> Result<T, String> {
+    match args.get(i) {
+        Some(arg) => arg.parse().map_err(|_| format!("Invalid {}: {}", name, arg)),
+        None => Err(format!("Missing argument: {}", name)),
+    }
+}
+
+impl AdminCommand {
+    pub fn parse(line: &str) -> Result<AdminCommand, String> {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        if words.is_empty() {
+            return Err("Empty command".to_string());
+        }
+
+        let args = &words[1..];
+        let command = match words[0] {
+            "players" => AdminCommand::ListPlayers,
+            "leaderboard" => AdminCommand::Leaderboard,
+            "kick" => AdminCommand::Kick(try!(parse_arg(args, 0, "player id"))),
+            "ban" => AdminCommand::Ban(try!(parse_arg(args, 0, "player id"))),
+            "map" => AdminCommand::ChangeMap(try!(parse_arg(args, 0, "map file"))),
+            "restart" => AdminCommand::RestartRound,
+            "tickrate" => {
+                let ticks_per_second: u32 = try!(parse_arg(args, 0, "tick rate"));
+                if ticks_per_second == 0 {
+                    return Err("Tick rate must be positive".to_string());
+                }
+                AdminCommand::SetTickRate(ticks_per_second)
+            }
+            "items" => AdminCommand::ToggleItems,
+            "spawn" => AdminCommand::Spawn {
+                type_name: try!(parse_arg(args, 0, "entity type")),
+                position: Vec2::new(try!(parse_arg(args, 1, "x")),
+                                    try!(parse_arg(args, 2, "y"))),
+            },
+            "snapshot" => AdminCommand::Snapshot(args.get(0).map(|path| path.to_string())),
+            "quit" => AdminCommand::Quit,
+            "help" => AdminCommand::Help,
+            _ => return Err(format!("Unknown command: {} (try help)", words[0])),
+        };
+
+        Ok(command)
+    }
+}
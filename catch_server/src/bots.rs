@@ -0,0 +1,368 @@
+use std::f32;
+
+use rand::Rng;
+use na::{Vec2, Norm, Dot};
+
+use shared::{PlayerId, PlayerInput, TeamId, ItemSlot};
+use shared::player::PlayerInputKey;
+use shared::navigation::NavGraph;
+
+// Non-catchers run away once the catcher is closer than this
+const FLEE_DISTANCE: f32 = 250.0;
+
+// Bots go for items that are closer than this
+const ITEM_DISTANCE: f32 = 400.0;
+
+// Bots dash when a threat or a target is closer than this
+const DASH_DISTANCE: f32 = 50.0;
+
+// Bots use items on targets in front of them that are closer than this
+const SHOOT_DISTANCE: f32 = 300.0;
+
+// Bots turn until facing their goal up to this angle, and move forward when facing it up to
+// the second angle
+const TURN_TOLERANCE: f32 = 0.1;
+const FORWARD_TOLERANCE: f32 = f32::consts::PI / 2.0;
+
+// Projectiles that will pass closer than this within the given time are dodged
+const DODGE_RADIUS: f32 = 20.0;
+const DODGE_TIME_S: f32 = 0.5;
+
+// Waypoints of a path count as reached when closer than this
+const WAYPOINT_DISTANCE: f32 = 10.0;
+
+// Time after which wandering bots choose a new direction
+const WANDER_TIME_S: f32 = 2.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub fn from_name(name: &str) -> Option<Difficulty> {
+        match name {
+            "easy" => Some(Difficulty::Easy),
+            "normal" => Some(Difficulty::Normal),
+            "hard" => Some(Difficulty::Hard),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Difficulty::Easy => "easy",
+            Difficulty::Normal => "normal",
+            Difficulty::Hard => "hard",
+        }
+    }
+
+    /// Time that passes before a bot reacts to changes around it
+    pub fn reaction_time_s(&self) -> f32 {
+        match *self {
+            Difficulty::Easy => 0.6,
+            Difficulty::Normal => 0.3,
+            Difficulty::Hard => 0.1,
+        }
+    }
+
+    /// Maximal error in radians when aiming at something
+    pub fn aim_error(&self) -> f32 {
+        match *self {
+            Difficulty::Easy => 0.5,
+            Difficulty::Normal => 0.2,
+            Difficulty::Hard => 0.05,
+        }
+    }
+}
+
+/// A player as seen by bots
+#[derive(Clone, Debug)]
+pub struct SeenPlayer {
+    pub id: PlayerId,
+    pub team: Option<TeamId>,
+    pub position: Vec2<f32>,
+    pub angle: f32,
+    pub is_catcher: bool,
+    pub vulnerable: bool,
+    pub dash_ready: bool,
+    pub has_hidden_item: bool,
+    pub free_slot: Option<ItemSlot>,
+    pub usable_slot: Option<ItemSlot>,
+}
+
+#[derive(Clone, Debug)]
+pub struct SeenProjectile {
+    pub owner: PlayerId,
+    pub position: Vec2<f32>,
+    pub velocity: Vec2<f32>,
+}
+
+/// Everything bots know about the world in one tick, collected by `BotSystem`
+#[derive(Clone, Debug, Default)]
+pub struct Observation {
+    pub players: Vec<SeenPlayer>,
+    pub items: Vec<Vec2<f32>>,
+    pub projectiles: Vec<SeenProjectile>,
+}
+
+impl Observation {
+    fn player(&self, id: PlayerId) -> Option<&SeenPlayer> {
+        self.players.iter().find(|player| player.id == id)
+    }
+
+    /// Players that `me` can interact with, i.e. everyone else not in the same team
+    fn enemies<'a>(&'a self, me: &'a SeenPlayer) -> Vec<&'a SeenPlayer> {
+        self.players.iter()
+            .filter(|player| player.id != me.id &&
+                             (me.team.is_none() || player.team != me.team))
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Goal {
+    Chase(PlayerId),
+    Flee(PlayerId),
+    PickUp(Vec2<f32>),
+    Wander(f32),
+}
+
+/// Behaviour of a server-side player without a client. Bots see the world only every
+/// `reaction_time_s` and don't aim perfectly, depending on their difficulty.
+pub struct Bot {
+    difficulty: Difficulty,
+
+    goal: Goal,
+
+    // Time until the bot reconsiders its goal
+    reaction_timer_s: f32,
+
+    // Error added to the direction of the goal, chosen anew with every decision
+    aim_offset: f32,
+
+    // Wants to dash as soon as it can
+    dash: bool,
+
+    // Waypoints around walls towards the goal, if it can't be seen directly
+    path: Vec<Vec2<f32>>,
+
+    wander_timer_s: f32,
+}
+
+fn closest<'a>(position: Vec2<f32>, players: &[&'a SeenPlayer]) -> Option<&'a SeenPlayer> {
+    let mut closest: Option<(&SeenPlayer, f32)> = None;
+    for &player in players {
+        let d = (player.position - position).norm();
+        if closest.is_none() || closest.unwrap().1 > d {
+            closest = Some((player, d));
+        }
+    }
+    closest.map(|(player, _)| player)
+}
+
+/// Normalizes an angle to be in [-pi, pi]
+fn normalize_angle(angle: f32) -> f32 {
+    let mut angle = angle % (2.0 * f32::consts::PI);
+    if angle > f32::consts::PI {
+        angle -= 2.0 * f32::consts::PI;
+    } else if angle < -f32::consts::PI {
+        angle += 2.0 * f32::consts::PI;
+    }
+    angle
+}
+
+impl Bot {
+    pub fn new(difficulty: Difficulty) -> Bot {
+        Bot {
+            difficulty: difficulty,
+            goal: Goal::Wander(0.0),
+            reaction_timer_s: 0.0,
+            aim_offset: 0.0,
+            dash: false,
+            path: Vec::new(),
+            wander_timer_s: 0.0,
+        }
+    }
+
+    pub fn difficulty(&self) -> Difficulty {
+        self.difficulty
+    }
+
+    /// Produces the input of the bot for the next `dur_s` seconds
+    pub fn think<R: Rng>(&mut self, me: PlayerId, observation: &Observation, graph: &NavGraph,
+                         rng: &mut R, dur_s: f32) -> PlayerInput {
+        let mut input = PlayerInput::new();
+
+        let me = match observation.player(me) {
+            Some(me) => me.clone(),
+            None => return input, // dead
+        };
+
+        self.reaction_timer_s -= dur_s;
+        self.wander_timer_s -= dur_s;
+        if self.reaction_timer_s <= 0.0 {
+            self.reaction_timer_s = self.difficulty.reaction_time_s();
+            self.decide(&me, observation, rng);
+
+            self.path = match self.target(observation) {
+                Some(target) => graph.find_path(me.position, target).unwrap_or(Vec::new()),
+                None => Vec::new(),
+            };
+        }
+
+        // Steer towards the goal, using the current positions of players. If the goal is behind
+        // walls, follow the path instead.
+        while !self.path.is_empty() && (self.path[0] - me.position).norm() < WAYPOINT_DISTANCE {
+            self.path.remove(0);
+        }
+
+        let direction = match self.target(observation) {
+            Some(target) if !self.path.is_empty() && !graph.line_of_sight(me.position, target) =>
+                Some(self.path[0] - me.position),
+            Some(target) => Some(target - me.position),
+            None => match self.goal {
+                Goal::Flee(id) => observation.player(id).map(|p| me.position - p.position),
+                Goal::Wander(angle) => Some(Vec2::new(angle.cos(), angle.sin())),
+                _ => None
+            },
+        };
+
+        if let Some(direction) = direction {
+            let goal_angle = direction.y.atan2(direction.x) + self.aim_offset;
+            let delta = normalize_angle(goal_angle - me.angle);
+
+            if delta > TURN_TOLERANCE {
+                input.set(PlayerInputKey::Left);
+            } else if delta < -TURN_TOLERANCE {
+                input.set(PlayerInputKey::Right);
+            }
+            if delta.abs() < FORWARD_TOLERANCE {
+                input.set(PlayerInputKey::Forward);
+            }
+        }
+
+        if self.dash && me.dash_ready {
+            input.set(PlayerInputKey::Dash);
+            self.dash = false;
+        }
+
+        // Equip new items right away; this needs the key of the slot pressed as well
+        if me.has_hidden_item {
+            if let Some(slot) = me.free_slot {
+                input.set(PlayerInputKey::Equip);
+                input.set(Bot::slot_key(slot));
+                return input;
+            }
+        }
+
+        // Use items on enemies in front of us
+        if let Some(slot) = me.usable_slot {
+            let facing = Vec2::new(me.angle.cos(), me.angle.sin());
+            let aim_error = self.difficulty.aim_error();
+            let in_sight = observation.enemies(&me).iter().any(|enemy| {
+                let delta = enemy.position - me.position;
+                let distance = delta.norm();
+                distance > 0.0 && distance < SHOOT_DISTANCE &&
+                (delta / distance).dot(&facing) > (aim_error + TURN_TOLERANCE).cos() &&
+                graph.line_of_sight(me.position, enemy.position)
+            });
+
+            if in_sight {
+                input.set(Bot::slot_key(slot));
+            }
+        }
+
+        input
+    }
+
+    /// Position that the bot is trying to reach, if any
+    fn target(&self, observation: &Observation) -> Option<Vec2<f32>> {
+        match self.goal {
+            Goal::Chase(id) => observation.player(id).map(|p| p.position),
+            Goal::PickUp(position) => Some(position),
+            _ => None
+        }
+    }
+
+    fn decide<R: Rng>(&mut self, me: &SeenPlayer, observation: &Observation, rng: &mut R) {
+        self.aim_offset = (rng.gen::<f32>() * 2.0 - 1.0) * self.difficulty.aim_error();
+        self.dash = false;
+
+        let enemies = observation.enemies(me);
+        let catcher = enemies.iter().find(|player| player.is_catcher).cloned();
+
+        let goal = if me.is_catcher {
+            // Chase the closest player that can be caught
+            let catchable = enemies.iter().filter(|player| player.vulnerable)
+                                   .cloned().collect::<Vec<_>>();
+            closest(me.position, &catchable).map(|target| {
+                self.dash = (target.position - me.position).norm() < DASH_DISTANCE;
+                Goal::Chase(target.id)
+            })
+        } else if let Some(catcher) = catcher.and_then(|catcher| {
+            if (catcher.position - me.position).norm() < FLEE_DISTANCE { Some(catcher) }
+            else { None }
+        }) {
+            self.dash = (catcher.position - me.position).norm() < DASH_DISTANCE;
+            Some(Goal::Flee(catcher.id))
+        } else if catcher.is_none() && me.usable_slot.is_some() {
+            // Without a catcher (e.g. in deathmatch), go after players when armed
+            closest(me.position, &enemies).map(|target| Goal::Chase(target.id))
+        } else {
+            None
+        };
+
+        let goal = goal.or_else(|| {
+            let mut closest_item: Option<(Vec2<f32>, f32)> = None;
+            for &item in &observation.items {
+                let d = (item - me.position).norm();
+                if d < ITEM_DISTANCE && (closest_item.is_none() || closest_item.unwrap().1 > d) {
+                    closest_item = Some((item, d));
+                }
+            }
+            closest_item.map(|(item, _)| Goal::PickUp(item))
+        });
+
+        self.goal = match goal {
+            Some(goal) => goal,
+            None => match self.goal {
+                Goal::Wander(angle) if self.wander_timer_s > 0.0 => Goal::Wander(angle),
+                _ => {
+                    self.wander_timer_s = WANDER_TIME_S;
+                    Goal::Wander(rng.gen::<f32>() * 2.0 * f32::consts::PI)
+                }
+            }
+        };
+
+        // Dash out of the way of projectiles that are about to hit us
+        for projectile in &observation.projectiles {
+            if projectile.owner == me.id {
+                continue;
+            }
+
+            let delta = me.position - projectile.position;
+            let speed_sq = projectile.velocity.dot(&projectile.velocity);
+            if speed_sq == 0.0 {
+                continue;
+            }
+
+            // Time of closest approach
+            let t = delta.dot(&projectile.velocity) / speed_sq;
+            let nearest = projectile.position + projectile.velocity * t;
+            if t > 0.0 && t < DODGE_TIME_S && (nearest - me.position).norm() < DODGE_RADIUS {
+                self.dash = true;
+            }
+        }
+    }
+
+    fn slot_key(slot: ItemSlot) -> PlayerInputKey {
+        match slot {
+            0 => PlayerInputKey::Item1,
+            1 => PlayerInputKey::Item2,
+            _ => PlayerInputKey::Item3,
+        }
+    }
+}
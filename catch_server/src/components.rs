@@ -6,9 +6,10 @@ use shared::player::Item;
 use shared::net_components::ComponentType;
 use shared::components::{HasPosition, HasOrientation, HasLinearVelocity, HasShape, HasPlayerState,
                          HasFullPlayerState, HasWallPosition, HasAngularVelocity, HasWall,
-                         HasProjectile};
+                         HasProjectile, HasZoneState};
 pub use shared::components::{NetEntity, Position, Orientation, LinearVelocity, Shape, PlayerState,
-                             Projectile, FullPlayerState, AngularVelocity, Wall, WallPosition};
+                             Projectile, FullPlayerState, AngularVelocity, Wall, WallPosition,
+                             ZoneState, WallType};
 
 /// Server-side information about net entities
 #[derive(Default)]
@@ -39,6 +40,16 @@ pub struct BouncyEnemy {
     pub attract: bool,    
 }
 
+/// Walls that break after taking enough damage
+pub struct Destructible {
+    pub hit_points: f32,
+}
+
+/// Entities with this component are removed after some time
+pub struct Lifetime {
+    pub time_left_s: f32,
+}
+
 /// Tag component for InteractionSystem
 pub struct Interact;
 
@@ -50,6 +61,9 @@ pub struct Rotate;
 pub struct ItemSpawn {
     pub spawned_entity: Option<ecs::Entity>,
     pub cooldown_s: Option<f32>,
+
+    // Spawn table of the item definitions to choose items from
+    pub table: Option<String>,
 }
 
 components! {
@@ -66,6 +80,7 @@ components! {
         #[hot] full_player_state: FullPlayerState,
         #[hot] wall_position: WallPosition,
         #[hot] wall: Wall,
+        #[cold] zone_state: ZoneState,
 
         #[cold] angular_velocity: AngularVelocity,
 
@@ -75,6 +90,8 @@ components! {
         #[cold] item_spawn: ItemSpawn,
         #[cold] rotate: Rotate,
         #[cold] projectile: Projectile,
+        #[cold] lifetime: Lifetime,
+        #[cold] destructible: Destructible,
     }
 }
 
@@ -167,3 +184,12 @@ impl HasProjectile for Components {
         &mut self.projectile
     }
 }
+
+impl HasZoneState for Components {
+    fn zone_state(&self) -> &ComponentList<Components, ZoneState> {
+        &self.zone_state
+    }
+    fn zone_state_mut(&mut self) -> &mut ComponentList<Components, ZoneState> {
+        &mut self.zone_state
+    }
+}
@@ -5,14 +5,19 @@ use ecs;
 use ecs::{BuildData, EntityData, DataHelper, EntityBuilder};
 
 use shared;
-use shared::{PlayerId, DeathReason, GameEvent};
+use shared::{EntityId, EntityTypeId, PlayerId, DeathReason, GameEvent};
 use shared::net_components::NetComponents;
 use shared::services::HasEvents;
 
 use components::{Components, NetEntity, ServerNetEntity, LinearVelocity, BouncyEnemy, ItemSpawn,
-                 AngularVelocity, Rotate, PlayerController};
+                 AngularVelocity, Rotate, PlayerController, Destructible, Lifetime, WallType};
 use services::Services;
 
+const WOOD_HIT_POINTS: f32 = 10.0;
+
+// Bullets can ricochet off iron walls, so they need to disappear at some point
+const BULLET_LIFETIME_S: f32 = 5.0;
+
 /// Create a new networked entity, replicating it to the clients
 pub fn build_net(type_name: &str,
                  owner: PlayerId,
@@ -29,15 +34,28 @@ pub fn build_net_custom<B: EntityBuilder<Components>>
                         builder: B)
                         -> ecs::Entity {
     let entity_type_id = data.services.entity_type_id(type_name);
-    let entity_type = data.services.entity_types[entity_type_id as usize].1.clone();
     let entity_id = data.services.next_entity_id();
 
-    debug!("building {} net entity {} for {}", type_name, entity_id, owner);
-
     // Tell the clients about the new entity
     data.services.add_event(
         &GameEvent::CreateEntity(entity_id, entity_type_id, owner));
 
+    build_net_with_id(entity_id, entity_type_id, owner, data, builder)
+}
+
+/// Create a net entity with a given id without telling the clients about it. This is used when
+/// restoring a snapshot, before any clients are connected.
+pub fn build_net_with_id<B: EntityBuilder<Components>>
+                        (entity_id: EntityId,
+                         entity_type_id: EntityTypeId,
+                         owner: PlayerId,
+                         data: &mut DataHelper<Components, Services>,
+                         builder: B)
+                         -> ecs::Entity {
+    let (type_name, entity_type) = data.services.entity_types[entity_type_id as usize].clone();
+
+    debug!("building {} net entity {} for {}", type_name, entity_id, owner);
+
     let entity = data.create_entity(|entity: BuildData<Components>, data: &mut Components| {
         data.net_entity.add(&entity, NetEntity {
             id: entity_id,
@@ -52,10 +70,10 @@ pub fn build_net_custom<B: EntityBuilder<Components>>
         }
 
         // Add shared components that don't need to be synchronized
-        shared::entities::build_shared(type_name, entity, data);
+        shared::entities::build_shared(&type_name, entity, data);
 
         // Add server-side only components
-        build_server(type_name, entity, data);
+        build_server(&type_name, entity, data);
 
         // Possibly add some custom components
         builder.build(entity, data);
@@ -86,10 +104,15 @@ pub fn build_server(type_name: &str,
               type_name == "frag" ||
               type_name == "shrapnel" {
         data.linear_velocity.add(&entity, LinearVelocity::default());
+        if type_name == "bullet" {
+            data.lifetime.add(&entity, Lifetime { time_left_s: BULLET_LIFETIME_S });
+        }
         if type_name == "frag" || type_name == "shrapnel" {
             data.angular_velocity.add(&entity, AngularVelocity { v: 1.0 * f32::consts::PI });
             data.rotate.add(&entity, Rotate);
         }
+    } else if type_name == "wall_wood" {
+        data.destructible.add(&entity, Destructible { hit_points: WOOD_HIT_POINTS });
     }
 }
 
@@ -119,6 +142,26 @@ pub fn remove_net(entity: ecs::Entity, data: &mut DataHelper<Components, Service
     }
 }
 
+/// Damages a wall if it is destructible, breaking it when it has no hit points left
+pub fn damage_wall(wall: EntityData<Components>,
+                   damage: f32,
+                   data: &mut DataHelper<Components, Services>) {
+    if data.wall[wall].wall_type != WallType::Wood || data.server_net_entity[wall].removed {
+        return;
+    }
+
+    data.destructible[wall].hit_points -= damage;
+
+    if data.destructible[wall].hit_points <= 0.0 {
+        let event = GameEvent::WallBroken {
+            pos_a: data.wall_position[wall].pos_a,
+            pos_b: data.wall_position[wall].pos_b,
+        };
+        data.services.add_event(&event);
+        remove_net(**wall, data);
+    }
+}
+
 pub fn damage_player(player_id: PlayerId,
                      responsible_player_id: PlayerId,
                      death_reason: DeathReason,
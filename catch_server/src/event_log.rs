@@ -0,0 +1,142 @@
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+
+use time;
+use na::Vec2;
+use rustc_serialize::json::{Json, ToJson};
+
+use shared::{TickNumber, GameEvent};
+
+/// Writes the gameplay events of every tick to a file, one JSON object per line.
+/// Events that only replicate state to clients, like entity creation and the stats sent every
+/// tick, are left out.
+pub struct EventLog {
+    path: String,
+    writer: BufWriter<File>,
+}
+
+fn vec_json(v: Vec2<f32>) -> Json {
+    Json::Array(vec![v.x.to_json(), v.y.to_json()])
+}
+
+/// Name and fields of an event, or None if the event is not logged
+fn event_fields(event: &GameEvent) -> Option<(&'static str, Vec<(&'static str, Json)>)> {
+    let fields = match *event {
+        GameEvent::PlayerJoin(player_id, ref info) =>
+            ("player_join", vec![("player_id", player_id.to_json()),
+                                 ("name", info.name.to_json())]),
+        GameEvent::PlayerLeave(player_id) =>
+            ("player_leave", vec![("player_id", player_id.to_json())]),
+        GameEvent::PlayerChangeTeam(player_id, team) =>
+            ("player_change_team", vec![("player_id", player_id.to_json()),
+                                        ("team", team.to_json())]),
+        GameEvent::MatchPhaseChange { phase, duration_s } =>
+            ("match_phase_change", vec![("phase", format!("{:?}", phase).to_json()),
+                                        ("duration_s", duration_s.to_json())]),
+        GameEvent::NextMap(ref map_name) =>
+            ("next_map", vec![("map", map_name.to_json())]),
+        GameEvent::ChangeMap(ref map_name) =>
+            ("change_map", vec![("map", map_name.to_json())]),
+        GameEvent::ChangeTickRate(ticks_per_second) =>
+            ("change_tick_rate", vec![("ticks_per_second", ticks_per_second.to_json())]),
+        GameEvent::PlayerDied { player_id, position, responsible_player_id, reason } =>
+            ("player_died", vec![("player_id", player_id.to_json()),
+                                 ("position", vec_json(position)),
+                                 ("responsible_player_id", responsible_player_id.to_json()),
+                                 ("reason", format!("{:?}", reason).to_json())]),
+        GameEvent::PlayerTagged { player_id, position, responsible_player_id } =>
+            ("player_tagged", vec![("player_id", player_id.to_json()),
+                                   ("position", vec_json(position)),
+                                   ("responsible_player_id", responsible_player_id.to_json())]),
+        GameEvent::CatcherChange(player_id) =>
+            ("catcher_change", vec![("player_id", player_id.to_json())]),
+        GameEvent::PlayerDash { player_id, position, orientation } =>
+            ("player_dash", vec![("player_id", player_id.to_json()),
+                                 ("position", vec_json(position)),
+                                 ("orientation", orientation.to_json())]),
+        GameEvent::PlayerFlip { player_id, position, orientation, speed, orientation_wall } =>
+            ("player_flip", vec![("player_id", player_id.to_json()),
+                                 ("position", vec_json(position)),
+                                 ("orientation", orientation.to_json()),
+                                 ("speed", speed.to_json()),
+                                 ("orientation_wall", orientation_wall.to_json())]),
+        GameEvent::PlayerTakeItem { player_id, position } =>
+            ("player_take_item", vec![("player_id", player_id.to_json()),
+                                      ("position", vec_json(position))]),
+        GameEvent::PlayerEquipItem { player_id, position, ref item } =>
+            ("player_equip_item", vec![("player_id", player_id.to_json()),
+                                       ("position", vec_json(position)),
+                                       ("item", item.name().to_json())]),
+        GameEvent::EnemyDied { position } =>
+            ("enemy_died", vec![("position", vec_json(position))]),
+        GameEvent::ProjectileImpact { position, strength } =>
+            ("projectile_impact", vec![("position", vec_json(position)),
+                                       ("strength", strength.to_json())]),
+        GameEvent::WallBroken { pos_a, pos_b } =>
+            ("wall_broken", vec![("pos_a", vec_json(pos_a)),
+                                 ("pos_b", vec_json(pos_b))]),
+        GameEvent::InitialPlayerList(_) |
+        GameEvent::UpdatePlayerStats(_) |
+        GameEvent::UpdateTeamScores(_) |
+        GameEvent::CreateEntity(..) |
+        GameEvent::RemoveEntity(_) =>
+            return None,
+    };
+
+    Some(fields)
+}
+
+impl EventLog {
+    /// Appends to the file if it exists already
+    pub fn open(path: &str) -> Result<EventLog, String> {
+        info!("logging events to {}", path);
+
+        let file = try!(OpenOptions::new().write(true).append(true).create(true).open(path)
+                            .map_err(|e| e.to_string()));
+
+        Ok(EventLog {
+            path: path.to_string(),
+            writer: BufWriter::new(file),
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Writes the events of a tick, with the tick number, the wall clock time in seconds since
+    /// the epoch and the game time
+    pub fn write(&mut self, tick_number: TickNumber, game_time_s: f32, events: &[GameEvent])
+                 -> Result<(), String> {
+        let now = time::get_time();
+        let time_s = now.sec as f64 + now.nsec as f64 / 1000000000.0;
+
+        let mut written = false;
+        for event in events {
+            let (name, fields) = match event_fields(event) {
+                Some(fields) => fields,
+                None => continue,
+            };
+
+            let mut entry = BTreeMap::new();
+            entry.insert("tick".to_string(), tick_number.to_json());
+            entry.insert("time".to_string(), time_s.to_json());
+            entry.insert("game_time_s".to_string(), game_time_s.to_json());
+            entry.insert("event".to_string(), name.to_json());
+            for (key, value) in fields {
+                entry.insert(key.to_string(), value);
+            }
+
+            try!(writeln!(self.writer, "{}", Json::Object(entry)).map_err(|e| e.to_string()));
+            written = true;
+        }
+
+        // Lines should be complete in case the server crashes
+        if written {
+            try!(self.writer.flush().map_err(|e| e.to_string()));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use rand::Rng;
+use rustc_serialize::json;
+
+use shared::Item;
+
+/// Names of the items in definition files
+pub const ITEM_NAMES: &'static [&'static str] = &["weapon", "frag_weapon", "speed_boost",
+                                                   "block_placer", "ball_spawner", "shield"];
+
+fn is_item_name(name: &str) -> bool {
+    ITEM_NAMES.iter().any(|&n| n == name)
+}
+
+/// Stats of one kind of item. Which of the optional fields are needed depends on the item.
+#[derive(Clone, Debug, RustcDecodable)]
+pub struct ItemDef {
+    // Number of times the item can be used
+    pub charges: Option<usize>,
+
+    // Time between two uses
+    pub cooldown_s: Option<f32>,
+
+    // Time that the effect of the item lasts
+    pub duration_s: Option<f32>,
+
+    pub projectile_speed: Option<f32>,
+    pub num_shrapnels: Option<usize>,
+
+    // Time until entities created by the item disappear
+    pub lifetime_s: Option<f32>,
+
+    // Relative chance of being chosen by item spawns without a spawn table
+    pub spawn_weight: u32,
+}
+
+/// Item stats and spawn tables, loaded from a JSON file by the server
+#[derive(Clone, Debug, RustcDecodable)]
+pub struct ItemDefs {
+    items: HashMap<String, ItemDef>,
+
+    // Named tables of spawn weights per item name, selected by the "table" property of item
+    // spawns in maps
+    spawn_tables: HashMap<String, HashMap<String, u32>>,
+}
+
+impl ItemDefs {
+    /// Loads and checks item definitions. Every item needs to be defined, so that the game
+    /// can't fail later on.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ItemDefs, String> {
+        info!("loading item definitions from {}", path.as_ref().to_str().unwrap());
+
+        let mut text = String::new();
+        match File::open(path) {
+            Ok(mut file) => try!(file.read_to_string(&mut text)
+                                     .map_err(|_| "Failed to read file".to_string())),
+            Err(_) => return Err("Failed to open file".to_string())
+        };
+
+        let defs: ItemDefs = try!(json::decode(&text).map_err(|e| e.to_string()));
+
+        if let Some(name) = defs.items.keys().find(|name| !is_item_name(name)) {
+            return Err(format!("Unknown item {}", name));
+        }
+
+        for &name in ITEM_NAMES {
+            let def = match defs.items.get(name) {
+                Some(def) => def,
+                None => return Err(format!("Missing definition of item {}", name)),
+            };
+
+            let required = match name {
+                "weapon" => vec![("charges", def.charges.is_some()),
+                                 ("projectile_speed", def.projectile_speed.is_some())],
+                "frag_weapon" => vec![("charges", def.charges.is_some()),
+                                      ("projectile_speed", def.projectile_speed.is_some()),
+                                      ("num_shrapnels", def.num_shrapnels.is_some())],
+                "speed_boost" => vec![("duration_s", def.duration_s.is_some())],
+                "block_placer" => vec![("charges", def.charges.is_some()),
+                                       ("lifetime_s", def.lifetime_s.is_some())],
+                "ball_spawner" => vec![("charges", def.charges.is_some())],
+                _ => vec![],
+            };
+            for (field, present) in required {
+                if !present {
+                    return Err(format!("Item {} needs a value for {}", name, field));
+                }
+            }
+        }
+
+        if defs.items.values().all(|def| def.spawn_weight == 0) {
+            return Err("At least one item needs a positive spawn weight".to_string());
+        }
+
+        for (table_name, table) in defs.spawn_tables.iter() {
+            if let Some(name) = table.keys().find(|name| !is_item_name(name)) {
+                return Err(format!("Unknown item {} in spawn table {}", name, table_name));
+            }
+            if table.values().all(|&weight| weight == 0) {
+                return Err(format!("Spawn table {} needs a positive weight", table_name));
+            }
+        }
+
+        Ok(defs)
+    }
+
+    pub fn get(&self, name: &str) -> &ItemDef {
+        &self.items[name]
+    }
+
+    pub fn def(&self, item: &Item) -> &ItemDef {
+        self.get(item.name())
+    }
+
+    pub fn has_spawn_table(&self, table: &str) -> bool {
+        self.spawn_tables.contains_key(table)
+    }
+
+    /// Creates a fresh item with its full charges
+    pub fn create(&self, name: &str) -> Item {
+        let def = &self.items[name];
+        match name {
+            "weapon" => Item::Weapon { charges: def.charges.unwrap() },
+            "frag_weapon" => Item::FragWeapon { charges: def.charges.unwrap() },
+            "speed_boost" => Item::SpeedBoost { duration_s: def.duration_s.unwrap() },
+            "block_placer" => Item::BlockPlacer { charges: def.charges.unwrap() },
+            "ball_spawner" => Item::BallSpawner { charges: def.charges.unwrap() },
+            "shield" => Item::Shield,
+            _ => panic!("unknown item: {}", name),
+        }
+    }
+
+    /// Chooses a random item with the weights of the given spawn table, or with the spawn
+    /// weights of the items if there is no table
+    pub fn choose<R: Rng>(&self, table: Option<&str>, rng: &mut R) -> Item {
+        let mut weights = match table {
+            Some(table) => self.spawn_tables[table].iter()
+                               .map(|(name, &weight)| (&name[..], weight))
+                               .collect::<Vec<_>>(),
+            None => self.items.iter()
+                        .map(|(name, def)| (&name[..], def.spawn_weight))
+                        .collect::<Vec<_>>(),
+        };
+
+        // The order of the hash maps differs between runs, so sort to get the same choice for
+        // the same random number
+        weights.sort();
+
+        let total = weights.iter().map(|&(_, weight)| weight).fold(0, |a, b| a + b);
+        let mut choice = rng.gen_range(0, total);
+        for &(name, weight) in weights.iter() {
+            if choice < weight {
+                return self.create(name);
+            }
+            choice -= weight;
+        }
+
+        unreachable!();
+    }
+}
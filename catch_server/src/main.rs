@@ -11,17 +11,33 @@ extern crate clock_ticks;
 extern crate rand;
 extern crate hprof;
 extern crate nalgebra as na;
+extern crate getopts;
 
 pub mod components;
 pub mod entities;
 pub mod services;
 pub mod systems;
 pub mod state;
+pub mod match_state;
+pub mod modes;
+pub mod admin;
+pub mod bots;
+pub mod item_defs;
+pub mod recorder;
+pub mod snapshot;
+pub mod stats;
+pub mod event_log;
+pub mod metrics;
 
-use std::collections::HashMap;
-use std::thread;
+use std::env;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use std::net::Ipv4Addr;
+use std::sync::atomic::{AtomicBool, Ordering, ATOMIC_BOOL_INIT};
 use time::{Duration, Timespec};
 
+use getopts::Options;
+
 use bincode::SizeLimit;
 use bincode::rustc_serialize::{encode, encode_into, decode};
 
@@ -30,7 +46,32 @@ use shared::{PlayerId, PlayerInfo, TickNumber, GameInfo, Tick};
 use shared::net::{ClientMessage, ServerMessage};
 use shared::util::PeriodicTimer;
 use shared::tick::DeltaEncodeTick;
-use state::GameState;
+use shared::console::Console;
+use shared::replay::DEFAULT_KEYFRAME_INTERVAL;
+use state::{GameState, SPAWNABLE_ENTITY_TYPES};
+use match_state::MatchConfig;
+use admin::AdminCommand;
+use bots::Difficulty;
+use item_defs::ItemDefs;
+use recorder::Recorder;
+use snapshot::Snapshot;
+use stats::StatsStore;
+use event_log::EventLog;
+use metrics::{MetricsServer, MetricsText, TickRateMeter, PhaseTimes};
+
+// Network events handled per iteration of the server loop at most, so that a flood of messages
+// can't keep the server from running ticks. Remaining events are handled in the next iteration.
+const MAX_EVENTS_PER_ITERATION: usize = 256;
+
+// Wrong admin passwords accepted from one address before its admin commands are ignored
+const MAX_ADMIN_PASSWORD_FAILURES: u32 = 5;
+
+// Set by the signal handler, so that the server can shut down cleanly
+static SHUTDOWN: AtomicBool = ATOMIC_BOOL_INIT;
+
+extern "C" fn request_shutdown(_: libc::c_int) {
+    SHUTDOWN.store(true, Ordering::SeqCst);
+}
 
 #[derive(PartialEq, Eq, Clone, Copy)]
 enum ClientState {
@@ -42,6 +83,9 @@ struct Client {
     peer: enet::Peer,
     state: ClientState,
 
+    // IP address of the peer, in network byte order
+    host: u32,
+
     ping_sent_time: Option<Timespec>,
     ping: Option<Duration>,
 
@@ -49,6 +93,9 @@ struct Client {
     at_tick: Option<TickNumber>,
 
     last_tick: Option<Tick>,
+
+    // Size of all ticks sent to the client
+    sent_tick_bytes: u64,
 }
 
 struct Server {
@@ -62,14 +109,64 @@ struct Server {
 
     tick_timer: PeriodicTimer,
 
+    // Admin commands can be entered on stdin, or sent by clients knowing the password
+    console: Console,
+    admin_password: Option<String>,
+
+    // Clients from these addresses are not allowed to connect
+    banned_hosts: HashSet<u32>,
+
+    // Number of wrong admin passwords sent from each address
+    admin_password_failures: HashMap<u32, u32>,
+
+    // Writes every tick to a replay file, if enabled
+    recorder: Option<Recorder>,
+
+    // The game state is saved here on shutdown, if set
+    snapshot_path: Option<String>,
+
+    // Set by the quit admin command
+    quit: bool,
+
+    // Answers HTTP requests for metrics, if enabled
+    metrics_server: Option<MetricsServer>,
+    tick_rate_meter: TickRateMeter,
+    ticks_total: u64,
+
+    // Number of messages from clients that were ignored, by reason
+    rejected_messages: BTreeMap<&'static str, u64>,
+
+    // Time spent in each phase of the server loop
+    phase_times: PhaseTimes,
+
+    // Ticks that took longer than the tick duration, and ticks that were started more than a
+    // tick duration too late
+    tick_overruns: u64,
+    late_ticks: u64,
+    last_tick_duration_ns: u64,
+
+    // Iterations of the server loop that had more events waiting than were handled
+    event_cap_reached: u64,
+
     // Statistics and stuff
     print_prof_timer: PeriodicTimer,
+    interval_tick_overruns: u64,
+    interval_late_ticks: u64,
     sum_tick_size: usize,
     samples_tick_size: usize,
 }
 
 impl Server {
     fn start(game_info: &GameInfo,
+             match_config: MatchConfig,
+             item_defs: ItemDefs,
+             admin_password: Option<String>,
+             record: Option<(String, TickNumber)>,
+             restore: Option<Snapshot>,
+             snapshot_path: Option<String>,
+             stats: Option<StatsStore>,
+             event_log: Option<EventLog>,
+             metrics_address: Option<String>,
              port: u16,
              peer_count: u32) -> Result<Server, String> {
         let host = try!(enet::Host::new_server(port, peer_count,
@@ -79,16 +176,60 @@ impl Server {
         info!("server started on port {}", port);
         info!("game info: {:?}", game_info);
 
-        let tick_duration_s = 1.0 / (game_info.ticks_per_second as f32);
+        let mut game_state = GameState::new(game_info, match_config, item_defs);
+        if let Some(snapshot) = restore {
+            try!(game_state.restore(snapshot));
+        }
+        if let Some(stats) = stats {
+            game_state.set_stats_store(stats);
+        }
+        if let Some(event_log) = event_log {
+            game_state.set_event_log(event_log);
+        }
+
+        // Don't give out the ids of restored players to new clients
+        let player_id_counter = game_state.player_ids().into_iter().max().unwrap_or(0);
+
+        // A restored game may run at a different tick rate
+        let tick_duration_s = 1.0 / (game_state.game_info().ticks_per_second as f32);
+
+        let metrics_server = match metrics_address {
+            Some(address) => Some(try!(MetricsServer::bind(&address))),
+            None => None,
+        };
+
+        let recorder = match record {
+            Some((path, keyframe_interval)) =>
+                Some(try!(Recorder::create(&path, &game_state, keyframe_interval))),
+            None => None,
+        };
 
         Ok(Server {
             game_info: game_info.clone(),
             host: host,
-            player_id_counter: 0,
+            player_id_counter: player_id_counter,
             clients: HashMap::new(),
-            game_state: GameState::new(game_info),
+            game_state: game_state,
             tick_timer: PeriodicTimer::new(tick_duration_s),
+            console: Console::start(),
+            admin_password: admin_password,
+            banned_hosts: HashSet::new(),
+            admin_password_failures: HashMap::new(),
+            recorder: recorder,
+            snapshot_path: snapshot_path,
+            quit: false,
+            metrics_server: metrics_server,
+            tick_rate_meter: TickRateMeter::new(),
+            ticks_total: 0,
+            rejected_messages: BTreeMap::new(),
+            phase_times: PhaseTimes::new(),
+            tick_overruns: 0,
+            late_ticks: 0,
+            last_tick_duration_ns: 0,
+            event_cap_reached: 0,
             print_prof_timer: PeriodicTimer::new(5.0),
+            interval_tick_overruns: 0,
+            interval_late_ticks: 0,
             sum_tick_size: 0,
             samples_tick_size: 0,
         })
@@ -98,13 +239,48 @@ impl Server {
         self.game_state.tick_number() as f32 + self.tick_timer.progress()
     }
 
-    fn service(&mut self) -> bool {
-        let event = self.host.service(0); 
+    fn tick_duration_ns(&self) -> u64 {
+        1000000000 / self.game_state.game_info().ticks_per_second as u64
+    }
+
+    /// Handles network events, waiting up to `timeout_ms` for the first one to arrive
+    fn service_events(&mut self, timeout_ms: u32) {
+        let mut timeout_ms = timeout_ms;
+
+        for _ in 0..MAX_EVENTS_PER_ITERATION {
+            if !self.service(timeout_ms) {
+                return;
+            }
+
+            // Don't wait again once something has happened
+            timeout_ms = 0;
+        }
+
+        debug!("handled {} events, deferring the rest", MAX_EVENTS_PER_ITERATION);
+        self.event_cap_reached += 1;
+    }
+
+    /// Waits for and handles one network event. Returns false if there was none.
+    fn service(&mut self, timeout_ms: u32) -> bool {
+        let wait_start_ns = clock_ticks::precise_time_ns();
+        let event = self.host.service(timeout_ms);
+        let handle_start_ns = clock_ticks::precise_time_ns();
+        self.phase_times.add("waiting", handle_start_ns - wait_start_ns);
+
+        let handled = self.handle_event(event);
+        self.phase_times.add("events", clock_ticks::precise_time_ns() - handle_start_ns);
+
+        handled
+    }
+
+    fn handle_event(&mut self, event: Result<enet::Event, String>) -> bool {
         match event {
             Ok(enet::Event::Connect(peer)) => {
                 self.player_id_counter += 1;
 
-                info!("client {} is connecting", self.player_id_counter);
+                let host = peer_host(&peer);
+                info!("client {} is connecting from {}", self.player_id_counter,
+                      format_host(host));
 
                 assert!(self.clients.get(&self.player_id_counter).is_none());
                 peer.set_user_data(self.player_id_counter as *mut libc::c_void);
@@ -112,12 +288,21 @@ impl Server {
                     Client {
                         peer: peer,
                         state: ClientState::Connecting,
+                        host: host,
                         ping_sent_time: None,
                         ping: None,
                         at_tick: None,
                         last_tick: None,
+                        sent_tick_bytes: 0,
                     });
 
+                if self.banned_hosts.contains(&host) {
+                    info!("refusing banned client {} from {}", self.player_id_counter,
+                          format_host(host));
+                    self.reject_message("banned");
+                    self.kick(self.player_id_counter, "You are banned from this server");
+                }
+
                 return true;
             }
             Ok(enet::Event::Disconnect(peer)) => {
@@ -141,13 +326,16 @@ impl Server {
 
                 if channel_id != net::Channel::Messages as u8 {
                     warn!("received packet on non-message channel from client {}", player_id);
+                    self.reject_message("wrong_channel");
                 }
                 
                 match decode(&packet.data()) {
                     Ok(message) => 
                         self.process_client_message(player_id, &message),
-                    Err(_) => 
-                        warn!("received invalid message from client {}", player_id),
+                    Err(_) => {
+                        warn!("received invalid message from client {}", player_id);
+                        self.reject_message("invalid");
+                    }
                 };
 
                 return true;
@@ -160,6 +348,10 @@ impl Server {
         }
     }
 
+    fn reject_message(&mut self, reason: &'static str) {
+        *self.rejected_messages.entry(reason).or_insert(0) += 1;
+    }
+
     fn send(&self, client: &Client, message: &ServerMessage) {
         //print!("sending message {:?}", message);
         assert!(client.state == ClientState::Connected);
@@ -173,16 +365,19 @@ impl Server {
         match message {
             &ClientMessage::Pong => {
                 debug!("got pong from {}", player_id);
-                let client = self.clients.get_mut(&player_id).unwrap();
+                let ping_sent_time = self.clients[&player_id].ping_sent_time;
                 
-                match client.ping_sent_time {
+                match ping_sent_time {
                     Some(ping_sent_time) =>
-                        client.ping = Some(time::get_time() - ping_sent_time),
-                    None =>
-                        warn!("received unwarranted pong from {}", player_id)
+                        self.clients.get_mut(&player_id).unwrap().ping =
+                            Some(time::get_time() - ping_sent_time),
+                    None => {
+                        warn!("received unwarranted pong from {}", player_id);
+                        self.reject_message("unexpected_pong");
+                    }
                 };
 
-                client.ping_sent_time = None;
+                self.clients.get_mut(&player_id).unwrap().ping_sent_time = None;
             }
             &ClientMessage::WishConnect { ref name } => {
                 let client_state = self.clients[&player_id].state;
@@ -190,23 +385,68 @@ impl Server {
                 if client_state != ClientState::Connecting {
                     warn!("connected player {} is trying to connect again, ignoring",
                           player_id);
+                    self.reject_message("duplicate_connect");
                     return;
                 }
 
+                // Names identify players in the lifetime stats, so modified clients must not
+                // be able to send arbitrary ones
+                if let Err(error) = shared::player::validate_name(name) {
+                    warn!("refusing client {} with invalid name: {}", player_id, error);
+                    self.reject_message("invalid_name");
+                    self.kick(player_id, &format!("Invalid name: {}", error));
+                    return;
+                }
+
+                // Clients of players restored from a snapshot take over their old id
+                let host = self.clients[&player_id].host;
+                let reconnecting_id = match self.game_state.reconnecting_player(name, host) {
+                    Ok(reconnecting_id) => reconnecting_id,
+                    Err(error) => {
+                        warn!("refusing client {} from {}: {}", player_id, format_host(host),
+                              error);
+                        self.reject_message("reconnect_mismatch");
+                        self.kick(player_id, "This name belongs to a player who is reconnecting");
+                        return;
+                    }
+                };
+                let player_id = match reconnecting_id {
+                    Some(restored_id) => {
+                        info!("client {} reconnected as restored player {}",
+                              player_id, restored_id);
+
+                        let client = self.clients.remove(&player_id).unwrap();
+                        client.peer.set_user_data(restored_id as *mut libc::c_void);
+                        self.clients.insert(restored_id, client);
+                        restored_id
+                    }
+                    None => player_id,
+                };
+
                 info!("player {} connected with name {}", player_id, name);
 
                 self.clients.get_mut(&player_id).unwrap().state = ClientState::Connected;
                 self.send(&self.clients[&player_id],
                           &ServerMessage::AcceptConnect {
                               your_id: player_id,
-                              game_info: self.game_info.clone(),
+                              game_info: self.game_state.game_info().clone(),
                           });
 
-                let player_info = PlayerInfo::new(name.clone());
+                if let Some(leaderboard) = self.game_state.leaderboard() {
+                    self.send(&self.clients[&player_id], &ServerMessage::Leaderboard(leaderboard));
+                }
+
+                if reconnecting_id.is_some() {
+                    // The player's state is replicated to the client at the beginning of the
+                    // next tick
+                    self.game_state.reconnect_player(player_id);
+                } else {
+                    let player_info = PlayerInfo::new(name.clone());
 
-                // This officially adds the player to the game state.
-                // At the beginning of the next tick, PlayerJoin messages will be sent out.
-                self.game_state.add_player(player_id, player_info);
+                    // This officially adds the player to the game state.
+                    // At the beginning of the next tick, PlayerJoin messages will be sent out.
+                    self.game_state.add_player(player_id, player_info, Some(host));
+                }
             }
             &ClientMessage::PlayerInput(ref input)  => {
                 self.game_state.on_player_input(player_id, input);
@@ -214,54 +454,379 @@ impl Server {
             &ClientMessage::StartingTick { ref tick } => {
                 self.clients.get_mut(&player_id).unwrap().at_tick = Some(*tick);
             }
+            &ClientMessage::AdminCommand { ref password, ref command } => {
+                if self.clients[&player_id].state != ClientState::Connected {
+                    warn!("admin command from unconnected client {}, ignoring", player_id);
+                    self.reject_message("unconnected_admin");
+                    return;
+                }
+
+                let host = self.clients[&player_id].host;
+                let failures = self.admin_password_failures.get(&host).cloned().unwrap_or(0);
+                let correct = self.admin_password.as_ref()
+                                  .map_or(false, |p| passwords_equal(p, password));
+
+                let reply = if failures >= MAX_ADMIN_PASSWORD_FAILURES {
+                    // Don't even check the password, so that it can't be guessed
+                    self.reject_message("admin_locked_out");
+                    "Too many wrong admin passwords".to_string()
+                } else if correct {
+                    info!("admin command from player {}: {}", player_id, command);
+                    self.run_admin_command(command)
+                } else {
+                    warn!("player {} sent admin command with wrong password from {}",
+                          player_id, format_host(host));
+                    self.reject_message("wrong_admin_password");
+                    self.admin_password_failures.insert(host, failures + 1);
+                    "Wrong admin password".to_string()
+                };
+
+                self.send(&self.clients[&player_id], &ServerMessage::AdminReply(reply));
+            }
+        }
+    }
+
+    /// Sends a reason to the client and disconnects them. The player is removed from the game
+    /// once the disconnect event arrives.
+    fn kick(&self, player_id: PlayerId, reason: &str) {
+        let client = &self.clients[&player_id];
+
+        // Not using `send` here, since the client may still be connecting
+        let message = ServerMessage::Kicked { reason: reason.to_string() };
+        let data = encode(&message, SizeLimit::Infinite).unwrap();
+        client.peer.send(&data, enet::ffi::ENET_PACKET_FLAG_RELIABLE,
+                         net::Channel::Messages as u8);
+        client.peer.disconnect(0);
+    }
+
+    fn kick_or_ban(&mut self, player_id: PlayerId, ban: bool) -> String {
+        let connected = self.clients.get(&player_id)
+                            .map_or(false, |c| c.state == ClientState::Connected);
+        if !connected {
+            return format!("No player with id {}", player_id);
+        }
+
+        let name = self.game_state.get_player_info(player_id).name.clone();
+        if ban {
+            // Ban the address rather than the name, which the player could simply change
+            self.banned_hosts.insert(self.clients[&player_id].host);
+            self.kick(player_id, "You have been banned");
+            format!("Banned {}", name)
+        } else {
+            self.kick(player_id, "You have been kicked");
+            format!("Kicked {}", name)
+        }
+    }
+
+    /// Executes an admin command, returning text to show to the admin
+    fn run_admin_command(&mut self, line: &str) -> String {
+        let command = match AdminCommand::parse(line) {
+            Ok(command) => command,
+            Err(error) => return error,
+        };
+
+        match command {
+            AdminCommand::ListPlayers => {
+                let mut lines = vec![format!("{:>4} {:<16} {:>6} {:>6}",
+                                             "id", "name", "ping", "score")];
+                let mut player_ids = self.game_state.player_ids();
+                player_ids.sort();
+                for player_id in player_ids {
+                    let info = self.game_state.get_player_info(player_id);
+                    let ping = match self.clients.get(&player_id).and_then(|c| c.ping) {
+                        Some(ping) => format!("{}", ping.num_milliseconds()),
+                        None => "-".to_string(),
+                    };
+                    lines.push(format!("{:>4} {:<16} {:>6} {:>6}",
+                                       player_id, info.name, ping, info.stats.score));
+                }
+                lines.join("\n")
+            }
+            AdminCommand::Leaderboard => {
+                let leaderboard = match self.game_state.leaderboard() {
+                    Some(leaderboard) => leaderboard,
+                    None => return "Stats are not enabled".to_string(),
+                };
+
+                let mut lines = vec![format!("{:<16} {:>6} {:>7} {:>6} {:>8} {:>7}",
+                                             "name", "kills", "catches", "deaths", "catcher",
+                                             "matches")];
+                for (name, stats) in leaderboard {
+                    lines.push(format!("{:<16} {:>6} {:>7} {:>6} {:>7}s {:>7}",
+                                       name, stats.total_kills(), stats.catches, stats.deaths,
+                                       stats.catcher_time_s as u32, stats.matches_played));
+                }
+                lines.join("\n")
+            }
+            AdminCommand::Kick(player_id) =>
+                self.kick_or_ban(player_id, false),
+            AdminCommand::Ban(player_id) =>
+                self.kick_or_ban(player_id, true),
+            AdminCommand::ChangeMap(map_name) => {
+                if !Path::new(&map_name).exists() {
+                    return format!("Map file {} does not exist", map_name);
+                }
+                self.game_state.change_map(&map_name);
+                format!("Changing map to {}", map_name)
+            }
+            AdminCommand::RestartRound => {
+                self.game_state.restart_round();
+                "Restarting round".to_string()
+            }
+            AdminCommand::SetTickRate(ticks_per_second) => {
+                self.tick_timer = PeriodicTimer::new(1.0 / (ticks_per_second as f32));
+                self.game_state.set_ticks_per_second(ticks_per_second);
+                format!("Tick rate set to {}", ticks_per_second)
+            }
+            AdminCommand::ToggleItems => {
+                if self.game_state.toggle_items() {
+                    "Items enabled".to_string()
+                } else {
+                    "Items disabled".to_string()
+                }
+            }
+            AdminCommand::Spawn { type_name, position } => {
+                match self.game_state.spawn_entity(&type_name, position) {
+                    Ok(()) => format!("Spawning {} at ({}, {})", type_name, position.x, position.y),
+                    Err(error) => error,
+                }
+            }
+            AdminCommand::Snapshot(path) => {
+                match path.or(self.snapshot_path.clone()) {
+                    Some(path) => {
+                        match self.game_state.snapshot().save(&path) {
+                            Ok(()) => format!("Saved snapshot to {}", path),
+                            Err(error) => format!("Couldn't save snapshot: {}", error),
+                        }
+                    }
+                    None => "No snapshot file given".to_string(),
+                }
+            }
+            AdminCommand::Quit => {
+                self.quit = true;
+                "Shutting down".to_string()
+            }
+            AdminCommand::Help => {
+                format!("{}\nspawnable entity types: {}", admin::HELP,
+                        SPAWNABLE_ENTITY_TYPES.join(", "))
+            }
         }
     }
 
     fn run(&mut self) {
         let mut start_ns = clock_ticks::precise_time_ns();
 
-        loop {
-            // Is this how DDOS happens?
-            while self.service() {}
+        while !self.quit && !SHUTDOWN.load(Ordering::SeqCst) {
+            // Block until the next tick is due, unless network events arrive before that
+            let timeout_ms = (self.tick_timer.remaining_s() * 1000.0).ceil() as u32;
+            self.service_events(timeout_ms);
+
+            let other_start_ns = clock_ticks::precise_time_ns();
+
+            while let Some(line) = self.console.next_line() {
+                if !line.trim().is_empty() {
+                    let reply = self.run_admin_command(&line);
+                    println!("{}", reply);
+                }
+            }
+
+            if let Some(mut metrics_server) = self.metrics_server.take() {
+                metrics_server.service(|| self.render_metrics());
+                self.metrics_server = Some(metrics_server);
+            }
+
+            let new_start_ns = clock_ticks::precise_time_ns();
+            self.phase_times.add("other", new_start_ns - other_start_ns);
+
+            let delta_s = (new_start_ns - start_ns) as f32 / 1000000000.0;
+            self.tick_timer.add(delta_s);
+            self.print_prof_timer.add(delta_s);
+            start_ns = new_start_ns;
+
+            if self.tick_timer.next() {
+                // If the next tick is due already, we have fallen behind by a whole tick
+                if self.tick_timer.remaining_s() == 0.0 {
+                    self.late_ticks += 1;
+                    self.interval_late_ticks += 1;
+                }
+
+                let tick_start_ns = clock_ticks::precise_time_ns();
 
-            {
-                // Start ticks
                 hprof::start_frame();
-                let mut r = false;
-                if self.tick_timer.next() {
+                {
                     let _g = hprof::enter("ticks");
                     self.tick();
-                    r = true;
                 }
                 hprof::end_frame();
 
-                if r && self.print_prof_timer.next_reset() {
-                    //hprof::profiler().print_timing();  
+                let tick_duration_ns = clock_ticks::precise_time_ns() - tick_start_ns;
+                self.phase_times.add("ticks", tick_duration_ns);
+                self.last_tick_duration_ns = tick_duration_ns;
 
-                    if self.samples_tick_size > 0 {
-                        info!("average tick size over last {} ticks: {:.2} bytes, {:.2} kb/s",
-                              self.samples_tick_size,
-                              self.sum_tick_size as f64 / self.samples_tick_size as f64,
-                              self.sum_tick_size as f64 / (1000.0 * 5.0));
-                    }
-                    self.sum_tick_size = 0;
-                    self.samples_tick_size = 0;
+                if tick_duration_ns > self.tick_duration_ns() {
+                    self.tick_overruns += 1;
+                    self.interval_tick_overruns += 1;
                 }
             }
 
-            thread::sleep_ms(0);
+            if self.print_prof_timer.next_reset() {
+                self.print_loop_stats();
+            }
+        }
 
-            let new_start_ns = clock_ticks::precise_time_ns();
-            let delta_s = (new_start_ns - start_ns) as f32 / 1000000000.0;
-            self.tick_timer.add(delta_s);
-            self.print_prof_timer.add(delta_s);
-            start_ns = new_start_ns;
+        info!("shutting down");
+
+        if let Err(error) = self.game_state.save_stats() {
+            error!("couldn't save stats: {}", error);
+        }
+
+        if let Some(path) = self.snapshot_path.clone() {
+            if let Err(error) = self.game_state.snapshot().save(&path) {
+                error!("couldn't save snapshot: {}", error);
+            }
+        }
+
+        if let Some(recorder) = self.recorder.take() {
+            if let Err(error) = recorder.finish() {
+                error!("couldn't finish replay: {}", error);
+            }
+        }
+    }
+
+    /// Logs what happened in the last interval of the print timer
+    fn print_loop_stats(&mut self) {
+        if self.samples_tick_size > 0 {
+            info!("average tick size over last {} ticks: {:.2} bytes, {:.2} kb/s",
+                  self.samples_tick_size,
+                  self.sum_tick_size as f64 / self.samples_tick_size as f64,
+                  self.sum_tick_size as f64 / (1000.0 * 5.0));
+        }
+        self.sum_tick_size = 0;
+        self.samples_tick_size = 0;
+
+        let phases = self.phase_times.take_interval().iter()
+                         .map(|(phase, time_ns)| {
+                             format!("{} {:.1}ms", phase, *time_ns as f64 / 1000000.0)
+                         })
+                         .collect::<Vec<_>>();
+        debug!("time spent in the last interval: {}", phases.join(", "));
+
+        if self.interval_tick_overruns > 0 || self.interval_late_ticks > 0 {
+            warn!("{} ticks took longer than the tick duration and {} ticks started late \
+                   in the last interval",
+                  self.interval_tick_overruns, self.interval_late_ticks);
+        }
+        self.interval_tick_overruns = 0;
+        self.interval_late_ticks = 0;
+    }
+
+    fn render_metrics(&self) -> String {
+        let mut m = MetricsText::new();
+
+        m.single("catch_ticks_total", "counter", "Ticks run since the server started",
+                 self.ticks_total as f64);
+        m.single("catch_tick_rate", "gauge", "Ticks per second achieved over the last seconds",
+                 self.tick_rate_meter.ticks_per_second() as f64);
+        m.single("catch_target_tick_rate", "gauge", "Ticks per second the server aims for",
+                 self.game_state.game_info().ticks_per_second as f64);
+        m.single("catch_tick_number", "gauge", "Number of the current tick",
+                 self.game_state.tick_number() as f64);
+
+        m.single("catch_tick_overruns_total", "counter",
+                 "Ticks that took longer than the tick duration",
+                 self.tick_overruns as f64);
+        m.single("catch_late_ticks_total", "counter",
+                 "Ticks that were started more than a tick duration too late",
+                 self.late_ticks as f64);
+        m.single("catch_last_tick_duration_seconds", "gauge", "Time it took to run the last tick",
+                 self.last_tick_duration_ns as f64 / 1000000000.0);
+        m.single("catch_event_cap_reached_total", "counter",
+                 "Iterations of the server loop that deferred network events to the next one",
+                 self.event_cap_reached as f64);
+
+        m.family("catch_phase_seconds_total", "counter",
+                 "Time spent in each phase of the server loop");
+        for (phase, &time_ns) in self.phase_times.total_ns().iter() {
+            m.sample("catch_phase_seconds_total", &[("phase", phase)],
+                     time_ns as f64 / 1000000000.0);
+        }
+
+        m.profile_sections();
+
+        m.family("catch_client_tick_bytes_total", "counter",
+                 "Size of the ticks sent to each connected client");
+        for (player_id, client) in self.clients.iter() {
+            if client.state == ClientState::Connected {
+                m.sample("catch_client_tick_bytes_total",
+                         &[("player_id", &player_id.to_string())],
+                         client.sent_tick_bytes as f64);
+            }
+        }
+
+        let num_connected = self.clients.values()
+                                .filter(|c| c.state == ClientState::Connected)
+                                .count();
+        m.single("catch_connected_players", "gauge", "Clients that have joined the game",
+                 num_connected as f64);
+        m.single("catch_connecting_clients", "gauge", "Clients that have not joined yet",
+                 (self.clients.len() - num_connected) as f64);
+        m.single("catch_players", "gauge", "Players in the game, including bots",
+                 self.game_state.player_ids().len() as f64);
+        m.single("catch_bots", "gauge", "Players controlled by the server",
+                 self.game_state.bot_ids().len() as f64);
+
+        let entity_types = &self.game_state.game_info().entity_types;
+        let mut entity_counts = vec![0; entity_types.len()];
+        for (_, type_id, _) in self.game_state.world.systems.net_entity_system
+                                   .entity_list(&self.game_state.world.data) {
+            entity_counts[type_id as usize] += 1;
+        }
+        m.family("catch_entities", "gauge", "Net entities by type");
+        for (&(ref type_name, _), count) in entity_types.iter().zip(entity_counts) {
+            m.sample("catch_entities", &[("type", type_name)], count as f64);
+        }
+
+        m.family("catch_rejected_messages_total", "counter",
+                 "Messages from clients that were ignored, by reason");
+        for (reason, &count) in self.rejected_messages.iter() {
+            m.sample("catch_rejected_messages_total", &[("reason", reason)], count as f64);
+        }
+
+        m.finish()
+    }
+
+    /// Adds or removes one bot per tick to get closer to the number of players that the
+    /// config asks for
+    fn tick_fill_bots(&mut self) {
+        let (fill_bots, difficulty) = {
+            let config = self.game_state.match_config();
+            (config.fill_bots, config.bot_difficulty)
+        };
+
+        let num_humans = self.clients.values()
+                             .filter(|c| c.state == ClientState::Connected)
+                             .count();
+        let bot_ids = self.game_state.bot_ids();
+
+        if num_humans + bot_ids.len() < fill_bots {
+            // Bots take their ids from the same counter as clients, so they can never collide
+            self.player_id_counter += 1;
+            info!("adding bot {}", self.player_id_counter);
+            self.game_state.add_bot(self.player_id_counter, difficulty);
+        } else if num_humans + bot_ids.len() > fill_bots && !bot_ids.is_empty() {
+            let bot_id = *bot_ids.iter().max().unwrap();
+            info!("removing bot {}", bot_id);
+            self.game_state.remove_player(bot_id);
         }
     }
 
     fn tick(&mut self) {
+        self.tick_fill_bots();
         self.game_state.tick();
 
+        self.ticks_total += 1;
+        self.tick_rate_meter.tick(clock_ticks::precise_time_ns());
+
         //debug!("sending tick {}", self.game_state.tick_number);
         
         // Broadcast tick to clients
@@ -281,7 +846,7 @@ impl Server {
 
                 self.game_state.world.systems.net_entity_system
                     .store_in_tick_state(player_id, &mut tick.state,
-                                         &mut self.game_state.world.data);
+                                         &self.game_state.world.data);
                 drop(_g);
                 let _g = hprof::enter("encode");
 
@@ -311,6 +876,7 @@ impl Server {
 
                 self.sum_tick_size += data.len();
                 self.samples_tick_size += 1;
+                self.clients.get_mut(&player_id).unwrap().sent_tick_bytes += data.len() as u64;
 
                 self.clients[&player_id]
                     .peer.send(&data, enet::ffi::ENET_PACKET_FLAG_RELIABLE,
@@ -322,21 +888,200 @@ impl Server {
                 self.clients.get_mut(&player_id).unwrap().last_tick = Some(tick);
             }
         }
+        drop(_g);
+
+        // Show the new leaderboard after a match
+        if self.game_state.take_leaderboard_changed() {
+            let message = ServerMessage::Leaderboard(self.game_state.leaderboard().unwrap());
+            for client in self.clients.values() {
+                if client.state == ClientState::Connected {
+                    self.send(client, &message);
+                }
+            }
+        }
+
+        if self.recorder.is_some() {
+            let _g = hprof::enter("record");
+
+            let result = self.recorder.as_mut().unwrap().record(&self.game_state);
+            if let Err(error) = result {
+                error!("couldn't record tick, stopping recording: {}", error);
+                self.recorder = None;
+            }
+        }
+
+        self.game_state.world.systems.net_entity_system
+            .clear_forced_components(&mut self.game_state.world.data);
+    }
+}
+
+/// Returns the IP address of a peer, in network byte order
+fn peer_host(peer: &enet::Peer) -> u32 {
+    peer.address().host
+}
+
+fn format_host(host: u32) -> String {
+    Ipv4Addr::from(u32::from_be(host)).to_string()
+}
+
+/// Compares passwords in time independent of where they differ
+fn passwords_equal(a: &str, b: &str) -> bool {
+    if a.len() != b.len() {
+        return false;
     }
+
+    a.bytes().zip(b.bytes()).fold(0, |difference, (x, y)| difference | (x ^ y)) == 0
 }
 
 fn main() {
     env_logger::init().unwrap();
     enet::initialize().unwrap();
 
+    let args: Vec<String> = env::args().collect();
+
+    let mut opts = Options::new();
+    opts.optmulti("m", "map", "add a map to the rotation", "FILE");
+    opts.optopt("", "mode", "set game mode for maps that don't specify one", "MODE");
+    opts.optopt("", "frag-limit", "set score limit of deathmatch mode (0 for none)", "FRAGS");
+    opts.optopt("", "spawn-protection", "set invulnerability time after spawning", "SECONDS");
+    opts.optflag("", "friendly-fire", "allow players to hurt their own team");
+    opts.optopt("", "team-rotate", "set time after which the catcher team changes", "SECONDS");
+    opts.optopt("", "hill-move", "set time after which the hill moves", "SECONDS");
+    opts.optopt("", "tag-immunity", "set time in which players can't be tagged back", "SECONDS");
+    opts.optopt("", "items", "load item definitions from a file", "FILE");
+    opts.optopt("", "admin-password", "allow clients to run admin commands with this password",
+                "PASSWORD");
+    opts.optopt("", "min-players", "set number of players needed to start a match", "NUM");
+    opts.optopt("", "bots", "add bots while there are fewer players than this", "NUM");
+    opts.optopt("", "bot-difficulty", "set difficulty of bots (easy, normal or hard)", "LEVEL");
+    opts.optopt("", "time-limit", "set match time limit in seconds (0 for none)", "SECONDS");
+    opts.optopt("", "score-limit", "set match score limit (0 for none)", "SCORE");
+    opts.optopt("", "seed", "seed the random number generator to reproduce a run", "SEED");
+    opts.optopt("", "record", "record every tick to a replay file", "FILE");
+    opts.optopt("", "keyframe-interval", "set number of ticks between full ticks in replays",
+                "TICKS");
+    opts.optopt("", "snapshot", "save the game state to a file when shutting down", "FILE");
+    opts.optopt("", "restore", "continue the game saved in a snapshot file", "FILE");
+    opts.optopt("", "stats", "keep lifetime stats of players in this file", "FILE");
+    opts.optopt("", "event-log", "append gameplay events as JSON lines to this file", "FILE");
+    opts.optopt("", "metrics", "serve metrics over HTTP on this address, e.g. 127.0.0.1:9989",
+                "ADDRESS");
+    let matches = match opts.parse(&args[1..]) {
+        Ok(m) => m,
+        Err(e) => panic!(e.to_string())
+    };
+
+    let mut match_config = MatchConfig::default();
+    let maps = matches.opt_strs("m");
+    if !maps.is_empty() {
+        match_config.maps = maps;
+    }
+    if let Some(frag_limit) = matches.opt_str("frag-limit") {
+        let frag_limit: u32 = frag_limit.parse().unwrap();
+        match_config.frag_limit = if frag_limit > 0 { Some(frag_limit) } else { None };
+    }
+    if let Some(spawn_protection_s) = matches.opt_str("spawn-protection") {
+        match_config.spawn_protection_s = spawn_protection_s.parse().unwrap();
+    }
+    if matches.opt_present("friendly-fire") {
+        match_config.friendly_fire = true;
+    }
+    if let Some(team_rotate_s) = matches.opt_str("team-rotate") {
+        match_config.team_rotate_s = team_rotate_s.parse().unwrap();
+    }
+    if let Some(hill_move_s) = matches.opt_str("hill-move") {
+        match_config.hill_move_s = hill_move_s.parse().unwrap();
+    }
+    if let Some(tag_immunity_s) = matches.opt_str("tag-immunity") {
+        match_config.tag_immunity_s = tag_immunity_s.parse().unwrap();
+    }
+    if let Some(mode) = matches.opt_str("mode") {
+        if modes::create(&mode, &match_config).is_none() {
+            panic!("unknown game mode {}", mode);
+        }
+        match_config.mode = mode;
+    }
+    if let Some(min_players) = matches.opt_str("min-players") {
+        match_config.min_players = min_players.parse().unwrap();
+    }
+    if let Some(fill_bots) = matches.opt_str("bots") {
+        match_config.fill_bots = fill_bots.parse().unwrap();
+    }
+    if let Some(difficulty) = matches.opt_str("bot-difficulty") {
+        match_config.bot_difficulty = match Difficulty::from_name(&difficulty) {
+            Some(difficulty) => difficulty,
+            None => panic!("unknown bot difficulty {}", difficulty),
+        };
+    }
+    if let Some(time_limit_s) = matches.opt_str("time-limit") {
+        let time_limit_s: f32 = time_limit_s.parse().unwrap();
+        match_config.time_limit_s = if time_limit_s > 0.0 { Some(time_limit_s) } else { None };
+    }
+    if let Some(score_limit) = matches.opt_str("score-limit") {
+        let score_limit: u32 = score_limit.parse().unwrap();
+        match_config.score_limit = if score_limit > 0 { Some(score_limit) } else { None };
+    }
+    if let Some(seed) = matches.opt_str("seed") {
+        match_config.seed = Some(seed.parse().unwrap());
+    }
+
     let entity_types = shared::entities::all_entity_types();
     let game_info = GameInfo {
-        map_name: "data/maps/linemap.tmx".to_string(),
+        map_name: match_config.maps[0].clone(),
         entity_types: entity_types,
         ticks_per_second: 30,
     };
 
-    match Server::start(&game_info, 9988, 128).as_mut() {
+    let items_file = matches.opt_str("items").unwrap_or("data/items.json".to_string());
+    let item_defs = match ItemDefs::load(&items_file) {
+        Ok(item_defs) => item_defs,
+        Err(error) => panic!("couldn't load item definitions from {}: {}", items_file, error),
+    };
+
+    let admin_password = matches.opt_str("admin-password");
+
+    let record = matches.opt_str("record").map(|path| {
+        let keyframe_interval = match matches.opt_str("keyframe-interval") {
+            Some(interval) => interval.parse().unwrap(),
+            None => DEFAULT_KEYFRAME_INTERVAL,
+        };
+        if keyframe_interval == 0 {
+            panic!("keyframe interval must be positive");
+        }
+        (path, keyframe_interval)
+    });
+
+    let restore = matches.opt_str("restore").map(|path| {
+        match Snapshot::load(&path) {
+            Ok(snapshot) => snapshot,
+            Err(error) => panic!("couldn't load snapshot {}: {}", path, error),
+        }
+    });
+    let snapshot_path = matches.opt_str("snapshot");
+
+    let stats = matches.opt_str("stats").map(|path| {
+        match StatsStore::open(&path) {
+            Ok(stats) => stats,
+            Err(error) => panic!("couldn't load stats from {}: {}", path, error),
+        }
+    });
+
+    let event_log = matches.opt_str("event-log").map(|path| {
+        match EventLog::open(&path) {
+            Ok(event_log) => event_log,
+            Err(error) => panic!("couldn't open event log {}: {}", path, error),
+        }
+    });
+
+    // Stop cleanly on Ctrl-C, so that the replay can be finished
+    unsafe {
+        libc::signal(libc::SIGINT, request_shutdown as libc::sighandler_t);
+        libc::signal(libc::SIGTERM, request_shutdown as libc::sighandler_t);
+    }
+
+    match Server::start(&game_info, match_config, item_defs, admin_password, record,
+                        restore, snapshot_path, stats, event_log, matches.opt_str("metrics"),
+                        9988, 128).as_mut() {
         Ok(server) =>
             server.run(),
         Err(error) =>
@@ -0,0 +1,192 @@
+use shared::MatchPhase;
+
+use bots::Difficulty;
+
+pub struct MatchConfig {
+    // Maps to rotate through
+    pub maps: Vec<String>,
+
+    // Game mode for maps that don't specify their own
+    pub mode: String,
+
+    // Players are invulnerable for this long after spawning
+    pub spawn_protection_s: f32,
+
+    // Score limit of the deathmatch mode, overriding `score_limit`
+    pub frag_limit: Option<u32>,
+
+    // Can players hurt members of their own team?
+    pub friendly_fire: bool,
+
+    // Time after which the catcher team changes in team catch
+    pub team_rotate_s: f32,
+
+    // Time after which the hill moves in king of the hill
+    pub hill_move_s: f32,
+
+    // Time in which the catcher can't tag back the player that tagged them in tag
+    pub tag_immunity_s: f32,
+
+    // Number of players needed to leave the warmup phase
+    pub min_players: usize,
+
+    // Bots are added while there are fewer players than this, and removed again when humans join
+    pub fill_bots: usize,
+    pub bot_difficulty: Difficulty,
+
+    pub countdown_s: f32,
+    pub intermission_s: f32,
+
+    // A match ends when either of the limits is reached
+    pub time_limit_s: Option<f32>,
+    pub score_limit: Option<u32>,
+
+    // Seed of the random number generator. Runs with the same seed and the same player
+    // inputs are identical. Chosen randomly if not set.
+    pub seed: Option<u32>,
+}
+
+impl Default for MatchConfig {
+    fn default() -> MatchConfig {
+        MatchConfig {
+            maps: vec!["data/maps/linemap.tmx".to_string()],
+            mode: "catch".to_string(),
+            spawn_protection_s: 2.5,
+            frag_limit: Some(25),
+            friendly_fire: false,
+            team_rotate_s: 60.0,
+            hill_move_s: 45.0,
+            tag_immunity_s: 3.0,
+            min_players: 2,
+            fill_bots: 0,
+            bot_difficulty: Difficulty::Normal,
+            countdown_s: 5.0,
+            intermission_s: 10.0,
+            time_limit_s: Some(5.0 * 60.0),
+            score_limit: Some(100),
+            seed: None,
+        }
+    }
+}
+
+/// Keeps track of the current phase of the match and the map rotation
+pub struct MatchState {
+    config: MatchConfig,
+    phase: MatchPhase,
+
+    // Time spent in the current phase
+    phase_time_s: f32,
+
+    // Index of the current map in `config.maps`
+    map_index: usize,
+}
+
+impl MatchState {
+    pub fn new(config: MatchConfig) -> MatchState {
+        assert!(!config.maps.is_empty(), "need at least one map");
+
+        MatchState {
+            config: config,
+            phase: MatchPhase::Warmup,
+            phase_time_s: 0.0,
+            map_index: 0,
+        }
+    }
+
+    pub fn phase(&self) -> MatchPhase {
+        self.phase
+    }
+
+    /// Time until the current phase ends, if it has a fixed duration
+    pub fn time_left_s(&self) -> Option<f32> {
+        self.phase_duration_s(self.phase).map(|d| (d - self.phase_time_s).max(0.0))
+    }
+
+    pub fn phase_duration_s(&self, phase: MatchPhase) -> Option<f32> {
+        match phase {
+            MatchPhase::Warmup => None,
+            MatchPhase::Countdown => Some(self.config.countdown_s),
+            MatchPhase::Playing => self.config.time_limit_s,
+            MatchPhase::Intermission => Some(self.config.intermission_s),
+        }
+    }
+
+    pub fn config(&self) -> &MatchConfig {
+        &self.config
+    }
+
+    pub fn current_map(&self) -> &str {
+        &self.config.maps[self.map_index]
+    }
+
+    pub fn next_map(&self) -> &str {
+        &self.config.maps[(self.map_index + 1) % self.config.maps.len()]
+    }
+
+    /// Advances the map rotation, returning the new map
+    pub fn rotate_map(&mut self) -> &str {
+        self.map_index = (self.map_index + 1) % self.config.maps.len();
+        self.current_map()
+    }
+
+    /// Time spent in the current phase
+    pub fn phase_time_s(&self) -> f32 {
+        self.phase_time_s
+    }
+
+    /// Continues a match from a snapshot. Maps that are not part of the rotation keep the
+    /// rotation at its start.
+    pub fn restore(&mut self, phase: MatchPhase, phase_time_s: f32, map_name: &str) {
+        self.phase = phase;
+        self.phase_time_s = phase_time_s;
+
+        match self.config.maps.iter().position(|map| map == map_name) {
+            Some(index) => self.map_index = index,
+            None => warn!("map {} of snapshot is not in the rotation", map_name),
+        }
+    }
+
+    /// Forces the match into the given phase
+    pub fn set_phase(&mut self, phase: MatchPhase) {
+        self.phase = phase;
+        self.phase_time_s = 0.0;
+    }
+
+    /// Advances the time of the current phase. Returns the new phase if it changed.
+    /// The game mode may override the score limit of the config with `mode_score_limit`.
+    pub fn tick(&mut self, dur_s: f32, num_players: usize, max_score: u32,
+                mode_score_limit: Option<u32>) -> Option<MatchPhase> {
+        self.phase_time_s += dur_s;
+
+        let score_limit = mode_score_limit.or(self.config.score_limit);
+        let enough_players = num_players >= self.config.min_players;
+        let time_over = match self.phase_duration_s(self.phase) {
+            Some(duration_s) => self.phase_time_s >= duration_s,
+            None => false,
+        };
+
+        let next_phase = match self.phase {
+            MatchPhase::Warmup if enough_players =>
+                Some(MatchPhase::Countdown),
+            MatchPhase::Countdown | MatchPhase::Playing if !enough_players =>
+                Some(MatchPhase::Warmup),
+            MatchPhase::Countdown if time_over =>
+                Some(MatchPhase::Playing),
+            MatchPhase::Playing if time_over =>
+                Some(MatchPhase::Intermission),
+            MatchPhase::Playing if score_limit.map_or(false, |l| max_score >= l) =>
+                Some(MatchPhase::Intermission),
+            MatchPhase::Intermission if time_over && enough_players =>
+                Some(MatchPhase::Countdown),
+            MatchPhase::Intermission if time_over =>
+                Some(MatchPhase::Warmup),
+            _ => None
+        };
+
+        if let Some(phase) = next_phase {
+            self.set_phase(phase);
+        }
+
+        next_phase
+    }
+}
@@ -0,0 +1,300 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use clock_ticks;
+use hprof;
+use hprof::ProfileNode;
+
+// Window over which the achieved tick rate is measured
+const TICK_RATE_WINDOW_NS: u64 = 5000000000;
+
+// Connections that haven't been answered within this time are dropped
+const CONNECTION_TIMEOUT_NS: u64 = 5000000000;
+
+// Limits on what the metrics server handles, so that it can't be used to slow down the game
+const MAX_ACCEPTS_PER_SERVICE: usize = 4;
+const MAX_CONNECTIONS: usize = 16;
+const MAX_REQUEST_SIZE: usize = 8192;
+
+/// Text in the Prometheus exposition format. All samples of a metric need to be added right
+/// after its `family` line.
+pub struct MetricsText {
+    text: String,
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace("\\", "\\\\").replace("\"", "\\\"").replace("\n", "\\n")
+}
+
+impl MetricsText {
+    pub fn new() -> MetricsText {
+        MetricsText {
+            text: String::new(),
+        }
+    }
+
+    /// Starts a metric. `kind` is either "counter" or "gauge".
+    pub fn family(&mut self, name: &str, kind: &str, help: &str) {
+        self.text.push_str(&format!("# HELP {} {}\n# TYPE {} {}\n", name, help, name, kind));
+    }
+
+    pub fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) {
+        if labels.is_empty() {
+            self.text.push_str(&format!("{} {}\n", name, value));
+        } else {
+            let labels = labels.iter()
+                               .map(|&(key, value)| format!("{}=\"{}\"", key, escape_label(value)))
+                               .collect::<Vec<_>>();
+            self.text.push_str(&format!("{}{{{}}} {}\n", name, labels.join(","), value));
+        }
+    }
+
+    /// A metric with a single sample
+    pub fn single(&mut self, name: &str, kind: &str, help: &str, value: f64) {
+        self.family(name, kind, help);
+        self.sample(name, &[], value);
+    }
+
+    /// Adds the total time and number of calls of every hprof section, named by their path
+    pub fn profile_sections(&mut self) {
+        fn collect(node: &ProfileNode, path: &str, sections: &mut Vec<(String, u64, u64)>) {
+            for child in node.children.borrow().iter() {
+                let child_path = if path.is_empty() { child.name.to_string() }
+                                 else { format!("{}/{}", path, child.name) };
+                sections.push((child_path.clone(), child.total_time.get(), child.calls.get()));
+                collect(child, &child_path, sections);
+            }
+        }
+
+        let mut sections = Vec::new();
+        collect(&hprof::profiler().root(), "", &mut sections);
+
+        self.family("catch_section_seconds_total", "counter",
+                    "Time spent in each profiled section of the server loop");
+        for &(ref section, time_ns, _) in &sections {
+            self.sample("catch_section_seconds_total", &[("section", section)],
+                        time_ns as f64 / 1000000000.0);
+        }
+
+        self.family("catch_section_calls_total", "counter",
+                    "Number of times each profiled section has been entered");
+        for &(ref section, _, calls) in &sections {
+            self.sample("catch_section_calls_total", &[("section", section)], calls as f64);
+        }
+    }
+
+    pub fn finish(self) -> String {
+        self.text
+    }
+}
+
+/// Measures the number of ticks per second that are actually run
+pub struct TickRateMeter {
+    // Times of the ticks within the window
+    tick_times_ns: VecDeque<u64>,
+}
+
+impl TickRateMeter {
+    pub fn new() -> TickRateMeter {
+        TickRateMeter {
+            tick_times_ns: VecDeque::new(),
+        }
+    }
+
+    pub fn tick(&mut self, time_ns: u64) {
+        self.tick_times_ns.push_back(time_ns);
+        while time_ns - self.tick_times_ns[0] > TICK_RATE_WINDOW_NS {
+            self.tick_times_ns.pop_front();
+        }
+    }
+
+    pub fn ticks_per_second(&self) -> f32 {
+        match (self.tick_times_ns.front(), self.tick_times_ns.back()) {
+            (Some(&first), Some(&last)) if last > first =>
+                (self.tick_times_ns.len() - 1) as f32 / ((last - first) as f32 / 1000000000.0),
+            _ => 0.0,
+        }
+    }
+}
+
+/// Wall clock time spent in the phases of the server loop, e.g. waiting for network events or
+/// running ticks. Besides the totals, the times since the last call of `take_interval` are
+/// kept for logging.
+pub struct PhaseTimes {
+    total_ns: BTreeMap<&'static str, u64>,
+    interval_ns: BTreeMap<&'static str, u64>,
+}
+
+impl PhaseTimes {
+    pub fn new() -> PhaseTimes {
+        PhaseTimes {
+            total_ns: BTreeMap::new(),
+            interval_ns: BTreeMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, phase: &'static str, time_ns: u64) {
+        *self.total_ns.entry(phase).or_insert(0) += time_ns;
+        *self.interval_ns.entry(phase).or_insert(0) += time_ns;
+    }
+
+    pub fn total_ns(&self) -> &BTreeMap<&'static str, u64> {
+        &self.total_ns
+    }
+
+    pub fn take_interval(&mut self) -> BTreeMap<&'static str, u64> {
+        let mut interval_ns = BTreeMap::new();
+        ::std::mem::swap(&mut interval_ns, &mut self.interval_ns);
+        interval_ns
+    }
+}
+
+/// Minimal HTTP server that answers requests for /metrics. It never blocks, so that it can be
+/// serviced from the server loop: requests and responses are read and written bit by bit over
+/// multiple calls of `service`.
+pub struct MetricsServer {
+    listener: TcpListener,
+    connections: Vec<Connection>,
+}
+
+struct Connection {
+    stream: TcpStream,
+    accept_time_ns: u64,
+
+    // What has been received of the request so far
+    request: Vec<u8>,
+
+    // Response and the number of bytes of it that have been sent
+    response: Option<(Vec<u8>, usize)>,
+}
+
+enum Progress {
+    Pending,
+    Done,
+}
+
+impl MetricsServer {
+    pub fn bind(address: &str) -> Result<MetricsServer, String> {
+        let listener = try!(TcpListener::bind(address).map_err(|e| e.to_string()));
+        try!(listener.set_nonblocking(true).map_err(|e| e.to_string()));
+
+        info!("serving metrics on http://{}/metrics", address);
+
+        Ok(MetricsServer {
+            listener: listener,
+            connections: Vec::new(),
+        })
+    }
+
+    /// Accepts new connections and makes progress on the pending ones. The metrics are only
+    /// rendered if someone asks for them.
+    pub fn service<F: FnMut() -> String>(&mut self, mut render: F) {
+        let now_ns = clock_ticks::precise_time_ns();
+
+        for _ in 0..MAX_ACCEPTS_PER_SERVICE {
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(_) => break, // would block
+            };
+
+            if self.connections.len() >= MAX_CONNECTIONS {
+                debug!("too many metrics connections, dropping one");
+                continue;
+            }
+            if let Err(error) = stream.set_nonblocking(true) {
+                debug!("couldn't set up metrics connection: {}", error);
+                continue;
+            }
+
+            self.connections.push(Connection {
+                stream: stream,
+                accept_time_ns: now_ns,
+                request: Vec::new(),
+                response: None,
+            });
+        }
+
+        // Rendered at most once per call, however many requests there are
+        let mut body = None;
+
+        let mut i = 0;
+        while i < self.connections.len() {
+            let keep = match self.connections[i].progress(&mut render, &mut body) {
+                Ok(Progress::Pending) =>
+                    now_ns - self.connections[i].accept_time_ns < CONNECTION_TIMEOUT_NS,
+                Ok(Progress::Done) => false,
+                Err(error) => {
+                    debug!("couldn't answer metrics request: {}", error);
+                    false
+                }
+            };
+
+            if keep {
+                i += 1;
+            } else {
+                self.connections.swap_remove(i);
+            }
+        }
+    }
+}
+
+fn would_block(error: &io::Error) -> bool {
+    error.kind() == io::ErrorKind::WouldBlock || error.kind() == io::ErrorKind::Interrupted
+}
+
+impl Connection {
+    fn progress<F: FnMut() -> String>(&mut self, render: &mut F, body: &mut Option<String>)
+                                      -> Result<Progress, String> {
+        if self.response.is_none() {
+            let mut buffer = [0; 1024];
+            loop {
+                match self.stream.read(&mut buffer) {
+                    Ok(0) => return Err("connection closed before the request ended".to_string()),
+                    Ok(len) => self.request.extend(buffer[..len].iter().cloned()),
+                    Err(ref error) if would_block(error) => break,
+                    Err(error) => return Err(error.to_string()),
+                }
+
+                if self.request.len() > MAX_REQUEST_SIZE {
+                    return Err("request too large".to_string());
+                }
+            }
+
+            // We don't care about the headers, but they need to be complete
+            let complete = self.request.windows(4).any(|w| w == &b"\r\n\r\n"[..]) ||
+                           self.request.windows(2).any(|w| w == &b"\n\n"[..]);
+            if !complete {
+                return Ok(Progress::Pending);
+            }
+
+            let request = String::from_utf8_lossy(&self.request).into_owned();
+            let path = request.split_whitespace().nth(1).unwrap_or("");
+
+            let response = if request.starts_with("GET ") && path == "/metrics" {
+                if body.is_none() {
+                    *body = Some(render());
+                }
+                let body = body.as_ref().unwrap();
+                format!("HTTP/1.0 200 OK\r\n\
+                         Content-Type: text/plain; version=0.0.4\r\n\
+                         Content-Length: {}\r\n\r\n{}", body.len(), body)
+            } else {
+                "HTTP/1.0 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_string()
+            };
+            self.response = Some((response.into_bytes(), 0));
+        }
+
+        let (ref response, ref mut sent) = *self.response.as_mut().unwrap();
+        while *sent < response.len() {
+            match self.stream.write(&response[*sent..]) {
+                Ok(0) => return Err("connection closed before the response was sent".to_string()),
+                Ok(len) => *sent += len,
+                Err(ref error) if would_block(error) => return Ok(Progress::Pending),
+                Err(error) => return Err(error.to_string()),
+            }
+        }
+
+        Ok(Progress::Done)
+    }
+}
@@ -0,0 +1,133 @@
+use ecs;
+use rand::Rng;
+use na::{Vec2, Norm};
+
+use shared::{NEUTRAL_PLAYER_ID, PlayerId, DeathReason, GameEvent};
+use shared::services::HasEvents;
+
+use modes::{GameMode, ModeContext};
+
+/// The classic rules: there is always exactly one catcher, who kills players by touching them
+pub struct CatchMode {
+    // Do kills give points? Other modes may reuse the catcher rules with their own scoring.
+    score_kills: bool,
+}
+
+impl CatchMode {
+    pub fn new() -> CatchMode {
+        CatchMode {
+            score_kills: true,
+        }
+    }
+
+    pub fn without_scoring() -> CatchMode {
+        CatchMode {
+            score_kills: false,
+        }
+    }
+}
+
+impl GameMode for CatchMode {
+    fn name(&self) -> &'static str {
+        "catch"
+    }
+
+    fn on_leave(&mut self, player_id: PlayerId, ctx: &mut ModeContext) {
+        // If the disconnected player was the catcher, choose a random new alive one as catcher
+        if ctx.is_catcher(player_id) {
+            let alive_players = ctx.alive_players().into_iter()
+                                   .filter(|&id| id != player_id)
+                                   .collect::<Vec<_>>();
+
+            if !alive_players.is_empty() {
+                let index = ctx.world.services.rng.gen_range(0, alive_players.len());
+                let chosen_one = alive_players[index];
+
+                assert!(!ctx.is_catcher(chosen_one));
+                ctx.set_catcher(chosen_one, true);
+            }
+        }
+    }
+
+    fn on_spawn(&mut self, player_id: PlayerId, _entity: ecs::Entity, ctx: &mut ModeContext) {
+        // If we don't have a catcher right now, this player is lucky
+        let is_catcher = ctx.current_catcher() == None;
+        ctx.set_catcher(player_id, is_catcher);
+    }
+
+    fn on_catch(&mut self, catcher_id: PlayerId, catchee_id: PlayerId, position: Vec2<f32>,
+                ctx: &mut ModeContext) {
+        // Being caught means being killed
+        ctx.world.services.add_event(&GameEvent::PlayerDied {
+            player_id: catchee_id,
+            position: position,
+            responsible_player_id: catcher_id,
+            reason: DeathReason::Caught,
+        });
+    }
+
+    fn on_death(&mut self, player_id: PlayerId, position: Vec2<f32>,
+                responsible_player_id: PlayerId, reason: DeathReason,
+                ctx: &mut ModeContext) {
+        // Update the score
+        ctx.players.get_mut(&player_id).unwrap().info.stats.deaths += 1;
+        if self.score_kills && responsible_player_id != NEUTRAL_PLAYER_ID {
+            let responsible_player = ctx.players.get_mut(&responsible_player_id).unwrap();
+            responsible_player.info.stats.score +=
+                match reason {
+                    DeathReason::Caught => 10,
+                    _ => 1,
+                };
+        }
+
+        // If this player is the catcher, we need to determine a new catcher
+        let is_catcher = ctx.is_catcher(player_id);
+        ctx.set_catcher(player_id, false);
+
+        if is_catcher {
+            let responsible_alive =
+                responsible_player_id != player_id &&
+                ctx.players.get(&responsible_player_id).map_or(false, |p| p.alive());
+
+            if responsible_alive {
+                // If we were killed by another player, that one becomes the catcher
+                ctx.set_catcher(responsible_player_id, true);
+            } else {
+                // Otherwise, find the player that is the closest to the dead catcher
+                let player_ids = ctx.alive_players().into_iter()
+                                    .filter(|&id| id != player_id)
+                                    .collect::<Vec<_>>();
+
+                let mut closest: Option<(PlayerId, f32)> = None;
+                for id in player_ids {
+                    let d = (position - ctx.player_position(id).unwrap()).norm();
+                    if closest.is_none() || closest.unwrap().1 > d {
+                        closest = Some((id, d));
+                    }
+                }
+
+                if let Some((closest_player_id, _)) = closest {
+                    assert!(!ctx.is_catcher(closest_player_id));
+                    ctx.set_catcher(closest_player_id, true);
+                } else {
+                    // If we are here, this should mean that nobody is alive
+                    for (id, player) in ctx.players.iter() {
+                        assert!(*id == player_id || player.entity.is_none());
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_integrity(&self, ctx: &mut ModeContext) {
+        // When we have at least one player that is alive, there should be exactly one catcher
+        let alive_players = ctx.alive_players();
+        let num_catchers = alive_players.iter()
+                                        .filter(|&&id| ctx.is_catcher(id))
+                                        .count();
+
+        if !alive_players.is_empty() {
+            assert!(num_catchers == 1, "There should be exactly one catcher!");
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use ecs;
+use na::Vec2;
+
+use shared::{NEUTRAL_PLAYER_ID, PlayerId, DeathReason};
+
+use modes::{GameMode, ModeContext};
+
+/// Free-for-all: there is no catcher, players score by killing each other with items
+pub struct DeathmatchMode {
+    frag_limit: Option<u32>,
+}
+
+impl DeathmatchMode {
+    pub fn new(frag_limit: Option<u32>) -> DeathmatchMode {
+        DeathmatchMode {
+            frag_limit: frag_limit,
+        }
+    }
+}
+
+impl GameMode for DeathmatchMode {
+    fn name(&self) -> &'static str {
+        "deathmatch"
+    }
+
+    fn score_limit(&self) -> Option<u32> {
+        self.frag_limit
+    }
+
+    fn credits_ball_owners(&self) -> bool {
+        true
+    }
+
+    fn on_spawn(&mut self, _player_id: PlayerId, entity: ecs::Entity, ctx: &mut ModeContext) {
+        // Without a weapon, nobody could score until the first item spawns
+        let weapon = ctx.world.services.item_defs.create("weapon");
+        ctx.world.with_entity_data(&entity, |e, c| {
+            c.player_state[e].is_catcher = false;
+            c.player_state[e].equip(0, weapon);
+        });
+    }
+
+    fn on_death(&mut self, player_id: PlayerId, _position: Vec2<f32>,
+                responsible_player_id: PlayerId, reason: DeathReason,
+                ctx: &mut ModeContext) {
+        ctx.players.get_mut(&player_id).unwrap().info.stats.deaths += 1;
+
+        if responsible_player_id == NEUTRAL_PLAYER_ID {
+            return;
+        }
+
+        let responsible_player = ctx.players.get_mut(&responsible_player_id).unwrap();
+        if responsible_player_id == player_id {
+            // Killing yourself with your own frags costs a point
+            responsible_player.info.stats.score =
+                responsible_player.info.stats.score.saturating_sub(1);
+        } else {
+            match reason {
+                DeathReason::Projectile | DeathReason::BouncyBall =>
+                    responsible_player.info.stats.score += 1,
+                DeathReason::Caught => (),
+            }
+        }
+    }
+
+    fn check_integrity(&self, ctx: &mut ModeContext) {
+        for player_id in ctx.alive_players() {
+            assert!(!ctx.is_catcher(player_id), "There are no catchers in deathmatch!");
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use ecs;
+use rand::Rng;
+use na::Vec2;
+
+use shared::{PlayerId, DeathReason};
+use shared::map::Map;
+use shared::net_components::ComponentType;
+
+use entities;
+use modes::{GameMode, ModeContext, ModeSnapshot, CatchMode};
+
+/// Players score by being alone in the hill zone. The hill moves between the `hill` objects of
+/// the map over time. The catcher rules still apply, but kills don't give any points.
+pub struct KingOfTheHillMode {
+    catch: CatchMode,
+
+    // Position and size of every hill on the map
+    hills: Vec<(Vec2<f32>, Vec2<f32>)>,
+    current_hill: usize,
+
+    zone: Option<ecs::Entity>,
+
+    // The hill moves after this many seconds
+    move_s: f32,
+    move_timer_s: f32,
+
+    // Time that the current owner has held the zone since their last point
+    hold_timer_s: f32,
+}
+
+impl KingOfTheHillMode {
+    pub fn new(move_s: f32) -> KingOfTheHillMode {
+        KingOfTheHillMode {
+            catch: CatchMode::without_scoring(),
+            hills: Vec::new(),
+            current_hill: 0,
+            zone: None,
+            move_s: move_s,
+            move_timer_s: 0.0,
+            hold_timer_s: 0.0,
+        }
+    }
+
+    /// Position and size of the `hill` objects of a map
+    fn find_hills(map: &Map) -> Vec<(Vec2<f32>, Vec2<f32>)> {
+        map.objects.iter()
+           .filter(|object| &object.type_str == "hill")
+           .map(|object| (Vec2::new(object.x, object.y), Vec2::new(object.width, object.height)))
+           .collect()
+    }
+
+    fn move_zone(&mut self, hill: usize, ctx: &mut ModeContext) {
+        let zone = self.zone.unwrap();
+        let (position, size) = self.hills[hill];
+
+        self.current_hill = hill;
+        self.move_timer_s = 0.0;
+        self.hold_timer_s = 0.0;
+
+        ctx.world.with_entity_data(&zone, |e, c| {
+            c.position[e].p = position;
+            c.zone_state[e].size = size;
+            c.zone_state[e].owner = None;
+            c.zone_state[e].contested = false;
+            c.zone_state[e].progress = 0.0;
+
+            // Don't let clients interpolate between two hills
+            c.server_net_entity[e].force(ComponentType::Position);
+        });
+    }
+
+    /// Players that are alive and inside of the zone
+    fn occupants(&self, ctx: &mut ModeContext) -> Vec<PlayerId> {
+        let zone = self.zone.unwrap();
+        let (position, zone_state) = ctx.world.with_entity_data(&zone, |e, c| {
+            (c.position[e].p, c.zone_state[e].clone())
+        }).unwrap();
+
+        ctx.alive_players().into_iter()
+           .filter(|&id| zone_state.contains(position, ctx.player_position(id).unwrap()))
+           .collect()
+    }
+}
+
+impl GameMode for KingOfTheHillMode {
+    fn name(&self) -> &'static str {
+        "king_of_the_hill"
+    }
+
+    fn reset_scores(&mut self) {
+        self.hold_timer_s = 0.0;
+    }
+
+    fn on_map_load(&mut self, map: &Map, ctx: &mut ModeContext) {
+        self.hills = KingOfTheHillMode::find_hills(map);
+
+        if self.hills.is_empty() {
+            warn!("map has no hill objects, nobody will be able to score");
+            self.zone = None;
+            return;
+        }
+
+        self.zone = Some(entities::build_net("zone", 0, &mut ctx.world.data));
+        ctx.world.flush_queue();
+
+        let hill = ctx.world.services.rng.gen_range(0, self.hills.len());
+        self.move_zone(hill, ctx);
+    }
+
+    fn snapshot(&self, ctx: &mut ModeContext) -> ModeSnapshot {
+        ModeSnapshot::KingOfTheHill {
+            current_hill: self.current_hill,
+            zone: self.zone.and_then(|zone| {
+                ctx.world.with_entity_data(&zone, |e, c| c.net_entity[e].id)
+            }),
+            move_timer_s: self.move_timer_s,
+            hold_timer_s: self.hold_timer_s,
+        }
+    }
+
+    fn restore(&mut self, map: &Map, snapshot: &ModeSnapshot, ctx: &mut ModeContext)
+               -> Result<(), String> {
+        let (current_hill, zone, move_timer_s, hold_timer_s) = match *snapshot {
+            ModeSnapshot::KingOfTheHill { current_hill, zone, move_timer_s, hold_timer_s } =>
+                (current_hill, zone, move_timer_s, hold_timer_s),
+            _ => return Err("Snapshot does not match game mode king_of_the_hill".to_string()),
+        };
+
+        let zone = match zone {
+            Some(id) => match ctx.world.services.net_entities.get(id) {
+                Some(zone) => Some(zone),
+                None => return Err(format!("Snapshot has no zone entity {}", id)),
+            },
+            None => None,
+        };
+
+        self.hills = KingOfTheHillMode::find_hills(map);
+        if current_hill >= self.hills.len() && !self.hills.is_empty() {
+            return Err(format!("Snapshot has hill {}, but the map only has {}",
+                               current_hill, self.hills.len()));
+        }
+
+        self.zone = zone;
+        self.current_hill = current_hill;
+        self.move_timer_s = move_timer_s;
+        self.hold_timer_s = hold_timer_s;
+
+        Ok(())
+    }
+
+    fn on_leave(&mut self, player_id: PlayerId, ctx: &mut ModeContext) {
+        self.catch.on_leave(player_id, ctx);
+    }
+
+    fn on_spawn(&mut self, player_id: PlayerId, entity: ecs::Entity, ctx: &mut ModeContext) {
+        self.catch.on_spawn(player_id, entity, ctx);
+    }
+
+    fn on_catch(&mut self, catcher_id: PlayerId, catchee_id: PlayerId, position: Vec2<f32>,
+                ctx: &mut ModeContext) {
+        self.catch.on_catch(catcher_id, catchee_id, position, ctx);
+    }
+
+    fn on_death(&mut self, player_id: PlayerId, position: Vec2<f32>,
+                responsible_player_id: PlayerId, reason: DeathReason,
+                ctx: &mut ModeContext) {
+        self.catch.on_death(player_id, position, responsible_player_id, reason, ctx);
+    }
+
+    fn tick(&mut self, ctx: &mut ModeContext) {
+        let zone = match self.zone {
+            Some(zone) => zone,
+            None => return,
+        };
+        let dur_s = ctx.world.services.tick_dur_s;
+
+        // Move the hill somewhere else from time to time
+        self.move_timer_s += dur_s;
+        if self.move_timer_s >= self.move_s && self.hills.len() > 1 {
+            let offset = ctx.world.services.rng.gen_range(1, self.hills.len());
+            let hill = (self.current_hill + offset) % self.hills.len();
+            self.move_zone(hill, ctx);
+        }
+
+        let occupants = self.occupants(ctx);
+        let previous_owner = ctx.world.with_entity_data(&zone, |e, c| {
+            c.zone_state[e].owner
+        }).unwrap();
+
+        let owner = if occupants.len() == 1 { Some(occupants[0]) } else { None };
+
+        // Score a point for every second of being alone in the zone
+        if owner.is_some() && owner == previous_owner {
+            self.hold_timer_s += dur_s;
+            if self.hold_timer_s >= 1.0 {
+                self.hold_timer_s -= 1.0;
+                ctx.players.get_mut(&owner.unwrap()).unwrap().info.stats.score += 1;
+            }
+        } else {
+            self.hold_timer_s = 0.0;
+        }
+
+        let progress = self.hold_timer_s;
+        ctx.world.with_entity_data(&zone, |e, c| {
+            c.zone_state[e].owner = owner;
+            c.zone_state[e].contested = occupants.len() > 1;
+            c.zone_state[e].progress = progress;
+        });
+    }
+
+    fn check_integrity(&self, ctx: &mut ModeContext) {
+        self.catch.check_integrity(ctx);
+    }
+}
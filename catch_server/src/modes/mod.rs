@@ -0,0 +1,214 @@
+pub mod teams;
+pub mod catch;
+pub mod deathmatch;
+pub mod team_catch;
+pub mod king_of_the_hill;
+pub mod tag;
+
+use ecs;
+use na::Vec2;
+
+use shared::{EntityId, PlayerId, TeamId, DeathReason, GameEvent};
+use shared::services::HasEvents;
+use shared::map::Map;
+use shared::util::FixedHashMap;
+
+use state::Player;
+use systems::Systems;
+use match_state::MatchConfig;
+
+pub use self::catch::CatchMode;
+pub use self::deathmatch::DeathmatchMode;
+pub use self::team_catch::TeamCatchMode;
+pub use self::king_of_the_hill::KingOfTheHillMode;
+pub use self::tag::TagMode;
+
+/// The parts of the game state that game modes can access in their hooks
+pub struct ModeContext<'a> {
+    pub players: &'a mut FixedHashMap<PlayerId, Player>,
+    pub world: &'a mut ecs::World<Systems>,
+}
+
+impl<'a> ModeContext<'a> {
+    pub fn new(players: &'a mut FixedHashMap<PlayerId, Player>,
+               world: &'a mut ecs::World<Systems>) -> ModeContext<'a> {
+        ModeContext {
+            players: players,
+            world: world,
+        }
+    }
+
+    /// Ids of players that currently control an entity
+    pub fn alive_players(&self) -> Vec<PlayerId> {
+        self.players.iter()
+            .filter(|&(_, player)| player.alive())
+            .map(|(&id, _)| id)
+            .collect()
+    }
+
+    pub fn is_catcher(&mut self, player_id: PlayerId) -> bool {
+        match self.players[&player_id].entity {
+            Some(entity) =>
+                self.world.with_entity_data(&entity, |e, c| c.player_state[e].is_catcher)
+                    .unwrap(),
+            None => false
+        }
+    }
+
+    pub fn set_catcher(&mut self, player_id: PlayerId, is_catcher: bool) {
+        let entity = self.players[&player_id].entity.unwrap();
+        self.world.with_entity_data(&entity, |e, c| {
+            c.player_state[e].is_catcher = is_catcher;
+        });
+    }
+
+    pub fn current_catcher(&mut self) -> Option<PlayerId> {
+        for player_id in self.alive_players() {
+            if self.is_catcher(player_id) {
+                return Some(player_id);
+            }
+        }
+        None
+    }
+
+    pub fn team(&self, player_id: PlayerId) -> Option<TeamId> {
+        self.players[&player_id].info.team
+    }
+
+    /// Moves a player into a team and lets the clients know
+    pub fn set_team(&mut self, player_id: PlayerId, team: Option<TeamId>) {
+        self.players.get_mut(&player_id).unwrap().info.team = team;
+
+        match team {
+            Some(team) => self.world.services.player_teams.insert(player_id, team),
+            None => self.world.services.player_teams.remove(&player_id),
+        };
+
+        self.world.services.add_event(&GameEvent::PlayerChangeTeam(player_id, team));
+    }
+
+    pub fn player_position(&mut self, player_id: PlayerId) -> Option<Vec2<f32>> {
+        match self.players[&player_id].entity {
+            Some(entity) =>
+                self.world.with_entity_data(&entity, |e, c| c.position[e].p),
+            None => None
+        }
+    }
+}
+
+/// Internal state of a game mode, stored in snapshots. Entities are referred to by their net
+/// ids. Catchers are not stored here, since they are part of the player entities.
+#[derive(Clone, Debug, RustcEncodable, RustcDecodable)]
+pub enum ModeSnapshot {
+    Stateless,
+    TeamCatch {
+        team_scores: Vec<u32>,
+        catcher_team: TeamId,
+        rotate_timer_s: f32,
+    },
+    KingOfTheHill {
+        current_hill: usize,
+        zone: Option<EntityId>,
+        move_timer_s: f32,
+        hold_timer_s: f32,
+    },
+    Tag {
+        last_tag: Option<(PlayerId, PlayerId, f32)>,
+        score_timers_s: Vec<(PlayerId, f32)>,
+    },
+}
+
+/// Defines the rules of a game, i.e. how players score and who is the catcher.
+/// The hooks are called by `GameState` in the scope of a tick, so they may queue up events.
+pub trait GameMode {
+    fn name(&self) -> &'static str;
+
+    /// Score at which a match ends. If None, the limit of the match config is used.
+    fn score_limit(&self) -> Option<u32> {
+        None
+    }
+
+    /// Are players responsible for the deaths caused by the balls they spawned? Otherwise,
+    /// balls are neutral like the ones placed on the map.
+    fn credits_ball_owners(&self) -> bool {
+        false
+    }
+
+    /// Scores of the teams, if the mode has teams
+    fn team_scores(&self) -> Option<Vec<(TeamId, u32)>> {
+        None
+    }
+
+    /// A new match starts, resetting all scores
+    fn reset_scores(&mut self) {
+    }
+
+    /// A new player joined the game, or the mode has just been activated. The player does not
+    /// necessarily control an entity.
+    fn on_join(&mut self, _player_id: PlayerId, _ctx: &mut ModeContext) {
+    }
+
+    /// A client has just been sent the game state, either after joining or after reconnecting
+    /// to a restored player
+    fn on_replicate(&mut self, _player_id: PlayerId, _ctx: &mut ModeContext) {
+    }
+
+    /// The entities of a new map have just been created
+    fn on_map_load(&mut self, _map: &Map, _ctx: &mut ModeContext) {
+    }
+
+    /// Internal state of the mode, to be stored in a snapshot
+    fn snapshot(&self, _ctx: &mut ModeContext) -> ModeSnapshot {
+        ModeSnapshot::Stateless
+    }
+
+    /// Continues with the state of a snapshot. Called instead of `on_map_load` once the
+    /// entities and players of the snapshot have been restored.
+    fn restore(&mut self, _map: &Map, snapshot: &ModeSnapshot, _ctx: &mut ModeContext)
+               -> Result<(), String> {
+        match *snapshot {
+            ModeSnapshot::Stateless => Ok(()),
+            _ => Err(format!("Snapshot does not match game mode {}", self.name())),
+        }
+    }
+
+    /// A player is about to be removed. Its entity, if any, still exists.
+    fn on_leave(&mut self, _player_id: PlayerId, _ctx: &mut ModeContext) {
+    }
+
+    /// A player has just been given a new entity
+    fn on_spawn(&mut self, _player_id: PlayerId, _entity: ecs::Entity,
+                _ctx: &mut ModeContext) {
+    }
+
+    /// A player is about to die. Its entity still exists and is removed afterwards.
+    fn on_death(&mut self, _player_id: PlayerId, _position: Vec2<f32>,
+                _responsible_player_id: PlayerId, _reason: DeathReason,
+                _ctx: &mut ModeContext) {
+    }
+
+    /// The catcher touched a vulnerable player
+    fn on_catch(&mut self, _catcher_id: PlayerId, _catchee_id: PlayerId, _position: Vec2<f32>,
+                _ctx: &mut ModeContext) {
+    }
+
+    /// Called once per tick after the entities have been advanced
+    fn tick(&mut self, _ctx: &mut ModeContext) {
+    }
+
+    /// Checks invariants of the mode at the start of every tick
+    fn check_integrity(&self, _ctx: &mut ModeContext) {
+    }
+}
+
+/// Creates a game mode by its name
+pub fn create(name: &str, config: &MatchConfig) -> Option<Box<GameMode>> {
+    match name {
+        "catch" => Some(Box::new(CatchMode::new())),
+        "deathmatch" => Some(Box::new(DeathmatchMode::new(config.frag_limit))),
+        "team_catch" => Some(Box::new(TeamCatchMode::new(config.team_rotate_s))),
+        "king_of_the_hill" => Some(Box::new(KingOfTheHillMode::new(config.hill_move_s))),
+        "tag" => Some(Box::new(TagMode::new(config.tag_immunity_s))),
+        _ => None
+    }
+}
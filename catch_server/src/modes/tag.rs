@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+
+use ecs;
+use na::Vec2;
+
+use shared::{PlayerId, DeathReason, GameEvent};
+use shared::services::HasEvents;
+use shared::map::Map;
+
+use modes::{GameMode, ModeContext, ModeSnapshot, CatchMode};
+
+/// Classic tag: touching a player passes on the catcher role. Everyone except for the catcher
+/// earns a point per second.
+pub struct TagMode {
+    // The catcher rules are used for choosing a catcher on spawns, deaths and disconnects
+    catch: CatchMode,
+
+    // The catcher that clients know about
+    catcher: Option<PlayerId>,
+
+    // The previous catcher can't be tagged back by the new one for this long
+    immunity_s: f32,
+
+    // Last tag (previous catcher, new catcher) and the time since it happened
+    last_tag: Option<(PlayerId, PlayerId, f32)>,
+
+    // Time since the last point for every player that isn't the catcher
+    score_timers_s: HashMap<PlayerId, f32>,
+}
+
+impl TagMode {
+    pub fn new(immunity_s: f32) -> TagMode {
+        TagMode {
+            catch: CatchMode::without_scoring(),
+            catcher: None,
+            immunity_s: immunity_s,
+            last_tag: None,
+            score_timers_s: HashMap::new(),
+        }
+    }
+
+    fn is_tag_back(&self, catcher_id: PlayerId, catchee_id: PlayerId) -> bool {
+        match self.last_tag {
+            Some((previous_catcher, new_catcher, time_s)) =>
+                previous_catcher == catchee_id && new_catcher == catcher_id &&
+                time_s < self.immunity_s,
+            None => false
+        }
+    }
+}
+
+impl GameMode for TagMode {
+    fn name(&self) -> &'static str {
+        "tag"
+    }
+
+    fn reset_scores(&mut self) {
+        self.score_timers_s.clear();
+        self.last_tag = None;
+    }
+
+    fn on_map_load(&mut self, _map: &Map, ctx: &mut ModeContext) {
+        // All players have lost their entities, so there is no catcher until the next spawn
+        self.catcher = None;
+        self.last_tag = None;
+        self.score_timers_s.clear();
+        ctx.world.services.add_event(&GameEvent::CatcherChange(None));
+    }
+
+    fn on_replicate(&mut self, player_id: PlayerId, ctx: &mut ModeContext) {
+        // Let the new player know who is it
+        ctx.world.services.add_player_event(player_id, &GameEvent::CatcherChange(self.catcher));
+    }
+
+    fn snapshot(&self, _ctx: &mut ModeContext) -> ModeSnapshot {
+        let mut score_timers_s = self.score_timers_s.iter()
+                                     .map(|(&id, &time_s)| (id, time_s))
+                                     .collect::<Vec<_>>();
+        score_timers_s.sort_by(|a, b| a.0.cmp(&b.0));
+
+        ModeSnapshot::Tag {
+            last_tag: self.last_tag,
+            score_timers_s: score_timers_s,
+        }
+    }
+
+    fn restore(&mut self, _map: &Map, snapshot: &ModeSnapshot, ctx: &mut ModeContext)
+               -> Result<(), String> {
+        match *snapshot {
+            ModeSnapshot::Tag { last_tag, ref score_timers_s } => {
+                self.last_tag = last_tag;
+                self.score_timers_s = score_timers_s.iter().cloned().collect();
+            }
+            _ => return Err("Snapshot does not match game mode tag".to_string()),
+        }
+
+        // The catcher is stored in the player entities
+        self.catcher = ctx.current_catcher();
+        Ok(())
+    }
+
+    fn on_leave(&mut self, player_id: PlayerId, ctx: &mut ModeContext) {
+        self.score_timers_s.remove(&player_id);
+        self.catch.on_leave(player_id, ctx);
+    }
+
+    fn on_spawn(&mut self, player_id: PlayerId, entity: ecs::Entity, ctx: &mut ModeContext) {
+        self.catch.on_spawn(player_id, entity, ctx);
+    }
+
+    fn on_catch(&mut self, catcher_id: PlayerId, catchee_id: PlayerId, position: Vec2<f32>,
+                ctx: &mut ModeContext) {
+        // Multiple catches may happen in one tick, but only the current catcher can tag
+        if !ctx.is_catcher(catcher_id) || ctx.is_catcher(catchee_id) ||
+           self.is_tag_back(catcher_id, catchee_id) {
+            return;
+        }
+
+        ctx.set_catcher(catcher_id, false);
+        ctx.set_catcher(catchee_id, true);
+        self.last_tag = Some((catcher_id, catchee_id, 0.0));
+
+        ctx.world.services.add_event(&GameEvent::PlayerTagged {
+            player_id: catchee_id,
+            position: position,
+            responsible_player_id: catcher_id,
+        });
+    }
+
+    fn on_death(&mut self, player_id: PlayerId, position: Vec2<f32>,
+                responsible_player_id: PlayerId, reason: DeathReason,
+                ctx: &mut ModeContext) {
+        self.score_timers_s.remove(&player_id);
+        self.catch.on_death(player_id, position, responsible_player_id, reason, ctx);
+    }
+
+    fn tick(&mut self, ctx: &mut ModeContext) {
+        let dur_s = ctx.world.services.tick_dur_s;
+
+        if let Some((_, _, ref mut time_s)) = self.last_tag {
+            *time_s += dur_s;
+        }
+
+        // Catchers also change on deaths and disconnects, so just look for any change here
+        let catcher = ctx.current_catcher();
+        if catcher != self.catcher {
+            self.catcher = catcher;
+            ctx.world.services.add_event(&GameEvent::CatcherChange(catcher));
+
+            if let Some(catcher) = catcher {
+                self.score_timers_s.remove(&catcher);
+            }
+        }
+
+        // A point for every second of not being it
+        for player_id in ctx.alive_players() {
+            if Some(player_id) == catcher {
+                continue;
+            }
+
+            let time_s = self.score_timers_s.entry(player_id).or_insert(0.0);
+            *time_s += dur_s;
+            if *time_s >= 1.0 {
+                *time_s -= 1.0;
+                ctx.players.get_mut(&player_id).unwrap().info.stats.score += 1;
+            }
+        }
+    }
+
+    fn check_integrity(&self, ctx: &mut ModeContext) {
+        self.catch.check_integrity(ctx);
+    }
+}
@@ -0,0 +1,144 @@
+use ecs;
+use na::Vec2;
+
+use shared::{NEUTRAL_PLAYER_ID, PlayerId, TeamId, DeathReason, GameEvent};
+use shared::player::team_color;
+use shared::services::HasEvents;
+use shared::map::Map;
+
+use modes::{GameMode, ModeContext, ModeSnapshot};
+use modes::teams::Teams;
+
+const NUM_TEAMS: usize = 2;
+
+/// Two teams take turns at being the catchers. Every member of the catcher team can catch the
+/// players of the other team.
+pub struct TeamCatchMode {
+    teams: Teams,
+    catcher_team: TeamId,
+
+    // The catcher team changes after this many seconds
+    rotate_s: f32,
+    rotate_timer_s: f32,
+}
+
+impl TeamCatchMode {
+    pub fn new(rotate_s: f32) -> TeamCatchMode {
+        TeamCatchMode {
+            teams: Teams::new(NUM_TEAMS),
+            catcher_team: 0,
+            rotate_s: rotate_s,
+            rotate_timer_s: 0.0,
+        }
+    }
+
+    fn rotate_catcher_team(&mut self, ctx: &mut ModeContext) {
+        self.catcher_team = (self.catcher_team + 1) % self.teams.num_teams() as TeamId;
+        self.rotate_timer_s = 0.0;
+
+        info!("team {} is now catching", self.catcher_team);
+
+        for player_id in ctx.alive_players() {
+            let is_catcher = ctx.team(player_id) == Some(self.catcher_team);
+            ctx.set_catcher(player_id, is_catcher);
+        }
+    }
+}
+
+impl GameMode for TeamCatchMode {
+    fn name(&self) -> &'static str {
+        "team_catch"
+    }
+
+    fn team_scores(&self) -> Option<Vec<(TeamId, u32)>> {
+        Some(self.teams.scores())
+    }
+
+    fn reset_scores(&mut self) {
+        self.teams.reset_scores();
+        self.rotate_timer_s = 0.0;
+    }
+
+    fn on_join(&mut self, player_id: PlayerId, ctx: &mut ModeContext) {
+        self.teams.assign(player_id, ctx);
+    }
+
+    fn snapshot(&self, _ctx: &mut ModeContext) -> ModeSnapshot {
+        ModeSnapshot::TeamCatch {
+            team_scores: self.teams.scores().into_iter().map(|(_, score)| score).collect(),
+            catcher_team: self.catcher_team,
+            rotate_timer_s: self.rotate_timer_s,
+        }
+    }
+
+    fn restore(&mut self, _map: &Map, snapshot: &ModeSnapshot, _ctx: &mut ModeContext)
+               -> Result<(), String> {
+        match *snapshot {
+            ModeSnapshot::TeamCatch { ref team_scores, catcher_team, rotate_timer_s } => {
+                try!(self.teams.set_scores(team_scores));
+                self.catcher_team = catcher_team;
+                self.rotate_timer_s = rotate_timer_s;
+                Ok(())
+            }
+            _ => Err("Snapshot does not match game mode team_catch".to_string()),
+        }
+    }
+
+    fn on_spawn(&mut self, player_id: PlayerId, entity: ecs::Entity, ctx: &mut ModeContext) {
+        let team = ctx.team(player_id).unwrap();
+        let is_catcher = team == self.catcher_team;
+
+        ctx.world.with_entity_data(&entity, |e, c| {
+            c.player_state[e].color = team_color(team);
+            c.player_state[e].is_catcher = is_catcher;
+        });
+    }
+
+    fn on_catch(&mut self, catcher_id: PlayerId, catchee_id: PlayerId, position: Vec2<f32>,
+                ctx: &mut ModeContext) {
+        ctx.world.services.add_event(&GameEvent::PlayerDied {
+            player_id: catchee_id,
+            position: position,
+            responsible_player_id: catcher_id,
+            reason: DeathReason::Caught,
+        });
+    }
+
+    fn on_death(&mut self, player_id: PlayerId, _position: Vec2<f32>,
+                responsible_player_id: PlayerId, reason: DeathReason,
+                ctx: &mut ModeContext) {
+        ctx.players.get_mut(&player_id).unwrap().info.stats.deaths += 1;
+        ctx.set_catcher(player_id, false);
+
+        if responsible_player_id == NEUTRAL_PLAYER_ID || responsible_player_id == player_id ||
+           ctx.team(responsible_player_id) == ctx.team(player_id) {
+            // No points for accidents and friendly fire
+            return;
+        }
+
+        let responsible_team = ctx.team(responsible_player_id).unwrap();
+        let score = match reason {
+            DeathReason::Caught => 10,
+            _ => 1,
+        };
+        ctx.players.get_mut(&responsible_player_id).unwrap().info.stats.score += score;
+        self.teams.add_score(responsible_team, score);
+    }
+
+    fn tick(&mut self, ctx: &mut ModeContext) {
+        self.rotate_timer_s += ctx.world.services.tick_dur_s;
+        if self.rotate_timer_s >= self.rotate_s {
+            self.rotate_catcher_team(ctx);
+        }
+
+        self.teams.balance(ctx);
+    }
+
+    fn check_integrity(&self, ctx: &mut ModeContext) {
+        for player_id in ctx.alive_players() {
+            let in_catcher_team = ctx.team(player_id) == Some(self.catcher_team);
+            assert!(ctx.is_catcher(player_id) == in_catcher_team,
+                    "Exactly the members of the catcher team should be catchers!");
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use shared::{PlayerId, TeamId};
+
+use modes::ModeContext;
+
+/// Team assignment and scores for game modes with teams
+pub struct Teams {
+    scores: Vec<u32>,
+}
+
+impl Teams {
+    pub fn new(num_teams: usize) -> Teams {
+        assert!(num_teams > 0);
+
+        Teams {
+            scores: vec![0; num_teams],
+        }
+    }
+
+    pub fn num_teams(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn scores(&self) -> Vec<(TeamId, u32)> {
+        self.scores.iter().enumerate().map(|(team, &score)| (team as TeamId, score)).collect()
+    }
+
+    pub fn add_score(&mut self, team: TeamId, score: u32) {
+        self.scores[team as usize] += score;
+    }
+
+    /// Replaces the scores, e.g. with the ones stored in a snapshot
+    pub fn set_scores(&mut self, scores: &[u32]) -> Result<(), String> {
+        if scores.len() != self.scores.len() {
+            return Err(format!("Expected scores of {} teams, got {}",
+                               self.scores.len(), scores.len()));
+        }
+
+        self.scores = scores.to_vec();
+        Ok(())
+    }
+
+    pub fn reset_scores(&mut self) {
+        for score in self.scores.iter_mut() {
+            *score = 0;
+        }
+    }
+
+    pub fn members(&self, team: TeamId, ctx: &ModeContext) -> Vec<PlayerId> {
+        ctx.players.iter()
+           .filter(|&(_, player)| player.info.team == Some(team))
+           .map(|(&id, _)| id)
+           .collect()
+    }
+
+    /// Returns the team with the least members. Ties go to the team with the lower score.
+    pub fn smallest_team(&self, ctx: &ModeContext) -> TeamId {
+        (0..self.num_teams() as TeamId)
+            .min_by_key(|&team| (self.members(team, ctx).len(), self.scores[team as usize]))
+            .unwrap()
+    }
+
+    /// Puts a player in the smallest team
+    pub fn assign(&self, player_id: PlayerId, ctx: &mut ModeContext) {
+        let team = self.smallest_team(ctx);
+        ctx.set_team(player_id, Some(team));
+    }
+
+    /// If the teams differ in size by more than one, moves a dead player from the largest to
+    /// the smallest team. Players that are alive are never moved, so that the mode does not
+    /// need to update their entities.
+    pub fn balance(&self, ctx: &mut ModeContext) {
+        let smallest = self.smallest_team(ctx);
+        let largest = (0..self.num_teams() as TeamId)
+                          .max_by_key(|&team| self.members(team, ctx).len())
+                          .unwrap();
+
+        if self.members(largest, ctx).len() <= self.members(smallest, ctx).len() + 1 {
+            return;
+        }
+
+        let dead_player = self.members(largest, ctx).into_iter()
+                              .find(|id| !ctx.players[id].alive());
+        if let Some(player_id) = dead_player {
+            info!("balancing teams: moving player {} from team {} to team {}",
+                  player_id, largest, smallest);
+            ctx.set_team(player_id, Some(smallest));
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use std::fs::File;
+use std::io::BufWriter;
+
+use shared::{NEUTRAL_PLAYER_ID, TickNumber, Tick};
+use shared::replay::{ReplayHeader, ReplayKeyframe, ReplayWriter};
+
+use state::GameState;
+
+/// Writes every tick of the game state to a replay file, as seen by a spectator
+pub struct Recorder {
+    path: String,
+    writer: ReplayWriter<BufWriter<File>>,
+}
+
+impl Recorder {
+    pub fn create(path: &str, game_state: &GameState, keyframe_interval: TickNumber)
+                  -> Result<Recorder, String> {
+        info!("recording replay to {}", path);
+
+        let file = try!(File::create(path).map_err(|e| e.to_string()));
+        let header = ReplayHeader {
+            game_info: game_state.game_info().clone(),
+            seed: game_state.seed(),
+            keyframe_interval: keyframe_interval,
+        };
+        let writer = try!(ReplayWriter::new(BufWriter::new(file), &header));
+
+        Ok(Recorder {
+            path: path.to_string(),
+            writer: writer,
+        })
+    }
+
+    /// Records the tick that was just run by the game state
+    pub fn record(&mut self, game_state: &GameState) -> Result<(), String> {
+        let mut tick = Tick::new(game_state.tick_number());
+        tick.events = game_state.spectator_events().to_vec();
+        game_state.world.systems.net_entity_system
+            .store_in_tick_state(NEUTRAL_PLAYER_ID, &mut tick.state, &game_state.world.data);
+
+        if self.writer.needs_keyframe() {
+            let game_info = game_state.game_info();
+            let keyframe = ReplayKeyframe {
+                map_name: game_info.map_name.clone(),
+                ticks_per_second: game_info.ticks_per_second,
+                phase: game_state.match_phase(),
+                phase_duration_s: game_state.match_time_left_s(),
+                players: game_state.player_list(),
+                entities: game_state.world.systems.net_entity_system
+                                    .entity_list(&game_state.world.data),
+                tick: tick,
+            };
+            self.writer.write_keyframe(&keyframe)
+        } else {
+            self.writer.write_tick(&tick)
+        }
+    }
+
+    /// Writes the index of the replay. Dropping the recorder does the same, but without
+    /// reporting errors.
+    pub fn finish(mut self) -> Result<(), String> {
+        info!("finishing replay {}", self.path);
+        self.writer.finish()
+    }
+}
@@ -2,10 +2,23 @@ use std::collections::HashMap;
 
 use ecs::ServiceManager;
 
-use shared::{EntityId, EntityTypeId, EntityTypes, TickNumber, PlayerId, GameEvent};
+use rand::{SeedableRng, XorShiftRng};
+use na::Vec2;
+
+use shared::{EntityId, EntityTypeId, EntityTypes, TickNumber, PlayerId, TeamId, GameEvent, Item};
 use shared::services::HasEvents;
 use shared::entities::NetEntities;
 
+use item_defs::ItemDefs;
+
+/// A catcher touched a vulnerable player. What happens then is decided by the game mode.
+#[derive(Clone, Debug)]
+pub struct Catch {
+    pub catcher_id: PlayerId,
+    pub catchee_id: PlayerId,
+    pub position: Vec2<f32>,
+}
+
 // State that can be accessed mutably by systems
 pub struct Services {
     // List of entity types by name
@@ -21,9 +34,34 @@ pub struct Services {
     // `next_player_events`.  Each event in `next_events` is also stored for each player here.
     pub next_player_events: HashMap<PlayerId, Vec<GameEvent>>,
 
+    // Catches that happened in a tick, to be handled by the game mode
+    pub next_catches: Vec<Catch>,
+
+    // Items used by players in a tick, for keeping statistics
+    pub next_item_uses: Vec<(PlayerId, Item)>,
+
+    // Team of each player, if the game mode has teams
+    pub player_teams: HashMap<PlayerId, TeamId>,
+
+    // Can players hurt members of their own team?
+    pub friendly_fire: bool,
+
+    // Are players responsible for the deaths caused by the balls they spawned? Set by the
+    // game mode.
+    pub credit_ball_owners: bool,
+
+    // Do item spawns create new items?
+    pub items_enabled: bool,
+
+    // Stats of the items
+    pub item_defs: ItemDefs,
+
     // Net entities
     pub net_entities: NetEntities,
 
+    // Every random decision in the game goes through this, so that runs can be reproduced
+    pub rng: XorShiftRng,
+
     // Counter for creating net entities
     entity_id_counter: EntityId,
 }
@@ -44,13 +82,21 @@ impl HasEvents for Services {
 }
 
 impl Services {
-    pub fn new(entity_types: EntityTypes) -> Services {
+    pub fn new(entity_types: EntityTypes, item_defs: ItemDefs, seed: u32) -> Services {
         Services {
             entity_types: entity_types,
             tick_dur_s: 0.0, // the correct duration is set by GameState::tick
             next_events: Vec::new(),
             next_player_events: HashMap::new(),
+            next_catches: Vec::new(),
+            next_item_uses: Vec::new(),
+            player_teams: HashMap::new(),
+            friendly_fire: false,
+            credit_ball_owners: false,
+            items_enabled: true,
+            item_defs: item_defs,
             net_entities: NetEntities::default(),
+            rng: Services::create_rng(seed),
             entity_id_counter: 0,
         }
     }
@@ -60,6 +106,8 @@ impl Services {
                             _number: TickNumber,
                             player_ids: T) {
         assert!(self.next_events.is_empty());
+        assert!(self.next_catches.is_empty());
+        assert!(self.next_item_uses.is_empty());
 
         let mut next_player_events = HashMap::new();
         for player_id in player_ids {
@@ -76,17 +124,54 @@ impl Services {
         self.next_player_events = next_player_events;
     }
 
+    fn create_rng(seed: u32) -> XorShiftRng {
+        // XorShift must not be seeded with only zeros
+        XorShiftRng::from_seed([seed, seed ^ 0x9e3779b9, 0x2545f491, 0x6c8e9cf5])
+    }
+
     /// Allocates a new net entity id. Used by `entities::build_net`
     pub fn next_entity_id(&mut self) -> EntityId {
         self.entity_id_counter += 1;
         self.entity_id_counter
     }
 
+    /// The last allocated net entity id, e.g. for storing in snapshots
+    pub fn entity_id_counter(&self) -> EntityId {
+        self.entity_id_counter
+    }
+
+    /// Continues allocating net entity ids after `counter`, after restoring a snapshot
+    pub fn set_entity_id_counter(&mut self, counter: EntityId) {
+        self.entity_id_counter = counter;
+    }
+
+    /// Replaces the random number generator by one created with the given seed
+    pub fn reseed(&mut self, seed: u32) {
+        self.rng = Services::create_rng(seed);
+    }
+
     /// Queue an event only for one specific player
     pub fn add_player_event(&mut self, player_id: PlayerId, event: &GameEvent) {
         self.next_player_events.get_mut(&player_id).unwrap().push(event.clone());
     }
 
+    /// Are two different players in the same team?
+    pub fn same_team(&self, a: PlayerId, b: PlayerId) -> bool {
+        a != b &&
+        match (self.player_teams.get(&a), self.player_teams.get(&b)) {
+            (Some(team_a), Some(team_b)) => team_a == team_b,
+            _ => false
+        }
+    }
+
+    pub fn add_catch(&mut self, catch: Catch) {
+        self.next_catches.push(catch);
+    }
+
+    pub fn add_item_use(&mut self, player_id: PlayerId, item: Item) {
+        self.next_item_uses.push((player_id, item));
+    }
+
     pub fn entity_type_id(&self, type_name: &str) -> EntityTypeId {
         self.entity_types.iter()
             .enumerate()
@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+
+use ecs;
+use ecs::{BuildData, DataHelper};
+
+use bincode::SizeLimit;
+use bincode::rustc_serialize::{encode_into, decode_from};
+
+use shared::{EntityId, EntityTypeId, PlayerId, PlayerInfo, TickNumber, MatchPhase, Tick, Item};
+use shared::net::TimedPlayerInput;
+use shared::net_components::NetComponents;
+
+use components::{Components, LinearVelocity, AngularVelocity, Shape, Projectile, BouncyEnemy,
+                 ItemSpawn, PlayerController, Lifetime, Destructible};
+use services::Services;
+use entities;
+use modes::ModeSnapshot;
+
+/// Needs to be increased whenever the format of snapshots or anything contained in them changes
+pub const SNAPSHOT_VERSION: u32 = 3;
+
+/// Server-side state of a bouncy enemy, with the orbited entity given by its net id
+#[derive(Clone, Debug, RustcEncodable, RustcDecodable)]
+pub struct BouncyEnemySnapshot {
+    pub orbit: Option<EntityId>,
+    pub attract: bool,
+}
+
+/// Server-side state of an item spawn, with the spawned item given by its net id
+#[derive(Clone, Debug, RustcEncodable, RustcDecodable)]
+pub struct ItemSpawnSnapshot {
+    pub spawned_entity: Option<EntityId>,
+    pub cooldown_s: Option<f32>,
+    pub table: Option<String>,
+}
+
+/// Components of a net entity that are not replicated to clients. Components that are always
+/// the same for an entity type, like walls and tags, are recreated when building the entity.
+#[derive(Clone, RustcEncodable, RustcDecodable)]
+pub struct ServerComponents {
+    pub linear_velocity: Option<LinearVelocity>,
+    pub angular_velocity: Option<AngularVelocity>,
+    pub shape: Option<Shape>,
+    pub player_inputs: Option<Vec<TimedPlayerInput>>,
+    pub bouncy_enemy: Option<BouncyEnemySnapshot>,
+    pub item: Option<Item>,
+    pub item_spawn: Option<ItemSpawnSnapshot>,
+    pub projectile: Option<Projectile>,
+    pub lifetime_s: Option<f32>,
+    pub hit_points: Option<f32>,
+}
+
+impl ServerComponents {
+    /// Adds the components to an entity that is being built, replacing the ones that its
+    /// entity type starts with. References to other entities are restored separately.
+    fn build(&self, entity: BuildData<Components>, c: &mut Components) {
+        if let Some(ref linear_velocity) = self.linear_velocity {
+            c.linear_velocity.add(&entity, linear_velocity.clone());
+        }
+        if let Some(ref angular_velocity) = self.angular_velocity {
+            c.angular_velocity.add(&entity, angular_velocity.clone());
+        }
+        if let Some(ref shape) = self.shape {
+            c.shape.add(&entity, shape.clone());
+        }
+        if let Some(ref inputs) = self.player_inputs {
+            c.player_controller.add(&entity, PlayerController { inputs: inputs.clone() });
+        }
+        if let Some(ref bouncy_enemy) = self.bouncy_enemy {
+            c.bouncy_enemy.add(&entity, BouncyEnemy {
+                orbit: None,
+                attract: bouncy_enemy.attract,
+            });
+        }
+        if let Some(ref item) = self.item {
+            c.item.add(&entity, item.clone());
+        }
+        if let Some(ref item_spawn) = self.item_spawn {
+            c.item_spawn.add(&entity, ItemSpawn {
+                spawned_entity: None,
+                cooldown_s: item_spawn.cooldown_s,
+                table: item_spawn.table.clone(),
+            });
+        }
+        if let Some(ref projectile) = self.projectile {
+            c.projectile.add(&entity, projectile.clone());
+        }
+        if let Some(time_left_s) = self.lifetime_s {
+            c.lifetime.add(&entity, Lifetime { time_left_s: time_left_s });
+        }
+        if let Some(hit_points) = self.hit_points {
+            c.destructible.add(&entity, Destructible { hit_points: hit_points });
+        }
+    }
+}
+
+#[derive(Clone, RustcEncodable, RustcDecodable)]
+pub struct EntitySnapshot {
+    pub id: EntityId,
+    pub type_id: EntityTypeId,
+    pub owner: PlayerId,
+    pub server: ServerComponents,
+}
+
+impl EntitySnapshot {
+    /// Stores a net entity. Returns its net components, which include the ones that are only
+    /// sent to the owner.
+    pub fn store(id: EntityId, type_id: EntityTypeId, owner: PlayerId,
+                 data: &mut DataHelper<Components, Services>,
+                 net_ids: &HashMap<ecs::Entity, EntityId>)
+                 -> (NetComponents, EntitySnapshot) {
+        let entity = data.services.net_entities[id];
+        let entity_type = data.services.entity_types[type_id as usize].1.clone();
+
+        data.with_entity_data(&entity, |e, c| {
+            let it = entity_type.component_types.iter()
+                                .chain(entity_type.owner_component_types.iter())
+                                .map(|c| *c);
+            let components = NetComponents::from_entity(it, e, &*c);
+
+            let server = ServerComponents {
+                linear_velocity: c.linear_velocity.get(&e),
+                angular_velocity: c.angular_velocity.get(&e),
+                shape: c.shape.get(&e),
+                player_inputs: if c.player_controller.has(&e) {
+                    Some(c.player_controller[e].inputs.clone())
+                } else {
+                    None
+                },
+                bouncy_enemy: if c.bouncy_enemy.has(&e) {
+                    Some(BouncyEnemySnapshot {
+                        orbit: c.bouncy_enemy[e].orbit.and_then(|o| net_ids.get(&o).cloned()),
+                        attract: c.bouncy_enemy[e].attract,
+                    })
+                } else {
+                    None
+                },
+                item: c.item.get(&e),
+                item_spawn: if c.item_spawn.has(&e) {
+                    Some(ItemSpawnSnapshot {
+                        spawned_entity: c.item_spawn[e].spawned_entity
+                                         .and_then(|s| net_ids.get(&s).cloned()),
+                        cooldown_s: c.item_spawn[e].cooldown_s,
+                        table: c.item_spawn[e].table.clone(),
+                    })
+                } else {
+                    None
+                },
+                projectile: c.projectile.get(&e),
+                lifetime_s: if c.lifetime.has(&e) {
+                    Some(c.lifetime[e].time_left_s)
+                } else {
+                    None
+                },
+                hit_points: if c.destructible.has(&e) {
+                    Some(c.destructible[e].hit_points)
+                } else {
+                    None
+                },
+            };
+
+            (components, EntitySnapshot {
+                id: id,
+                type_id: type_id,
+                owner: owner,
+                server: server,
+            })
+        }).unwrap()
+    }
+
+    /// Recreates the net entity with its original id, without telling any clients
+    pub fn restore(&self, components: &NetComponents,
+                   data: &mut DataHelper<Components, Services>) -> ecs::Entity {
+        let entity_type = data.services.entity_types[self.type_id as usize].1.clone();
+
+        let entity = entities::build_net_with_id(self.id, self.type_id, self.owner, data,
+            |entity: BuildData<Components>, c: &mut Components| {
+                self.server.build(entity, c);
+            });
+
+        data.with_entity_data(&entity, |e, c| {
+            let it = entity_type.component_types.iter()
+                                .chain(entity_type.owner_component_types.iter())
+                                .map(|c| *c);
+            components.load_to_entity(it, e, c);
+        });
+
+        entity
+    }
+
+    /// Points references to other entities at the restored entities. Needs to be called once
+    /// all entities have been restored.
+    pub fn restore_references(&self, data: &mut DataHelper<Components, Services>) {
+        let entity = data.services.net_entities[self.id];
+        let orbit = self.server.bouncy_enemy.as_ref()
+                        .and_then(|b| b.orbit)
+                        .and_then(|id| data.services.net_entities.get(id));
+        let spawned_entity = self.server.item_spawn.as_ref()
+                                 .and_then(|s| s.spawned_entity)
+                                 .and_then(|id| data.services.net_entities.get(id));
+
+        data.with_entity_data(&entity, |e, c| {
+            if c.bouncy_enemy.has(&e) {
+                c.bouncy_enemy[e].orbit = orbit;
+            }
+            if c.item_spawn.has(&e) {
+                c.item_spawn[e].spawned_entity = spawned_entity;
+            }
+        });
+    }
+}
+
+#[derive(Clone, RustcEncodable, RustcDecodable)]
+pub struct PlayerSnapshot {
+    pub id: PlayerId,
+    pub info: PlayerInfo,
+    pub entity: Option<EntityId>,
+    pub respawn_time: Option<f32>,
+
+    // Difficulty of the bot, if the player is controlled by the server
+    pub bot: Option<String>,
+
+    // Address that the client of the player was connected from. Only a client from the same
+    // address can take over the player.
+    pub host: Option<u32>,
+}
+
+/// The complete state of a running game. Restoring it continues the match where it was left,
+/// with players taking over their old entities when they reconnect with the same name from the
+/// same address.
+#[derive(Clone, RustcEncodable, RustcDecodable)]
+pub struct Snapshot {
+    pub map_name: String,
+    pub ticks_per_second: u32,
+    pub tick_number: TickNumber,
+    pub time_s: f32,
+
+    pub phase: MatchPhase,
+    pub phase_time_s: f32,
+
+    // The random number generator is reseeded with this on restore
+    pub seed: u32,
+
+    pub items_enabled: bool,
+    pub entity_id_counter: EntityId,
+
+    pub players: Vec<PlayerSnapshot>,
+    pub entities: Vec<EntitySnapshot>,
+
+    // Internal state of the game mode, such as team scores
+    pub mode: ModeSnapshot,
+
+    // Net components of all entities, including the ones that are only sent to owners
+    pub net_state: Tick,
+}
+
+impl Snapshot {
+    pub fn load(path: &str) -> Result<Snapshot, String> {
+        info!("loading snapshot {}", path);
+
+        let file = try!(File::open(path).map_err(|e| e.to_string()));
+        let mut reader = BufReader::new(file);
+
+        let version: u32 = try!(decode_from(&mut reader, SizeLimit::Infinite)
+                                    .map_err(|e| e.to_string()));
+        if version != SNAPSHOT_VERSION {
+            return Err(format!("Snapshot has version {}, but only version {} is supported",
+                               version, SNAPSHOT_VERSION));
+        }
+
+        decode_from(&mut reader, SizeLimit::Infinite).map_err(|e| e.to_string())
+    }
+
+    /// Writes to a temporary file first, so that an existing snapshot is not lost if writing
+    /// fails halfway
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        info!("saving snapshot to {}", path);
+
+        let tmp_path = format!("{}.tmp", path);
+        {
+            let file = try!(File::create(&tmp_path).map_err(|e| e.to_string()));
+            let mut writer = BufWriter::new(file);
+
+            try!(encode_into(&SNAPSHOT_VERSION, &mut writer, SizeLimit::Infinite)
+                     .map_err(|e| e.to_string()));
+            try!(encode_into(self, &mut writer, SizeLimit::Infinite)
+                     .map_err(|e| e.to_string()));
+            try!(writer.flush().map_err(|e| e.to_string()));
+        }
+
+        fs::rename(&tmp_path, path).map_err(|e| e.to_string())
+    }
+}
@@ -2,36 +2,60 @@ use std::f32;
 use std::collections::HashMap;
 
 use ecs;
-use rand;
+use rand::{self, Rng};
 use hprof;
-use na::{Vec2, Norm};
+use na::Vec2;
 
 use shared::{NEUTRAL_PLAYER_ID, TickNumber, GameInfo, DeathReason, GameEvent, PlayerId, PlayerInfo,
-             Item};
+             PlayerStats, LifetimeStats, MatchPhase, Item, EntityId, Tick};
 use shared::services::HasEvents;
 use shared::map::Map;
 use shared::net::TimedPlayerInput;
+use shared::util::FixedHashMap;
 
 use components::WallPosition;
 use systems::Systems;
 use services::Services;
+use match_state::{MatchConfig, MatchState};
+use modes::{self, GameMode, ModeContext};
 use entities;
+use bots::{Bot, Difficulty};
+use item_defs::ItemDefs;
+use snapshot::{Snapshot, PlayerSnapshot, EntitySnapshot};
+use stats::{StatsStore, LEADERBOARD_SIZE};
+use event_log::EventLog;
 
 const RESPAWN_TIME_S: f32 = 5.0;
 
+// Players restored from a snapshot are removed if they don't reconnect within this time
+const RECONNECT_TIME_S: f32 = 60.0;
+
+// Entity types that can be created by admins. Other types need more setup than a position.
+pub const SPAWNABLE_ENTITY_TYPES: &'static [&'static str] = &["bouncy_enemy", "item_spawn"];
+
 pub struct Player {
     // Has this player been sent its first tick yet?
-    is_new: bool,
+    pub is_new: bool,
 
     // If true, player (and owned entities) will be removed next tick
-    remove: bool,
+    pub remove: bool,
 
-    info: PlayerInfo,
+    pub info: PlayerInfo,
 
     // Entity controlled by the player, if alive
-    entity: Option<ecs::Entity>,
+    pub entity: Option<ecs::Entity>,
+
+    pub respawn_time: Option<f32>, 
+
+    // Set for players restored from a snapshot until their client reconnects
+    pub reconnect_time_s: Option<f32>,
+
+    // Has the client of a restored player just reconnected? Everyone else already knows
+    // about the player then.
+    pub is_rejoin: bool,
 
-    respawn_time: Option<f32>, 
+    // IP address of the player's client in network byte order, or None for bots
+    pub host: Option<u32>,
 }
 
 pub struct SpawnPoint {
@@ -47,10 +71,13 @@ impl Player {
             info: info,
             entity: None,
             respawn_time: Some(0.0),
+            reconnect_time_s: None,
+            is_rejoin: false,
+            host: None,
         }
     }
 
-    fn alive(&self) -> bool {
+    pub fn alive(&self) -> bool {
         self.entity.is_some()
     }
 }
@@ -62,160 +89,436 @@ pub struct GameState {
     pub world: ecs::World<Systems>, 
     pub tick_number: TickNumber,
     time_s: f32,
-    players: HashMap<PlayerId, Player>,
+    players: FixedHashMap<PlayerId, Player>,
+
+    match_state: MatchState,
+
+    // Rules of the game, chosen per map
+    mode: Box<GameMode>,
+
+    // If set, this map will be loaded at the start of the next tick
+    next_map: Option<String>,
+
+    // If set, the match will switch to this phase at the start of the next tick
+    forced_phase: Option<MatchPhase>,
+
+    // Changes requested by admins, applied at the start of the next tick
+    next_ticks_per_second: Option<u32>,
+    next_spawns: Vec<(String, Vec2<f32>)>,
+
+    // Players controlled by the server
+    bots: FixedHashMap<PlayerId, Bot>,
+
+    // Seed that the random number generator of the services was created with
+    seed: u32,
+
+    // Events of the last tick as seen by someone who is not playing, e.g. for replays
+    spectator_events: Vec<GameEvent>,
+
+    // Lifetime stats of player names, if enabled
+    stats: Option<StatsStore>,
+
+    // Set when the stats have been updated at the end of a match
+    leaderboard_changed: bool,
+
+    // Writes the events of every tick to a file, if enabled
+    event_log: Option<EventLog>,
 }
 
 impl GameState {
-    pub fn new(game_info: &GameInfo) -> GameState {
+    pub fn new(game_info: &GameInfo, match_config: MatchConfig, item_defs: ItemDefs)
+               -> GameState {
+        let match_state = MatchState::new(match_config);
+
+        let mut game_info = game_info.clone();
+        game_info.map_name = match_state.current_map().to_string();
+
         let map = Map::load(&game_info.map_name).unwrap();
+        let spawn_points = GameState::spawn_points(&map);
+
+        let seed = match match_state.config().seed {
+            Some(seed) => seed,
+            None => rand::random::<u32>(),
+        };
+        info!("random seed: {}", seed);
 
-        let spawn_points = map.objects.iter()
-               .filter(|object| &object.type_str == "player_spawn")
-               .map(|object| SpawnPoint {
-                        position: Vec2::new(object.x, object.y),
-                        size: Vec2::new(object.width, object.height),
-                    })
-               .collect();
+        let mut services = Services::new(game_info.entity_types.clone(), item_defs, seed);
+        services.friendly_fire = match_state.config().friendly_fire;
 
-        let services = Services::new(game_info.entity_types.clone());
+        let mode = GameState::create_mode(&map, match_state.config());
+        services.credit_ball_owners = mode.credits_ball_owners();
 
         GameState {
-            game_info: game_info.clone(),
+            next_map: Some(game_info.map_name.clone()),
+            game_info: game_info,
             map: map,
             spawn_points: spawn_points,
             world: ecs::World::with_services(services),
+            seed: seed,
             tick_number: 0,
             time_s: 0.0,
-            players: HashMap::new(),
+            players: FixedHashMap::default(),
+            match_state: match_state,
+            mode: mode,
+            forced_phase: None,
+            next_ticks_per_second: None,
+            next_spawns: Vec::new(),
+            bots: FixedHashMap::default(),
+            spectator_events: Vec::new(),
+            stats: None,
+            leaderboard_changed: false,
+            event_log: None,
         }
     }
 
+    /// Creates the game mode given by the "mode" property of the map, falling back to the
+    /// mode of the match config
+    fn create_mode(map: &Map, config: &MatchConfig) -> Box<GameMode> {
+        if let Some(name) = map.property("mode") {
+            match modes::create(name, config) {
+                Some(mode) => return mode,
+                None => warn!("unknown game mode {} in map, using {}", name, config.mode),
+            }
+        }
+
+        modes::create(&config.mode, config).unwrap()
+    }
+
+    fn spawn_points(map: &Map) -> Vec<SpawnPoint> {
+        map.objects.iter()
+           .filter(|object| &object.type_str == "player_spawn")
+           .map(|object| SpawnPoint {
+                    position: Vec2::new(object.x, object.y),
+                    size: Vec2::new(object.width, object.height),
+                })
+           .collect()
+    }
+
     fn create_map_objects(&mut self) {
         for object in self.map.objects.iter() {
             if &object.type_str == "item_spawn" {
+                let table = match object.property("table") {
+                    Some(table) if self.world.services.item_defs.has_spawn_table(table) =>
+                        Some(table.to_string()),
+                    Some(table) => {
+                        warn!("unknown item spawn table {} in map, using all items", table);
+                        None
+                    }
+                    None => None
+                };
+
                 let entity = entities::build_net(&object.type_str, 0, &mut self.world.data);
                 self.world.with_entity_data(&entity, |e, c| {
                     c.position[e].p = Vec2::new(object.x, object.y);
+                    c.item_spawn[e].table = table.clone();
                 });
             } else if &object.type_str == "bouncy_enemy" {
                 let entity = entities::build_net(&object.type_str, 0, &mut self.world.data);
+                let angle = self.world.services.rng.gen::<f32>() * f32::consts::PI * 2.0;
+                let attract = self.world.services.rng.gen::<bool>();
                 self.world.with_entity_data(&entity, |e, c| {
                     c.position[e].p = Vec2::new(object.x, object.y);
-                    c.orientation[e].angle = rand::random::<f32>() * f32::consts::PI * 2.0;
-                    c.bouncy_enemy[e].attract = rand::random::<bool>();
+                    c.orientation[e].angle = angle;
+                    c.bouncy_enemy[e].attract = attract;
                 });
             } else if &object.type_str == "player_spawn" {
+            } else if &object.type_str == "hill" {
+                // Used by the king of the hill mode
             } else {
                 warn!("ignoring unknown entity type {} in map", object.type_str);
             }
         }
 
-        for &(pos_a, pos_b) in self.map.lines.iter() {
-            let entity = entities::build_net("wall_wood", 0, &mut self.world.data);
+        for line in self.map.lines.iter() {
+            let entity = entities::build_net(line.wall_type.entity_type(), 0,
+                                             &mut self.world.data);
             self.world.with_entity_data(&entity, |e, c| {
                 c.wall_position[e] = WallPosition {
-                    pos_a: pos_a,
-                    pos_b: pos_b
+                    pos_a: line.pos_a,
+                    pos_b: line.pos_b
                 };
             });
         }
+
+        self.world.flush_queue();
     }
 
-    // For adding test entities and stuff
-    fn init_first_tick(&mut self) {
-        self.create_map_objects();
+    pub fn game_info(&self) -> &GameInfo {
+        &self.game_info
+    }
 
-        /*let num_bouncies = 50;
+    pub fn match_phase(&self) -> MatchPhase {
+        self.match_state.phase()
+    }
 
-        for _ in 0..num_bouncies {
-            let entity = entities::build_net("bouncy_enemy", 0, &mut self.world.data);
+    /// Time until the current match phase ends, if it has a fixed duration
+    pub fn match_time_left_s(&self) -> Option<f32> {
+        self.match_state.time_left_s()
+    }
 
-            // Pick a random non-blocked tile
-            let mut rx;
-            let mut ry;
-            loop {
-                rx = rand::random::<usize>() % self.map.width();
-                ry = rand::random::<usize>() % self.map.height();
+    pub fn mode_name(&self) -> &'static str {
+        self.mode.name()
+    }
 
-                if self.map.get_tile(LayerId::Block, rx, ry).is_none() {
-                    break;
-                }
-            }
+    /// Switches to the given map at the start of the next tick, starting a new warmup phase
+    pub fn change_map(&mut self, map_name: &str) {
+        self.next_map = Some(map_name.to_string());
+        self.forced_phase = Some(MatchPhase::Warmup);
+    }
 
-            let position = [(rx * self.map.tile_width()) as f64 +
-                            self.map.tile_width() as f64 / 2.0,
-                            (ry * self.map.tile_height()) as f64 +
-                            self.map.tile_height() as f64 / 2.0];
+    /// Restarts the match on the current map, starting with a countdown
+    pub fn restart_round(&mut self) {
+        self.forced_phase = Some(MatchPhase::Countdown);
+    }
 
-            self.world.with_entity_data(&entity, |e, c| {
-                c.position[e].p = position;
-                c.orientation[e].angle = rand::random::<f64>() * f64::consts::PI * 2.0;
-            });
-        }*/
-        
-        let num_walls = 30;
-        let width = self.map.width_pixels() as f32;
-        let height = self.map.height_pixels() as f32;
+    /// Changes the tick rate at the start of the next tick
+    pub fn set_ticks_per_second(&mut self, ticks_per_second: u32) {
+        assert!(ticks_per_second > 0);
+        self.next_ticks_per_second = Some(ticks_per_second);
+    }
 
-        /*for _ in 0..num_walls {
-            let entity = entities::build_net("wall_wood", 0, &mut self.world.data);
+    /// Enables or disables spawning of new items. Returns true if items are now enabled.
+    pub fn toggle_items(&mut self) -> bool {
+        self.world.services.items_enabled = !self.world.services.items_enabled;
+        self.world.services.items_enabled
+    }
 
-            let ax = rand::random::<f32>() * width;
-            let ay = rand::random::<f32>() * height;
-            let phi = rand::random::<f32>() * f32::consts::PI * 2.0;
-            let r = rand::random::<f32>() * (400.0 - 32.0) + 32.0;
-            let bx = ax + phi.cos() * r;
-            let by = ay + phi.sin() * r;
+    /// Creates a net entity at the start of the next tick
+    pub fn spawn_entity(&mut self, type_name: &str, position: Vec2<f32>) -> Result<(), String> {
+        if !SPAWNABLE_ENTITY_TYPES.contains(&type_name) {
+            return Err(format!("Can only spawn the following entity types: {}",
+                               SPAWNABLE_ENTITY_TYPES.join(", ")));
+        }
 
-            //println!("wall at {},{},{},{}", ax, ay, bx, by);
+        self.next_spawns.push((type_name.to_string(), position));
+        Ok(())
+    }
 
-            self.world.with_entity_data(&entity, |e, c| {
-                c.wall_position[e] = WallPosition {
-                    pos_a: Vec2::new(ax, ay),
-                    pos_b: Vec2::new(bx, by), 
-                };
-            });
-        }*/
+    pub fn player_ids(&self) -> Vec<PlayerId> {
+        self.players.keys().cloned().collect()
+    }
 
-        /*let entity = entities::build_net("wall_wood", 0, &mut self.world.data);
-        self.world.with_entity_data(&entity, |e, c| {
-            c.wall_position[e] = WallPosition {
-                pos_a: Vec2::new(0.0, 0.0),
-                pos_b: Vec2::new(width, 0.0)
-            };
-        });
-        let entity = entities::build_net("wall_wood", 0, &mut self.world.data);
-        self.world.with_entity_data(&entity, |e, c| {
-            c.wall_position[e] = WallPosition {
-                pos_a: Vec2::new(0.0, 0.0),
-                pos_b: Vec2::new(0.0, height)
-            };
-        });
-        let entity = entities::build_net("wall_wood", 0, &mut self.world.data);
-        self.world.with_entity_data(&entity, |e, c| {
-            c.wall_position[e] = WallPosition {
-                pos_a: Vec2::new(width, 0.0),
-                pos_b: Vec2::new(width, height)
-            };
-        });
-        let entity = entities::build_net("wall_wood", 0, &mut self.world.data);
-        self.world.with_entity_data(&entity, |e, c| {
-            c.wall_position[e] = WallPosition {
-                pos_a: Vec2::new(0.0, height), 
-                pos_b: Vec2::new(width, height)
-            };
-        });*/
+    /// Info of every player, ordered by id
+    pub fn player_list(&self) -> Vec<(PlayerId, PlayerInfo)> {
+        let mut players = self.players.iter()
+                              .map(|(&id, player)| (id, player.info.clone()))
+                              .collect::<Vec<_>>();
+        players.sort_by(|a, b| a.0.cmp(&b.0));
+        players
+    }
 
-        self.world.flush_queue();
+    /// Events of the last tick that are not specific to any player
+    pub fn spectator_events(&self) -> &[GameEvent] {
+        &self.spectator_events
+    }
+
+    pub fn match_config(&self) -> &MatchConfig {
+        self.match_state.config()
+    }
+
+    /// Ids of bots that are not about to be removed
+    pub fn bot_ids(&self) -> Vec<PlayerId> {
+        self.bots.keys().cloned().filter(|id| !self.players[id].remove).collect()
     }
 
     pub fn tick_number(&self) -> TickNumber {
         self.tick_number 
     }
 
-    pub fn add_player(&mut self, id: PlayerId, info: PlayerInfo) {
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    /// Stores the complete state of the game. Should be called between ticks.
+    pub fn snapshot(&mut self) -> Snapshot {
+        // Entities refer to each other by their ecs handles, which are stored as net ids
+        let net_ids = self.world.services.net_entities.iter()
+                          .map(|(&id, &entity)| (entity, id))
+                          .collect::<HashMap<_, _>>();
+
+        let mode = self.mode.snapshot(&mut ModeContext::new(&mut self.players, &mut self.world));
+
+        let mut net_state = Tick::new(self.tick_number);
+        let mut entities = Vec::new();
+        for (id, type_id, owner) in self.world.systems.net_entity_system
+                                        .entity_list(&self.world.data) {
+            let (components, entity) = EntitySnapshot::store(id, type_id, owner,
+                                                             &mut self.world.data, &net_ids);
+            net_state.state.entities.push((id, components));
+            entities.push(entity);
+        }
+
+        let mut players = self.players.iter()
+            .filter(|&(_, player)| !player.remove)
+            .map(|(&id, player)| PlayerSnapshot {
+                id: id,
+                info: player.info.clone(),
+                entity: player.entity.and_then(|entity| net_ids.get(&entity).cloned()),
+                respawn_time: player.respawn_time,
+                bot: self.bots.get(&id).map(|bot| bot.difficulty().name().to_string()),
+                host: player.host,
+            })
+            .collect::<Vec<_>>();
+        players.sort_by(|a, b| a.id.cmp(&b.id));
+
+        Snapshot {
+            map_name: self.game_info.map_name.clone(),
+            ticks_per_second: self.game_info.ticks_per_second,
+            tick_number: self.tick_number,
+            time_s: self.time_s,
+            phase: self.match_state.phase(),
+            phase_time_s: self.match_state.phase_time_s(),
+            // Taken from a copy, so that saving a snapshot does not change the course of the game
+            seed: self.world.services.rng.clone().gen(),
+            items_enabled: self.world.services.items_enabled,
+            entity_id_counter: self.world.services.entity_id_counter(),
+            players: players,
+            entities: entities,
+            mode: mode,
+            net_state: net_state,
+        }
+    }
+
+    /// Continues the game stored in a snapshot. Must be called before the first tick.
+    /// Human players wait for their clients to reconnect with the same name.
+    pub fn restore(&mut self, snapshot: Snapshot) -> Result<(), String> {
+        assert!(self.tick_number == 0 && self.players.is_empty(),
+                "can only restore a snapshot into a fresh game state");
+
+        info!("restoring tick {} on map {}", snapshot.tick_number, snapshot.map_name);
+
+        let map = try!(Map::load(&snapshot.map_name));
+        self.mode = GameState::create_mode(&map, self.match_state.config());
+        self.world.services.credit_ball_owners = self.mode.credits_ball_owners();
+        self.spawn_points = GameState::spawn_points(&map);
+        self.map = map;
+        self.next_map = None;
+
+        self.game_info.map_name = snapshot.map_name.clone();
+        self.game_info.ticks_per_second = snapshot.ticks_per_second;
+        self.tick_number = snapshot.tick_number;
+        self.time_s = snapshot.time_s;
+        self.seed = snapshot.seed;
+        self.match_state.restore(snapshot.phase, snapshot.phase_time_s, &snapshot.map_name);
+
+        self.world.services.reseed(snapshot.seed);
+        self.world.services.items_enabled = snapshot.items_enabled;
+        self.world.services.set_entity_id_counter(snapshot.entity_id_counter);
+
+        if snapshot.entities.len() != snapshot.net_state.state.entities.len() {
+            return Err("Snapshot has entities without net components".to_string());
+        }
+        for (entity, &(id, ref components)) in snapshot.entities.iter()
+                                                   .zip(&snapshot.net_state.state.entities) {
+            if entity.id != id {
+                return Err(format!("Snapshot has no net components for entity {}", entity.id));
+            }
+            entity.restore(components, &mut self.world.data);
+        }
+        for entity in &snapshot.entities {
+            entity.restore_references(&mut self.world.data);
+        }
+
+        for player_snapshot in snapshot.players {
+            let id = player_snapshot.id;
+            let mut player = Player::new(player_snapshot.info);
+            player.is_new = false;
+            player.entity = player_snapshot.entity
+                                .and_then(|entity| self.world.services.net_entities.get(entity));
+            player.respawn_time = player_snapshot.respawn_time;
+            player.host = player_snapshot.host;
+
+            match player_snapshot.bot.as_ref().and_then(|name| Difficulty::from_name(name)) {
+                Some(difficulty) => {
+                    self.bots.insert(id, Bot::new(difficulty));
+                }
+                None => player.reconnect_time_s = Some(RECONNECT_TIME_S),
+            }
+            if let Some(team) = player.info.team {
+                self.world.services.player_teams.insert(id, team);
+            }
+
+            self.players.insert(id, player);
+        }
+
+        self.world.flush_queue();
+
+        try!(self.mode.restore(&self.map, &snapshot.mode,
+                               &mut ModeContext::new(&mut self.players, &mut self.world)));
+        self.world.flush_queue();
+
+        Ok(())
+    }
+
+    /// A restored player with the given name whose client has not reconnected yet. Fails if
+    /// the player was connected from a different address, since then someone else may be
+    /// trying to take over the player.
+    pub fn reconnecting_player(&self, name: &str, host: u32) -> Result<Option<PlayerId>, String> {
+        let restored = self.players.iter()
+            .find(|&(_, player)| player.reconnect_time_s.is_some() && !player.remove &&
+                                 player.info.name == name);
+
+        match restored {
+            Some((&id, player)) if player.host == Some(host) => Ok(Some(id)),
+            Some((&id, _)) =>
+                Err(format!("restored player {} with name {} was connected from another address",
+                            id, name)),
+            None => Ok(None),
+        }
+    }
+
+    /// Lets a reconnected client take over a restored player. The state is replicated to the
+    /// client at the start of the next tick.
+    pub fn reconnect_player(&mut self, id: PlayerId) {
+        let player = self.players.get_mut(&id).unwrap();
+        assert!(player.reconnect_time_s.is_some());
+
+        player.reconnect_time_s = None;
+        player.is_new = true;
+        player.is_rejoin = true;
+    }
+
+    pub fn set_event_log(&mut self, event_log: EventLog) {
+        self.event_log = Some(event_log);
+    }
+
+    /// Keeps lifetime stats of the players in the given store
+    pub fn set_stats_store(&mut self, stats: StatsStore) {
+        self.stats = Some(stats);
+    }
+
+    /// The best players over all matches, if stats are enabled
+    pub fn leaderboard(&self) -> Option<Vec<(String, LifetimeStats)>> {
+        self.stats.as_ref().map(|stats| stats.leaderboard(LEADERBOARD_SIZE))
+    }
+
+    /// Returns true once after a match has ended and changed the leaderboard
+    pub fn take_leaderboard_changed(&mut self) -> bool {
+        let changed = self.leaderboard_changed;
+        self.leaderboard_changed = false;
+        changed
+    }
+
+    pub fn save_stats(&mut self) -> Result<(), String> {
+        match self.stats.as_mut() {
+            Some(stats) => stats.save(),
+            None => Ok(()),
+        }
+    }
+
+    /// Adds a player whose client is connected from the address `host`
+    pub fn add_player(&mut self, id: PlayerId, info: PlayerInfo, host: Option<u32>) {
         assert!(self.players.get(&id).is_none());
-        self.players.insert(id, Player::new(info));
+
+        let mut player = Player::new(info);
+        player.host = host;
+        self.players.insert(id, player);
+    }
+
+    /// Adds a player that is controlled by the server. The id must not be used by any client.
+    pub fn add_bot(&mut self, id: PlayerId, difficulty: Difficulty) {
+        self.add_player(id, PlayerInfo::new(format!("bot{}", id)), None);
+        self.bots.insert(id, Bot::new(difficulty));
     }
 
     pub fn remove_player(&mut self, id: PlayerId) {
@@ -233,19 +536,17 @@ impl GameState {
 
         // Pick a random spawn point
         let position = {
-            let spawn_point = &self.spawn_points[rand::random::<usize>() %
-                                                 self.spawn_points.len()];
-            Vec2::new(spawn_point.position[0] + rand::random::<f32>() * spawn_point.size[0],
-                      spawn_point.position[1] + rand::random::<f32>() * spawn_point.size[1])
+            let rng = &mut self.world.data.services.rng;
+            let spawn_point = &self.spawn_points[rng.gen_range(0, self.spawn_points.len())];
+            Vec2::new(spawn_point.position[0] + rng.gen::<f32>() * spawn_point.size[0],
+                      spawn_point.position[1] + rng.gen::<f32>() * spawn_point.size[1])
         };
 
-        // If we don't have a catcher right now, this player is lucky
-        let is_catcher = self.current_catcher() == None; 
+        let spawn_protection_s = self.match_state.config().spawn_protection_s;
 
         self.world.with_entity_data(&entity, |e, c| {
             c.position[e].p = position;
-            c.player_state[e].invulnerable_s = Some(2.5);
-            c.player_state[e].is_catcher = is_catcher;
+            c.player_state[e].invulnerable_s = Some(spawn_protection_s);
             c.player_state[e].has_shield = true;
 
             // We'll equip a gun for now
@@ -254,6 +555,8 @@ impl GameState {
             //c.player_state[e].equip(2, Item::BallSpawner { charges: 3 }); 
         });
 
+        self.mode.on_spawn(id, entity, &mut ModeContext::new(&mut self.players, &mut self.world));
+
         entity
     }
 
@@ -271,22 +574,10 @@ impl GameState {
         }
     }
 
-    fn current_catcher(&mut self) -> Option<PlayerId> {
-        for (player_id, player) in self.players.iter() {
-            if let Some(entity) = player.entity {
-                if self.world.with_entity_data(&entity, |e, c| c.player_state[e].is_catcher)
-                       .unwrap() {
-                    return Some(*player_id);
-                }
-            }
-        }
-        return None;
-    }
-
     /// Advances the state of the server by one tick.
     /// Events generated during the tick are stored for each player separately in the services.
     pub fn tick(&mut self) {
-        self.check_integrity();
+        self.mode.check_integrity(&mut ModeContext::new(&mut self.players, &mut self.world));
 
         self.tick_number += 1;
         self.world.services.tick_dur_s = 1.0 / (self.game_info.ticks_per_second as f32);
@@ -297,12 +588,24 @@ impl GameState {
         
         // Initialize the event queue of each player to be empty
         self.world.services.prepare_for_tick(self.tick_number, self.players.keys().map(|i| *i));
+        self.spectator_events.clear();
+
+        // Apply a new tick rate before anything else uses the tick duration
+        if let Some(ticks_per_second) = self.next_ticks_per_second.take() {
+            info!("changing tick rate to {}", ticks_per_second);
+            self.game_info.ticks_per_second = ticks_per_second;
+            self.world.services.tick_dur_s = 1.0 / (ticks_per_second as f32);
+            self.world.services.add_event(&GameEvent::ChangeTickRate(ticks_per_second));
+        }
 
         // First, handle adding new players. Send out events to new players to replicate our state
         // and entities. This means queueing up InitialPlayerList and CreateEntity events.
         // We also send out PlayerJoin events to non-new players.
         self.tick_add_new_players();
         
+        // Give up on restored players that did not reconnect in time
+        self.tick_expire_restored_players();
+
         // Remove any players that disconnected this tick.
         // This means broadcasting PlayerLeave and RemoveEntity events.
         self.tick_remove_disconnected_players();
@@ -310,14 +613,24 @@ impl GameState {
         // Send out a table of player stats (we probably don't need to do this every frame)
         self.tick_replicate_player_stats();
 
-        // Create some initial entities, e.g. from the map specified in self.game_info
-        if self.tick_number == 1 {
-            self.init_first_tick();
+        // Advance the match phase, possibly queueing a map change
+        self.tick_match_state();
+
+        // Load a new map, replacing all entities. Initially, this is where the entities of the
+        // first map are created.
+        if let Some(map_name) = self.next_map.take() {
+            self.tick_load_map(&map_name);
         }
 
+        // Create entities that were requested by admins
+        self.tick_spawn_entities();
+
         // Check if we can respawn some players
         self.tick_respawn_players();
 
+        // Let bots queue up their input like any other player
+        self.tick_bots();
+
         // Finally, run the input queued up for every player (via ClientMessage::PlayerInput).
         // This is the only place where the time of player-controlled entities is advanced.
         // If we don't receive any input from a player, their entity does not move.
@@ -335,36 +648,87 @@ impl GameState {
             self.world.systems.projectile_system.tick(&mut self.world.data);
             self.world.systems.item_spawn_system.tick(&mut self.world.data);
             self.world.systems.rotate_system.tick(&mut self.world.data);
+            self.world.systems.lifetime_system.tick(&mut self.world.data);
             self.world.systems.interaction_system.tick(&mut self.world.data);
         }
-        
+
+        // Keep statistics of the items that were used
+        let item_uses = self.world.services.next_item_uses.drain(..).collect::<Vec<_>>();
+        for (player_id, item) in item_uses {
+            self.update_stats(player_id, |stats| {
+                *stats.items_used.entry(item.name().to_string()).or_insert(0) += 1;
+            });
+        }
+
+        // Let the game mode decide what happens on catches. This may queue up more events.
+        let catches = self.world.services.next_catches.drain(..).collect::<Vec<_>>();
+        for catch in catches {
+            self.mode.on_catch(catch.catcher_id, catch.catchee_id, catch.position,
+                               &mut ModeContext::new(&mut self.players, &mut self.world));
+        }
+
+        // Let the game mode advance its own state
+        self.mode.tick(&mut ModeContext::new(&mut self.players, &mut self.world));
+
+        self.tick_catcher_time();
+
         // Process events generated in this tick
         for i in 0..self.world.services.next_events.len() {
             let event = self.world.services.next_events[i].clone();
             self.tick_process_event(event);
         }
-        self.world.services.next_events.clear();
+        self.spectator_events.extend(self.world.services.next_events.drain(..));
+
+        self.tick_write_event_log();
 
         self.world.flush_queue();
 
+        // Bots and restored players that did not reconnect yet don't have a client that
+        // receives their events
+        for (id, player) in self.players.iter() {
+            if self.bots.contains_key(id) || player.reconnect_time_s.is_some() {
+                self.world.services.next_player_events.get_mut(id).unwrap().clear();
+            }
+        }
+
         self.time_s += self.world.services.tick_dur_s;
     }
 
+    fn tick_write_event_log(&mut self) {
+        let result = match self.event_log.as_mut() {
+            Some(event_log) => event_log.write(self.tick_number, self.time_s,
+                                               &self.spectator_events),
+            None => return,
+        };
+
+        if let Err(error) = result {
+            error!("couldn't write event log {}, disabling it: {}",
+                   self.event_log.as_ref().unwrap().path(), error);
+            self.event_log = None;
+        }
+    }
+
     fn tick_add_new_players(&mut self) {
         // Find new and non-new players
         let mut new_players = vec![];
+        let mut rejoined_players = vec![];
         let mut non_new_players = vec![];
         for (&player_id, player) in self.players.iter_mut() {
             if player.is_new {
                 new_players.push(player_id);
+                if player.is_rejoin {
+                    rejoined_players.push(player_id);
+                }
                 player.is_new = false;
+                player.is_rejoin = false;
             } else {
                 non_new_players.push(player_id);
             }
         }
 
         // Replicate the game state to the new clients
-        for new_player_id in new_players {
+        for &new_player_id in &new_players {
+
             info!("replicating net state to player {}", new_player_id);
 
             // First, tell them about the player list (note: this already includes themselves!)
@@ -372,16 +736,54 @@ impl GameState {
             let event = GameEvent::InitialPlayerList(players);
             self.world.services.add_player_event(new_player_id, &event);
 
+            // Let them know in which phase the match is
+            let event = GameEvent::MatchPhaseChange {
+                phase: self.match_state.phase(),
+                duration_s: self.match_state.time_left_s(),
+            };
+            self.world.services.add_player_event(new_player_id, &event);
+
             // Now we can create entities
             self.world.systems.net_entity_system
                 .replicate_entities(new_player_id, &mut self.world.data);
 
+            self.mode.on_replicate(new_player_id,
+                                   &mut ModeContext::new(&mut self.players, &mut self.world));
+
+            // Players that reconnected after restoring a snapshot are known to everyone else
+            if rejoined_players.contains(&new_player_id) {
+                continue;
+            }
+
             // Tell any non-new players about this new player
             let new_player_info = self.players[&new_player_id].info.clone();
             let event = GameEvent::PlayerJoin(new_player_id, new_player_info);
             for &player_id in &non_new_players {
                 self.world.services.add_player_event(player_id, &event);
             }
+            self.spectator_events.push(event);
+        }
+
+        // Only now that every player knows about the new players, the game mode may assign
+        // them to teams
+        for &new_player_id in new_players.iter().filter(|id| !rejoined_players.contains(id)) {
+            self.mode.on_join(new_player_id,
+                              &mut ModeContext::new(&mut self.players, &mut self.world));
+        }
+    }
+
+    fn tick_expire_restored_players(&mut self) {
+        let dur_s = self.world.services.tick_dur_s;
+        for (&player_id, player) in self.players.iter_mut() {
+            if let Some(time_s) = player.reconnect_time_s {
+                if time_s <= dur_s {
+                    info!("player {} did not reconnect in time", player_id);
+                    player.reconnect_time_s = None;
+                    player.remove = true;
+                } else {
+                    player.reconnect_time_s = Some(time_s - dur_s);
+                }
+            }
         }
     }
 
@@ -395,35 +797,157 @@ impl GameState {
         }
 
         for &id in remove.iter() {
-            // Was this player the catcher?
-            let is_catcher = if let Some(entity) = self.players[&id].entity {
-                self.world.with_entity_data(&entity, |e, c| {
-                    c.player_state[e].is_catcher
-                }).unwrap()
-            } else {
-                false
-            };
+            self.mode.on_leave(id, &mut ModeContext::new(&mut self.players, &mut self.world));
 
             self.world.systems.net_entity_system.remove_player_entities(id, &mut self.world.data);
             self.players.remove(&id); 
+            self.bots.remove(&id);
+            self.world.services.player_teams.remove(&id);
             self.world.services.add_event(&GameEvent::PlayerLeave(id));
+        }
 
-            // If the disconnected player was the catcher, choose a random new alive one as catcher
-            let alive_players = self.players.iter()
-                                    .filter(|&(_, player)| player.alive())
-                                    .map(|(&id, _)| id)
-                                    .collect::<Vec<_>>();
-            if is_catcher && !alive_players.is_empty() {
-                let chosen_one = alive_players[rand::random::<usize>() % alive_players.len()];
-
-                self.world.with_entity_data(&self.players[&chosen_one].entity.unwrap(), |e, c| {
-                    assert!(!c.player_state[e].is_catcher);
-                    c.player_state[e].is_catcher = true;
-                });
+        // Allow systems to remove references to newly-removed entities
+        self.world.flush_queue();
+    }
+
+    fn tick_match_state(&mut self) {
+        let num_players = self.players.len();
+        let max_player_score = self.players.values().map(|p| p.info.stats.score).max();
+        let max_team_score = self.mode.team_scores()
+                                 .and_then(|scores| scores.iter().map(|&(_, s)| s).max());
+        let max_score = max_player_score.unwrap_or(0).max(max_team_score.unwrap_or(0));
+        let previous_phase = self.match_state.phase();
+
+        if let Some(phase) = self.forced_phase.take() {
+            self.match_state.set_phase(phase);
+            self.on_match_phase_change(previous_phase, phase);
+        } else if let Some(phase) = self.match_state.tick(self.world.services.tick_dur_s,
+                                                   num_players, max_score,
+                                                   self.mode.score_limit()) {
+            self.on_match_phase_change(previous_phase, phase);
+        }
+    }
+
+    fn on_match_phase_change(&mut self, previous_phase: MatchPhase, phase: MatchPhase) {
+        info!("match phase: {:?} -> {:?}", previous_phase, phase);
+
+        match phase {
+            MatchPhase::Warmup => {
+                // After the intermission, we continue on the next map in the rotation
+                if previous_phase == MatchPhase::Intermission && self.next_map.is_none() {
+                    self.next_map = Some(self.match_state.rotate_map().to_string());
+                }
+            }
+            MatchPhase::Countdown => {
+                // Players are frozen from here on, so this is the only map load needed for
+                // the match. Keep a map that has already been queued, e.g. by an admin.
+                if self.next_map.is_none() {
+                    self.next_map = Some(if previous_phase == MatchPhase::Intermission {
+                        self.match_state.rotate_map().to_string()
+                    } else {
+                        self.game_info.map_name.clone()
+                    });
+                }
+            }
+            MatchPhase::Playing => {
+                // Start the match with fresh scores; the map was loaded for the countdown
+                self.mode.reset_scores();
+                for player in self.players.values_mut() {
+                    player.info.stats = PlayerStats {
+                        ping_ms: player.info.stats.ping_ms,
+                        .. PlayerStats::default()
+                    };
+                }
+            }
+            MatchPhase::Intermission => {
+                if previous_phase == MatchPhase::Playing {
+                    self.finish_match_stats();
+                }
+
+                let event = GameEvent::NextMap(self.match_state.next_map().to_string());
+                self.world.services.add_event(&event);
             }
         }
 
-        // Allow systems to remove references to newly-removed entities
+        let event = GameEvent::MatchPhaseChange {
+            phase: phase,
+            duration_s: self.match_state.phase_duration_s(phase),
+        };
+        self.world.services.add_event(&event);
+    }
+
+    /// Removes all entities and creates the entities of the new map. Players are respawned.
+    fn tick_load_map(&mut self, map_name: &str) {
+        info!("loading map {}", map_name);
+
+        let map = match Map::load(map_name) {
+            Ok(map) => map,
+            Err(error) => {
+                error!("couldn't load map {}: {}", map_name, error);
+                return;
+            }
+        };
+
+        // Kill all players without counting it as death
+        for player in self.players.values_mut() {
+            if let Some(entity) = player.entity {
+                entities::remove_net(entity, &mut self.world.data);
+                player.entity = None;
+            }
+            player.respawn_time = Some(0.0);
+        }
+
+        self.world.systems.net_entity_system.remove_all_entities(&mut self.world.data);
+        self.world.flush_queue();
+
+        let mode = GameState::create_mode(&map, self.match_state.config());
+        if mode.name() != self.mode.name() {
+            info!("switching game mode to {}", mode.name());
+            self.mode = mode;
+            self.world.services.credit_ball_owners = self.mode.credits_ball_owners();
+
+            // Teams of the previous mode are meaningless now. The new mode gets to treat
+            // everyone as newly joined.
+            let player_ids = self.players.keys().cloned().collect::<Vec<_>>();
+            for &player_id in &player_ids {
+                if self.players[&player_id].info.team.is_some() {
+                    ModeContext::new(&mut self.players, &mut self.world)
+                        .set_team(player_id, None);
+                }
+            }
+            for &player_id in &player_ids {
+                self.mode.on_join(player_id,
+                                  &mut ModeContext::new(&mut self.players, &mut self.world));
+            }
+        }
+
+        self.spawn_points = GameState::spawn_points(&map);
+        self.map = map;
+        self.game_info.map_name = map_name.to_string();
+
+        let event = GameEvent::ChangeMap(map_name.to_string());
+        self.world.services.add_event(&event);
+
+        self.create_map_objects();
+
+        self.mode.on_map_load(&self.map, &mut ModeContext::new(&mut self.players, &mut self.world));
+        self.world.flush_queue();
+    }
+
+    fn tick_spawn_entities(&mut self) {
+        for (type_name, position) in self.next_spawns.drain(..).collect::<Vec<_>>() {
+            info!("spawning {} at {:?}", type_name, position);
+
+            let angle = self.world.services.rng.gen::<f32>() * f32::consts::PI * 2.0;
+            let entity = entities::build_net(&type_name, 0, &mut self.world.data);
+            self.world.with_entity_data(&entity, |e, c| {
+                c.position[e].p = position;
+                if type_name == "bouncy_enemy" {
+                    c.orientation[e].angle = angle;
+                }
+            });
+        }
+
         self.world.flush_queue();
     }
 
@@ -431,6 +955,10 @@ impl GameState {
         let stats = self.players.iter().map(|(&id, p)| (id, p.info.stats.clone())).collect();
         let event = GameEvent::UpdatePlayerStats(stats);
         self.world.services.add_event(&event);
+
+        if let Some(team_scores) = self.mode.team_scores() {
+            self.world.services.add_event(&GameEvent::UpdateTeamScores(team_scores));
+        }
     }
 
     fn tick_respawn_players(&mut self) {
@@ -459,9 +987,41 @@ impl GameState {
         self.world.flush_queue();
     }
 
+    fn tick_bots(&mut self) {
+        if self.bots.is_empty() {
+            return;
+        }
+
+        let observation = self.world.systems.bot_system.observe(&mut self.world.data);
+        let dur_s = self.world.services.tick_dur_s;
+
+        let inputs = {
+            let graph = self.world.systems.navigation_system.graph();
+            let rng = &mut self.world.data.services.rng;
+            self.bots.iter_mut()
+                .map(|(&id, bot)| (id, bot.think(id, &observation, graph, rng, dur_s)))
+                .collect::<Vec<_>>()
+        };
+
+        for (id, input) in inputs {
+            self.on_player_input(id, &TimedPlayerInput {
+                duration_s: dur_s,
+                input: input,
+            });
+        }
+    }
+
     fn tick_run_player_input(&mut self) {
-        self.world.systems.player_controller_system
-            .run_queued_inputs(&mut self.world.data);
+        let phase = self.match_state.phase();
+        if phase == MatchPhase::Countdown || phase == MatchPhase::Intermission {
+            // Players are frozen on the fresh map until the match starts, and while showing
+            // the scores
+            self.world.systems.player_controller_system
+                .clear_queued_inputs(&mut self.world.data);
+        } else {
+            self.world.systems.player_controller_system
+                .run_queued_inputs(&mut self.world.data);
+        }
     }
 
     fn tick_process_event(&mut self, event: GameEvent) {
@@ -474,6 +1034,9 @@ impl GameState {
             } => {
                 self.on_player_died(player_id, position, responsible_player_id, reason);
             }
+            GameEvent::PlayerTagged { responsible_player_id, .. } => {
+                self.update_stats(responsible_player_id, |stats| stats.catches += 1);
+            }
             _ => ()
         }
     }
@@ -492,68 +1055,21 @@ impl GameState {
         } else {
             let player_entity = self.players[&player_id].entity.unwrap();
 
-            // Update the score
-            {
-                let player = self.players.get_mut(&player_id).unwrap();
-                player.info.stats.deaths += 1;
-            }
-            if responsible_player_id != NEUTRAL_PLAYER_ID {
-                let responsible_player = self.players.get_mut(&responsible_player_id).unwrap();
-                responsible_player.info.stats.score +=
-                    match reason {
-                        DeathReason::Caught => 10,
-                        _ => 1,
-                    };
-            }
+            // Scoring and roles are up to the game mode
+            self.mode.on_death(player_id, position, responsible_player_id, reason,
+                               &mut ModeContext::new(&mut self.players, &mut self.world));
 
-            // If this player is the catcher, we need to determine a new catcher
-            let is_catcher = self.world.with_entity_data(&player_entity, |e, c| {
-                let is_catcher = c.player_state[e].is_catcher;
-                c.player_state[e].is_catcher = false;
-                is_catcher
-            }).unwrap();
-
-            if is_catcher {
-                let responsible_entity = 
-                    if responsible_player_id != player_id {
-                        self.players.get(&responsible_player_id).map(|player| player.entity)
-                    } else {
-                        None
-                    };
-
-                if let Some(Some(responsible_entity)) = responsible_entity {
-                    // If we were killed by another player, that one becomes the catcher
-                    self.world.with_entity_data(&responsible_entity, |e, c| {
-                        c.player_state[e].is_catcher = true;
-                    });
-                } else {
-                    // Otherwise, find the player that is the closest to the dead catcher
-                    let player_ids = self.players.keys().filter(|id| **id != player_id);
-
-                    let mut closest: Option<(ecs::Entity, f32)> = None; 
-                    for &id in player_ids {
-                        if let Some(entity) = self.players[&id].entity {
-                            let d = self.world.with_entity_data(&entity, |e, c| {
-                                (position - c.position[e].p).norm()
-                            }).unwrap();
-                            if closest.is_none() || closest.unwrap().1 > d {
-                                closest = Some((entity, d));
-                            }
-                        }
-                    }
-                    
-                    if let Some((closest_player_entity, _)) = closest {
-                        self.world.with_entity_data(&closest_player_entity, |e, c| {
-                            assert!(!c.player_state[e].is_catcher);
-                            c.player_state[e].is_catcher = true;
-                        });
-                    } else {
-                        // If we are here, this should mean that nobody is alive
-                        for (id, player) in self.players.iter() {
-                            assert!(*id == player_id || player.entity.is_none());
-                        }
+            self.update_stats(player_id, |stats| stats.deaths += 1);
+            if responsible_player_id != NEUTRAL_PLAYER_ID && responsible_player_id != player_id {
+                // Catches are counted apart from kills, so that the leaderboard doesn't count
+                // them twice
+                let reason_name = format!("{:?}", reason);
+                self.update_stats(responsible_player_id, |stats| {
+                    match reason {
+                        DeathReason::Caught => stats.catches += 1,
+                        _ => *stats.kills.entry(reason_name).or_insert(0) += 1,
                     }
-                }
+                });
             }
 
             // Kill the player
@@ -567,24 +1083,45 @@ impl GameState {
         }
     }
 
-    fn check_integrity(&mut self) {
-        // When we have at least one player that is alive, there should be exactly one catcher
-        let mut num_alive = 0;
-        let mut num_catchers = 0;
+    /// Changes the lifetime stats of a human player. Only what happens while a match is being
+    /// played counts.
+    fn update_stats<F: FnOnce(&mut LifetimeStats)>(&mut self, player_id: PlayerId, f: F) {
+        if self.match_state.phase() != MatchPhase::Playing || self.bots.contains_key(&player_id) {
+            return;
+        }
 
-        for (_, player) in self.players.iter() {
-            if let Some(entity) = player.entity {
-                num_alive += 1;
-                self.world.with_entity_data(&entity, |e, c| {
-                    if c.player_state[e].is_catcher {
-                        num_catchers += 1;
-                    }
-                });
+        if let (Some(stats), Some(player)) = (self.stats.as_mut(), self.players.get(&player_id)) {
+            f(stats.get_mut(&player.info.name));
+        }
+    }
+
+    fn tick_catcher_time(&mut self) {
+        if self.stats.is_none() || self.match_state.phase() != MatchPhase::Playing {
+            return;
+        }
+
+        let dur_s = self.world.services.tick_dur_s;
+        for player_id in self.player_ids() {
+            if ModeContext::new(&mut self.players, &mut self.world).is_catcher(player_id) {
+                self.update_stats(player_id, |stats| stats.catcher_time_s += dur_s);
             }
         }
+    }
 
-        if num_alive > 0 {
-            assert!(num_catchers == 1, "There should be exactly one catcher!");
+    /// Counts the match for every human player that was there at its end and saves the stats
+    fn finish_match_stats(&mut self) {
+        if let Some(stats) = self.stats.as_mut() {
+            for (id, player) in self.players.iter() {
+                if !self.bots.contains_key(id) && !player.remove &&
+                   player.reconnect_time_s.is_none() {
+                    stats.get_mut(&player.info.name).matches_played += 1;
+                }
+            }
+
+            if let Err(error) = stats.save() {
+                error!("couldn't save stats: {}", error);
+            }
+            self.leaderboard_changed = true;
         }
     }
 }
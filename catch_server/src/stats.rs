@@ -0,0 +1,85 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+
+use rustc_serialize::json;
+
+use shared::LifetimeStats;
+
+// Number of players shown on the leaderboard
+pub const LEADERBOARD_SIZE: usize = 10;
+
+/// Lifetime statistics of every player name that has played on the server, stored in a
+/// JSON file
+pub struct StatsStore {
+    path: String,
+    players: BTreeMap<String, LifetimeStats>,
+
+    // Have the stats changed since they were last saved?
+    dirty: bool,
+}
+
+impl StatsStore {
+    /// Loads the stats from a file. If the file does not exist yet, it is created when saving.
+    pub fn open(path: &str) -> Result<StatsStore, String> {
+        let players = match File::open(path) {
+            Ok(mut file) => {
+                let mut text = String::new();
+                try!(file.read_to_string(&mut text).map_err(|e| e.to_string()));
+                try!(json::decode(&text).map_err(|e| e.to_string()))
+            }
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => {
+                info!("stats file {} does not exist yet", path);
+                BTreeMap::new()
+            }
+            Err(error) => return Err(error.to_string()),
+        };
+
+        Ok(StatsStore {
+            path: path.to_string(),
+            players: players,
+            dirty: false,
+        })
+    }
+
+    /// Writes the stats if they changed. A temporary file is used, so that the old stats are
+    /// kept if writing fails.
+    pub fn save(&mut self) -> Result<(), String> {
+        if !self.dirty {
+            return Ok(());
+        }
+
+        debug!("saving stats to {}", self.path);
+
+        let tmp_path = format!("{}.tmp", self.path);
+        {
+            let mut file = try!(File::create(&tmp_path).map_err(|e| e.to_string()));
+            try!(write!(file, "{}", json::as_pretty_json(&self.players))
+                     .map_err(|e| e.to_string()));
+        }
+        try!(fs::rename(&tmp_path, &self.path).map_err(|e| e.to_string()));
+
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Stats of a player name, to be modified
+    pub fn get_mut(&mut self, name: &str) -> &mut LifetimeStats {
+        self.dirty = true;
+        self.players.entry(name.to_string()).or_insert(LifetimeStats::default())
+    }
+
+    /// The best `n` players, ranked by kills and catches
+    pub fn leaderboard(&self, n: usize) -> Vec<(String, LifetimeStats)> {
+        let mut players = self.players.iter()
+                              .map(|(name, stats)| (name.clone(), stats.clone()))
+                              .collect::<Vec<_>>();
+        players.sort_by(|a, b| {
+            let score_a = a.1.total_kills() + a.1.catches;
+            let score_b = b.1.total_kills() + b.1.catches;
+            (score_b, &a.0).cmp(&(score_a, &b.0))
+        });
+        players.truncate(n);
+        players
+    }
+}
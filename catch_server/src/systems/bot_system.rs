@@ -0,0 +1,91 @@
+use ecs::{Aspect, Process, System, DataHelper};
+
+use shared::{Item, ItemSlot, NUM_ITEM_SLOTS};
+use shared::util::CachedAspect;
+
+use components::Components;
+use services::Services;
+use bots::{Observation, SeenPlayer, SeenProjectile};
+
+/// Collects what bots can see of the world
+pub struct BotSystem {
+    player_aspect: CachedAspect<Components>,
+    item_aspect: CachedAspect<Components>,
+    projectile_aspect: CachedAspect<Components>,
+}
+
+impl BotSystem {
+    pub fn new(player_aspect: Aspect<Components>,
+               item_aspect: Aspect<Components>,
+               projectile_aspect: Aspect<Components>) -> BotSystem {
+        BotSystem {
+            player_aspect: CachedAspect::new(player_aspect),
+            item_aspect: CachedAspect::new(item_aspect),
+            projectile_aspect: CachedAspect::new(projectile_aspect),
+        }
+    }
+
+    pub fn observe(&self, data: &mut DataHelper<Components, Services>) -> Observation {
+        let mut observation = Observation::default();
+
+        for e in self.player_aspect.iter() {
+            let player_id = data.net_entity[e].owner;
+
+            // Only the first slot holding an item that bots know how to use
+            let mut usable_slot = None;
+            for (slot, equipped_item) in data.player_state[e].items.iter().enumerate() {
+                let usable = match equipped_item {
+                    &Some(ref equipped_item) => match equipped_item.item {
+                        Item::Weapon { .. } | Item::FragWeapon { .. } |
+                        Item::BallSpawner { .. } => equipped_item.cooldown_s.is_none(),
+                        _ => false
+                    },
+                    &None => false
+                };
+                if usable {
+                    usable_slot = Some(slot as ItemSlot);
+                    break;
+                }
+            }
+
+            let free_slot = (0..NUM_ITEM_SLOTS).find(|&slot| {
+                data.player_state[e].get_item(slot).is_none()
+            });
+
+            observation.players.push(SeenPlayer {
+                id: player_id,
+                team: data.services.player_teams.get(&player_id).cloned(),
+                position: data.position[e].p,
+                angle: data.orientation[e].angle,
+                is_catcher: data.player_state[e].is_catcher,
+                vulnerable: data.player_state[e].vulnerable(),
+                dash_ready: data.full_player_state[e].dash_cooldown_s.is_none(),
+                has_hidden_item: data.full_player_state[e].hidden_item.is_some(),
+                free_slot: free_slot,
+                usable_slot: usable_slot,
+            });
+        }
+
+        for e in self.item_aspect.iter() {
+            observation.items.push(data.position[e].p);
+        }
+
+        for e in self.projectile_aspect.iter() {
+            observation.projectiles.push(SeenProjectile {
+                owner: data.net_entity[e].owner,
+                position: data.position[e].p,
+                velocity: data.linear_velocity[e].v,
+            });
+        }
+
+        observation
+    }
+}
+
+impl_cached_system!(Components, Services, BotSystem,
+                    player_aspect, item_aspect, projectile_aspect);
+
+impl Process for BotSystem {
+    fn process(&mut self, _: &mut DataHelper<Components, Services>) {
+    }
+}
@@ -1,9 +1,13 @@
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
 use hprof;
+use ecs;
 use ecs::{System, Process, Aspect, EntityData, DataHelper};
-use na::Norm;
+use na::{Vec2, Norm};
 
 use shared::math;
-use shared::util::CachedAspect;
+use shared::spatial_hash::{self, SpatialAspect};
 use shared::movement::{self, WallInteractionType};
 
 use components::{Components, Shape}; 
@@ -28,15 +32,39 @@ pub trait Interaction {
              data: &mut DataHelper<Components, Services>) -> InteractionResponse;
 }
 
+// Entities that moved further than this since the last tick were teleported, so their
+// movement is not swept
+const MAX_SWEEP_DISTANCE: f32 = 100.0;
+
+// Maximal number of steps for sweeping non-circle shapes
+const MAX_SWEEP_STEPS: usize = 16;
+
+/// Two entities that touch at some point during the tick
+struct Contact<'a> {
+    interaction: &'a Interaction,
+    a: EntityData<'a, Components>,
+    b: EntityData<'a, Components>,
+
+    // Time of impact within the tick, in [0,1]
+    time: f32,
+
+    // Movement of the entities over the whole tick
+    motion_a: Vec2<f32>,
+    motion_b: Vec2<f32>,
+}
+
 pub struct InteractionSystem {
     /// Walls in the map
-    wall_aspect: CachedAspect<Components>,
+    wall_aspect: SpatialAspect<Components>,
 
     /// Interactions between two different entity types
-    interactions: Vec<(CachedAspect<Components>, CachedAspect<Components>, Box<Interaction>)>,
+    interactions: Vec<(SpatialAspect<Components>, SpatialAspect<Components>, Box<Interaction>)>,
 
     /// Interactions between entities of the same type
-    self_interactions: Vec<(CachedAspect<Components>, Box<Interaction>)>,
+    self_interactions: Vec<(SpatialAspect<Components>, Box<Interaction>)>,
+
+    /// Positions of the entities at the end of the last tick, for sweeping their movement
+    previous_positions: HashMap<ecs::Entity, Vec2<f32>>,
 }
 
 impl InteractionSystem {
@@ -46,110 +74,302 @@ impl InteractionSystem {
             self_interactions: Vec<(Aspect<Components>, Box<Interaction>)>)
             -> InteractionSystem {
         InteractionSystem {
-            wall_aspect: CachedAspect::new(wall_aspect),
+            wall_aspect: SpatialAspect::new(wall_aspect, spatial_hash::WALL_CELL_SIZE,
+                                            spatial_hash::wall_bounds),
             interactions:
                 interactions.into_iter()
-                            .map(|(a, b, i)| (CachedAspect::new(a), CachedAspect::new(b), i))
+                            .map(|(a, b, i)| (InteractionSystem::entity_aspect(a),
+                                              InteractionSystem::entity_aspect(b),
+                                              i))
                             .collect(),
             self_interactions:
                 self_interactions.into_iter()
-                                 .map(|(a, i)| (CachedAspect::new(a), i))
-                                 .collect()
+                                 .map(|(a, i)| (InteractionSystem::entity_aspect(a), i))
+                                 .collect(),
+            previous_positions: HashMap::new(),
         }
     }
 
-    pub fn tick(&self, data: &mut DataHelper<Components, Services>) {
-        // n^2 kinda loop over all entity pairs that can interact
-        
+    fn entity_aspect(aspect: Aspect<Components>) -> SpatialAspect<Components> {
+        SpatialAspect::new(aspect, spatial_hash::ENTITY_CELL_SIZE, spatial_hash::shape_bounds)
+    }
+
+    /// Interactions are applied in the order in which the entities touched during the tick.
+    /// Entities removed by an earlier interaction don't take part in later ones, and entities
+    /// displaced by an earlier interaction only take part if they still overlap.
+    pub fn tick(&mut self, data: &mut DataHelper<Components, Services>) {
         let _g = hprof::enter("interaction");
 
-        for &(ref aspect_a, ref aspect_b, ref interaction) in self.interactions.iter() {
-            for entity_a in aspect_a.iter() {
-                for entity_b in aspect_b.iter() {
-                    InteractionSystem::try_interaction(&**interaction, entity_a, entity_b,
-                                                       &self.wall_aspect, data);
-                }
+        // Entities have moved since the last tick; their cells cover the whole movement
+        {
+            let _g = hprof::enter("broadphase");
+
+            let previous = &self.previous_positions;
+            let c = &data.components;
+
+            for &mut (ref mut aspect_a, ref mut aspect_b, _) in self.interactions.iter_mut() {
+                aspect_a.update_with(|e| InteractionSystem::swept_bounds(e, previous, c));
+                aspect_b.update_with(|e| InteractionSystem::swept_bounds(e, previous, c));
+            }
+            for &mut (ref mut aspect, _) in self.self_interactions.iter_mut() {
+                aspect.update_with(|e| InteractionSystem::swept_bounds(e, previous, c));
             }
         }
 
-        for &(ref aspect, ref interaction) in self.self_interactions.iter() {
-            for entity_a in aspect.iter() {
-                for entity_b in aspect.iter() {
-                    if entity_a.index() <= entity_b.index() {
-                        // Don't perform interactions twice
-                        continue;
+        {
+            let mut contacts = Vec::new();
+
+            // Only entities in nearby cells of the spatial hash can touch
+            for &(ref aspect_a, ref aspect_b, ref interaction) in self.interactions.iter() {
+                let mut candidates = Vec::new();
+                for entity_a in aspect_a.iter() {
+                    let (min, max) = InteractionSystem::swept_bounds(entity_a,
+                                                                     &self.previous_positions,
+                                                                     &data.components);
+                    aspect_b.query(min, max, &mut candidates);
+                    for &entity_b in candidates.iter() {
+                        contacts.extend(self.contact(&**interaction, entity_a, entity_b,
+                                                     &data.components));
                     }
+                }
+            }
 
-                    InteractionSystem::try_interaction(&**interaction, entity_a, entity_b,
-                                                       &self.wall_aspect, data);
+            for &(ref aspect, ref interaction) in self.self_interactions.iter() {
+                let mut candidates = Vec::new();
+                for entity_a in aspect.iter() {
+                    let (min, max) = InteractionSystem::swept_bounds(entity_a,
+                                                                     &self.previous_positions,
+                                                                     &data.components);
+                    aspect.query(min, max, &mut candidates);
+                    for &entity_b in candidates.iter() {
+                        if entity_a.index() <= entity_b.index() {
+                            // Don't perform interactions twice
+                            continue;
+                        }
+
+                        contacts.extend(self.contact(&**interaction, entity_a, entity_b,
+                                                     &data.components));
+                    }
                 }
             }
+
+            contacts.sort_by(|x, y| x.time.partial_cmp(&y.time).unwrap_or(Ordering::Equal));
+
+            // Entities that have been displaced in this tick. Their contacts were found with
+            // the movement over the whole tick, which is no longer where they went.
+            let mut displaced = HashSet::new();
+
+            for contact in contacts.iter() {
+                if data.server_net_entity[contact.a].removed ||
+                   data.server_net_entity[contact.b].removed {
+                    continue;
+                }
+
+                let displaced_response =
+                    if displaced.contains(&**contact.a) || displaced.contains(&**contact.b) {
+                        if !InteractionSystem::shapes_overlap(&data.shape[contact.a],
+                                                              data.position[contact.a].p,
+                                                              data.orientation[contact.a].angle,
+                                                              &data.shape[contact.b],
+                                                              data.position[contact.b].p,
+                                                              data.orientation[contact.b].angle) {
+                            continue;
+                        }
+
+                        // They touch where they are now, so there is nothing to rewind
+                        let current = Contact {
+                            interaction: contact.interaction,
+                            a: contact.a,
+                            b: contact.b,
+                            time: 1.0,
+                            motion_a: Vec2::new(0.0, 0.0),
+                            motion_b: Vec2::new(0.0, 0.0),
+                        };
+                        InteractionSystem::try_interaction(&current, &self.wall_aspect, data)
+                    } else {
+                        InteractionSystem::try_interaction(contact, &self.wall_aspect, data)
+                    };
+
+                if displaced_response {
+                    displaced.insert(**contact.a);
+                    displaced.insert(**contact.b);
+                }
+            }
+        }
+
+        // Remember where everything is for sweeping in the next tick
+        self.previous_positions.clear();
+        for &(ref aspect_a, ref aspect_b, _) in self.interactions.iter() {
+            for entity in aspect_a.iter().chain(aspect_b.iter()) {
+                self.previous_positions.insert(**entity, data.position[entity].p);
+            }
+        }
+        for &(ref aspect, _) in self.self_interactions.iter() {
+            for entity in aspect.iter() {
+                self.previous_positions.insert(**entity, data.position[entity].p);
+            }
+        }
+    }
+
+    /// Movement of an entity since the last tick
+    fn motion(e: EntityData<Components>,
+              previous_positions: &HashMap<ecs::Entity, Vec2<f32>>,
+              c: &Components)
+              -> Vec2<f32> {
+        let p = c.position[e].p;
+        let motion = match previous_positions.get(&**e) {
+            Some(&previous) => p - previous,
+            None => Vec2::new(0.0, 0.0),
+        };
+
+        if motion.norm() <= MAX_SWEEP_DISTANCE {
+            motion
+        } else {
+            Vec2::new(0.0, 0.0)
         }
     }
 
-    fn try_interaction(interaction: &Interaction,
-                       e_a: EntityData<Components>,
-                       e_b: EntityData<Components>,
-                       wall_aspect: &CachedAspect<Components>,
-                       c: &mut DataHelper<Components, Services>) {
-        if interaction.condition(e_a, e_b, c) &&
-           InteractionSystem::overlap(e_a, e_b, &c.components) {
-            let response = interaction.apply(e_a, e_b, c);
-
-            match response {
-                InteractionResponse::None => {
+    /// Bounding box of an entity over its movement since the last tick
+    fn swept_bounds(e: EntityData<Components>,
+                    previous_positions: &HashMap<ecs::Entity, Vec2<f32>>,
+                    c: &Components)
+                    -> (Vec2<f32>, Vec2<f32>) {
+        let (min, max) = spatial_hash::shape_bounds(e, c);
+        let motion = InteractionSystem::motion(e, previous_positions, c);
+
+        (Vec2::new(min.x.min(min.x - motion.x), min.y.min(min.y - motion.y)),
+         Vec2::new(max.x.max(max.x - motion.x), max.y.max(max.y - motion.y)))
+    }
+
+    /// Checks if two entities touch at some point during their movement in the last tick
+    fn contact<'a>(&self,
+                   interaction: &'a Interaction,
+                   e_a: EntityData<'a, Components>,
+                   e_b: EntityData<'a, Components>,
+                   c: &Components)
+                   -> Option<Contact<'a>> {
+        let motion_a = InteractionSystem::motion(e_a, &self.previous_positions, c);
+        let motion_b = InteractionSystem::motion(e_b, &self.previous_positions, c);
+
+        InteractionSystem::time_of_impact(&c.shape[e_a], c.position[e_a].p - motion_a,
+                                          motion_a, c.orientation[e_a].angle,
+                                          &c.shape[e_b], c.position[e_b].p - motion_b,
+                                          motion_b, c.orientation[e_b].angle)
+            .map(|time| {
+                Contact {
+                    interaction: interaction,
+                    a: e_a,
+                    b: e_b,
+                    time: time,
+                    motion_a: motion_a,
+                    motion_b: motion_b,
                 }
-                InteractionResponse::DisplaceNoOverlap => {
-                    // Displace the shapes so they no longer overlap
-                    let p1 = c.position[e_a].p;
-                    let p2 = c.position[e_b].p;
-                    let delta = p2 - p1;
-                    let cur_dist = delta.norm();
-                    let min_dist_no_overlap = c.shape[e_a].radius() + c.shape[e_b].radius() + 0.05;
-                    assert!(min_dist_no_overlap > cur_dist); // otherwise why are we here?
-                    let delta_no_overlap = delta.normalize() * (min_dist_no_overlap - cur_dist);
+            })
+    }
+
+    /// Finds the earliest time in [0,1] at which two shapes moving by `v_a` and `v_b` from
+    /// `p_a` and `p_b` overlap
+    fn time_of_impact(shape_a: &Shape, p_a: Vec2<f32>, v_a: Vec2<f32>, angle_a: f32,
+                      shape_b: &Shape, p_b: Vec2<f32>, v_b: Vec2<f32>, angle_b: f32)
+                      -> Option<f32> {
+        // The bounding circles can't touch later than the shapes themselves
+        let t = match math::moving_circles_intersection_time(p_a, v_a, shape_a.bounding_radius(),
+                                                             p_b, v_b, shape_b.bounding_radius()) {
+            Some(t) => t,
+            None => return None,
+        };
+
+        match (shape_a, shape_b) {
+            (&Shape::Circle { .. }, &Shape::Circle { .. }) => Some(t),
+            _ => {
+                // Step through the rest of the movement, finely enough that the shapes can't
+                // pass through each other between two steps
+                let (w_a, h_a) = shape_a.dimensions();
+                let (w_b, h_b) = shape_b.dimensions();
+                let step = w_a.min(h_a).min(w_b.min(h_b)) / 2.0;
+                let distance = (v_a - v_b).norm() * (1.0 - t);
+                let num_steps = ((distance / step).ceil() as usize).max(1).min(MAX_SWEEP_STEPS);
+
+                (0..num_steps+1)
+                    .map(|i| t + (1.0 - t) * i as f32 / num_steps as f32)
+                    .find(|&s| InteractionSystem::shapes_overlap(shape_a, p_a + v_a * s, angle_a,
+                                                                 shape_b, p_b + v_b * s, angle_b))
+            }
+        }
+    }
+
+    /// Applies the interaction of a contact if its condition holds. Returns true if the
+    /// entities have been displaced.
+    fn try_interaction(contact: &Contact,
+                       wall_aspect: &SpatialAspect<Components>,
+                       c: &mut DataHelper<Components, Services>) -> bool {
+        let (e_a, e_b) = (contact.a, contact.b);
+
+        if !contact.interaction.condition(e_a, e_b, c) {
+            return false;
+        }
+
+        let response = contact.interaction.apply(e_a, e_b, c);
+
+        match response {
+            InteractionResponse::None => false,
+            InteractionResponse::DisplaceNoOverlap => {
+                // Go back to where the shapes touched, so that they can't pass through each
+                // other
+                let rewind = 1.0 - contact.time;
+                let interaction = ConstWallInteraction(WallInteractionType::Slide);
+                movement::move_entity(e_a, contact.motion_a * -rewind, &interaction,
+                                      &wall_aspect, c);
+                movement::move_entity(e_b, contact.motion_b * -rewind, &interaction,
+                                      &wall_aspect, c);
+
+                // Displace the shapes so they no longer overlap
+                let p1 = c.position[e_a].p;
+                let p2 = c.position[e_b].p;
+                let delta = p2 - p1;
+                let cur_dist = delta.norm();
+                // Bounding radii, so that this also holds for overlapping rect corners
+                let min_dist_no_overlap = c.shape[e_a].bounding_radius() +
+                                          c.shape[e_b].bounding_radius() + 0.05;
+                if cur_dist < min_dist_no_overlap && cur_dist > 0.0 {
+                    let delta_no_overlap = delta.normalize() *
+                                           (min_dist_no_overlap - cur_dist);
                     let interaction = ConstWallInteraction(WallInteractionType::Flip);
                     movement::move_entity(e_a, delta_no_overlap * -0.5, &interaction,
                                           &wall_aspect, c);
                     movement::move_entity(e_b, delta_no_overlap * 0.5, &interaction,
                                           &wall_aspect, c);
                 }
+
+                true
             }
         }
     }
 
-    /// Checks if two entities can interact right now. We simply do this by checking if they
-    /// currently overlap. In the future, it might be necessary to consider movement, though.
-    fn overlap(e_a: EntityData<Components>,
-               e_b: EntityData<Components>,
-               c: &Components)
-               -> bool {
-        let p_a = c.position[e_a].p;
-        let p_b = c.position[e_b].p;
-
-        match (&c.shape[e_a], &c.shape[e_b]) {
+    /// Checks if two shapes at the given positions and orientations overlap
+    fn shapes_overlap(shape_a: &Shape, p_a: Vec2<f32>, angle_a: f32,
+                      shape_b: &Shape, p_b: Vec2<f32>, angle_b: f32)
+                      -> bool {
+        match (shape_a, shape_b) {
             (&Shape::Circle { radius: r_a }, &Shape::Circle { radius: r_b }) => {
                 let d = (p_a - p_b).norm();
                 d <= r_a + r_b
             }
-            (&Shape::Circle { radius: r }, &Shape::Square { size: s }) => {
-                // TODO
-                let angle = c.orientation[e_b].angle;
-                math::rect_circle_overlap(p_b, s, s, angle, p_a, r)
-            }
-            (&Shape::Circle { radius: r }, &Shape::Rect { width: w, height: h }) => {
-                let angle = c.orientation[e_b].angle;
-                math::rect_circle_overlap(p_b, w, h, angle, p_a, r)
+            (&Shape::Circle { radius: r }, shape_b) => {
+                let (w, h) = shape_b.dimensions();
+                math::rect_circle_overlap(p_b, w, h, angle_b, p_a, r)
             }
 
             // Try the other way around...
-            (&Shape::Square { size: _ }, &Shape::Circle { radius: _ }) =>
-                InteractionSystem::overlap(e_b, e_a, c),
-            (&Shape::Rect { width: _, height: _ }, &Shape::Circle { radius: _ }) =>
-                InteractionSystem::overlap(e_b, e_a, c),
+            (_, &Shape::Circle { radius: _ }) =>
+                InteractionSystem::shapes_overlap(shape_b, p_b, angle_b, shape_a, p_a, angle_a),
 
-            (shape_a, shape_b) =>
-                panic!("shape interaction not implemented: {:?}, {:?}", shape_a, shape_b),
+            // Squares and rects
+            (shape_a, shape_b) => {
+                let (w_a, h_a) = shape_a.dimensions();
+                let (w_b, h_b) = shape_b.dimensions();
+                math::rect_rect_overlap(p_a, w_a, h_a, angle_a, p_b, w_b, h_b, angle_b)
+            }
         }
     }
 }
@@ -161,12 +381,21 @@ impl System for InteractionSystem {
     fn activated(&mut self, entity: &EntityData<Components>, components: &Components,
                  _: &mut Services) {
         self.wall_aspect.activated(entity, components);
+        let mut interacts = false;
         for &mut (ref mut aspect_a, ref mut aspect_b, _) in self.interactions.iter_mut() {
             aspect_a.activated(entity, components);
             aspect_b.activated(entity, components);
+            interacts = interacts || aspect_a.check(entity, components) ||
+                        aspect_b.check(entity, components);
         }
         for &mut (ref mut aspect, _) in self.self_interactions.iter_mut() {
             aspect.activated(entity, components);
+            interacts = interacts || aspect.check(entity, components);
+        }
+
+        // New entities are swept from where they were created
+        if interacts {
+            self.previous_positions.insert(***entity, components.position[*entity].p);
         }
     }
 
@@ -192,6 +421,7 @@ impl System for InteractionSystem {
         for &mut (ref mut aspect, _) in self.self_interactions.iter_mut() {
             aspect.deactivated(entity, components);
         }
+        self.previous_positions.remove(&***entity);
     }
 }
 
@@ -3,12 +3,12 @@ use std::f32;
 use ecs::{EntityData, DataHelper};
 use na::{Vec2, Norm};
 
-use shared::{GameEvent, DeathReason, NEUTRAL_PLAYER_ID};
+use shared::{NEUTRAL_PLAYER_ID, GameEvent, DeathReason};
 use shared::services::HasEvents;
 
 use entities;
 use components::Components;
-use services::Services;
+use services::{Services, Catch};
 use systems::interaction_system::{InteractionResponse, Interaction};
 use systems::projectile_system;
 
@@ -26,10 +26,16 @@ impl Interaction for PlayerBouncyEnemyInteraction {
              player: EntityData<Components>, enemy: EntityData<Components>,
              data: &mut DataHelper<Components, Services>) -> InteractionResponse {
         if data.player_state[player].vulnerable() {
+            // Balls spawned by players are owned by them, but only some modes give them credit
             let player_id = data.net_entity[player].owner;
+            let responsible_player_id = if data.services.credit_ball_owners {
+                data.net_entity[enemy].owner
+            } else {
+                NEUTRAL_PLAYER_ID
+            };
             let player_killed = 
                 entities::damage_player(player_id,
-                                        NEUTRAL_PLAYER_ID,
+                                        responsible_player_id,
                                         DeathReason::BouncyBall,
                                         player,
                                         data);
@@ -147,9 +153,17 @@ impl Interaction for ProjectilePlayerInteraction {
     fn condition(&self,
                  projectile: EntityData<Components>, player: EntityData<Components>,
                  data: &mut DataHelper<Components, Services>) -> bool {
-        (data.net_entity[projectile].owner != data.net_entity[player].owner ||
-         data.projectile[projectile].lethal_to_owner()) &&
-        data.player_state[player].vulnerable()
+        let projectile_owner = data.net_entity[projectile].owner;
+        let player_owner = data.net_entity[player].owner;
+
+        let hits = if projectile_owner == player_owner {
+            data.projectile[projectile].lethal_to_owner()
+        } else {
+            data.services.friendly_fire ||
+            !data.services.same_team(projectile_owner, player_owner)
+        };
+
+        hits && data.player_state[player].vulnerable()
     }
 
     fn apply(&self,
@@ -174,6 +188,14 @@ impl Interaction for PlayerPlayerInteraction {
     fn condition(&self,
                  player1: EntityData<Components>, player2: EntityData<Components>,
                  data: &mut DataHelper<Components, Services>) -> bool {
+        // Catchers can't catch each other, and there is no catching within a team
+        let owner1 = data.net_entity[player1].owner;
+        let owner2 = data.net_entity[player2].owner;
+        if data.player_state[player1].is_catcher == data.player_state[player2].is_catcher ||
+           data.services.same_team(owner1, owner2) {
+            return false;
+        }
+
         (data.player_state[player1].is_catcher && data.player_state[player2].vulnerable()) ||
         (data.player_state[player2].is_catcher && data.player_state[player1].vulnerable())
     }
@@ -190,15 +212,13 @@ impl Interaction for PlayerPlayerInteraction {
         assert!(data.player_state[catcher].is_catcher);
         assert!(!data.player_state[catchee].is_catcher);
         
-        let player_id = data.net_entity[catchee].owner;
-        let position = data.position[catchee].p;
-        let responsible_player_id = data.net_entity[catcher].owner;
-        data.services.add_event(&GameEvent::PlayerDied {
-            player_id: player_id,
-            position: position,
-            responsible_player_id: responsible_player_id,
-            reason: DeathReason::Caught,
-        });
+        // The game mode decides what happens to the caught player
+        let catch = Catch {
+            catcher_id: data.net_entity[catcher].owner,
+            catchee_id: data.net_entity[catchee].owner,
+            position: data.position[catchee].p,
+        };
+        data.services.add_catch(catch);
 
         InteractionResponse::None
     }
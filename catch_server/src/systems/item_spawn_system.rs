@@ -1,8 +1,6 @@
-use rand;
 use hprof;
 use ecs::{Aspect, Process, System, BuildData, DataHelper};
 
-use shared::Item;
 use shared::util::CachedAspect;
 
 use components::Components;
@@ -57,17 +55,17 @@ impl ItemSpawnSystem {
             };
             
             // Should we spawn a new item?
-            if data.item_spawn[e].spawned_entity.is_none() && !have_cooldown {
+            if data.item_spawn[e].spawned_entity.is_none() && !have_cooldown &&
+               data.services.items_enabled {
+                let item = {
+                    let table = data.item_spawn[e].table.clone();
+                    let services = &mut data.services;
+                    services.item_defs.choose(table.as_ref().map(|table| &table[..]),
+                                              &mut services.rng)
+                };
+
                 let item_entity = entities::build_net_custom("item", 0, data,
                     |item_e: BuildData<Components>, c: &mut Components| {
-                        let choices = vec![
-                                           Item::Weapon { charges: 10 },
-                                           Item::FragWeapon { charges: 5 },
-                                           Item::BallSpawner { charges: 3 },
-                                           Item::Shield,
-                                          ];
-                        let item = choices[rand::random::<usize>() % choices.len()].clone();
-
                         c.item.add(&item_e, item);
                     });
 
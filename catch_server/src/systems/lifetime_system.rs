@@ -0,0 +1,44 @@
+use hprof;
+use ecs::{Aspect, Process, DataHelper};
+
+use shared::util::CachedAspect;
+
+use components::Components;
+use services::Services;
+use entities;
+
+/// Removes entities whose lifetime has run out
+pub struct LifetimeSystem {
+    aspect: CachedAspect<Components>,
+}
+
+impl LifetimeSystem {
+    pub fn new(aspect: Aspect<Components>) -> LifetimeSystem {
+        LifetimeSystem {
+            aspect: CachedAspect::new(aspect),
+        }
+    }
+
+    pub fn tick(&self, data: &mut DataHelper<Components, Services>) {
+        let _g = hprof::enter("lifetime");
+
+        let mut expired = Vec::new();
+        for e in self.aspect.iter() {
+            data.lifetime[e].time_left_s -= data.services.tick_dur_s;
+            if data.lifetime[e].time_left_s <= 0.0 {
+                expired.push(**e);
+            }
+        }
+
+        for entity in expired {
+            entities::remove_net(entity, data);
+        }
+    }
+}
+
+impl_cached_system!(Components, Services, LifetimeSystem, aspect);
+
+impl Process for LifetimeSystem {
+    fn process(&mut self, _: &mut DataHelper<Components, Services>) {
+    }
+}
@@ -3,11 +3,14 @@ pub mod player_controller_system;
 pub mod bouncy_enemy_system;
 pub mod item_spawn_system;
 pub mod rotate_system;
+pub mod lifetime_system;
 pub mod projectile_system;
 pub mod movement_system;
 pub mod wall_interactions;
 pub mod interaction_system;
 pub mod interactions;
+pub mod bot_system;
+pub mod navigation_system;
 
 use super::components::{Components};
 use super::services::Services;
@@ -16,9 +19,12 @@ pub use self::player_controller_system::PlayerControllerSystem;
 pub use self::bouncy_enemy_system::BouncyEnemySystem;
 pub use self::item_spawn_system::ItemSpawnSystem;
 pub use self::rotate_system::RotateSystem;
+pub use self::lifetime_system::LifetimeSystem;
 pub use self::projectile_system::ProjectileSystem;
 pub use self::movement_system::MovementSystem;
 pub use self::interaction_system::InteractionSystem;
+pub use self::bot_system::BotSystem;
+pub use self::navigation_system::NavigationSystem;
 
 systems! {
     struct Systems<Components, Services> {
@@ -26,13 +32,16 @@ systems! {
             aspect!(<Components> all: [net_entity])),
         player_controller_system: PlayerControllerSystem = PlayerControllerSystem::new(
             aspect!(<Components> all: [player_controller]),
-            aspect!(<Components> all: [wall])),
+            aspect!(<Components> all: [wall]),
+            aspect!(<Components> all: [position, shape])),
         bouncy_enemy_system: BouncyEnemySystem = BouncyEnemySystem::new(
             aspect!(<Components> all: [bouncy_enemy])),
         item_spawn_system: ItemSpawnSystem = ItemSpawnSystem::new(
             aspect!(<Components> all: [item_spawn])),
         rotate_system: RotateSystem = RotateSystem::new(
             aspect!(<Components> all: [rotate])),
+        lifetime_system: LifetimeSystem = LifetimeSystem::new(
+            aspect!(<Components> all: [lifetime])),
         projectile_system: ProjectileSystem = ProjectileSystem::new(
             aspect!(<Components> all: [projectile])),
         movement_system: MovementSystem = MovementSystem::new(
@@ -65,5 +74,11 @@ systems! {
                  (aspect!(<Components> all: [player_state]),
                   Box::new(interactions::PlayerPlayerInteraction)),
                 ]),
+        bot_system: BotSystem = BotSystem::new(
+            aspect!(<Components> all: [player_state]),
+            aspect!(<Components> all: [item]),
+            aspect!(<Components> all: [projectile])),
+        navigation_system: NavigationSystem = NavigationSystem::new(
+            aspect!(<Components> all: [net_entity, wall, wall_position])),
     }
 }
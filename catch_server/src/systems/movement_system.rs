@@ -2,6 +2,7 @@ use hprof;
 use ecs::{Aspect, Process, System, DataHelper, EntityData};
 
 use shared::util::CachedAspect;
+use shared::spatial_hash::{self, SpatialAspect};
 use shared::movement;
 
 use components::Components;
@@ -10,7 +11,7 @@ use services::Services;
 pub type WallInteraction = movement::WallInteraction<Components, Services>;
 
 pub struct MovementSystem {
-    wall_aspect: CachedAspect<Components>,
+    wall_aspect: SpatialAspect<Components>,
     aspects: Vec<(CachedAspect<Components>, Box<WallInteraction>)>,
 }
 
@@ -19,7 +20,8 @@ impl MovementSystem {
                aspects: Vec<(Aspect<Components>, Box<WallInteraction>)>)
                -> MovementSystem {
         MovementSystem {
-            wall_aspect: CachedAspect::new(wall_aspect),
+            wall_aspect: SpatialAspect::new(wall_aspect, spatial_hash::WALL_CELL_SIZE,
+                                            spatial_hash::wall_bounds),
             aspects:
                 aspects.into_iter()
                        .map(|(a, i)| (CachedAspect::new(a), i))
@@ -73,3 +75,69 @@ impl Process for MovementSystem {
     fn process(&mut self, _: &mut DataHelper<Components, Services>) {
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ecs::World;
+    use na::Vec2;
+
+    use shared::entities::all_entity_types;
+    use shared::movement::{self, WallInteractionType};
+
+    use components::WallPosition;
+    use entities;
+    use item_defs::ItemDefs;
+    use services::Services;
+    use systems::Systems;
+    use systems::wall_interactions::ConstWallInteraction;
+
+    fn create_world() -> World<Systems> {
+        let item_defs = ItemDefs::load(concat!(env!("CARGO_MANIFEST_DIR"),
+                                               "/../data/items.json")).unwrap();
+        World::with_services(Services::new(all_entity_types(), item_defs, 1))
+    }
+
+    /// Moves a bullet that already overlaps a wall towards the wall. Returns the position and
+    /// velocity of the bullet afterwards.
+    fn move_bullet_in_wall(interaction_type: WallInteractionType) -> (Vec2<f32>, Vec2<f32>) {
+        let mut world = create_world();
+
+        let wall = entities::build_net("wall_iron", 0, &mut world.data);
+        world.with_entity_data(&wall, |e, c| {
+            c.wall_position[e] = WallPosition {
+                pos_a: Vec2::new(100.0, 0.0),
+                pos_b: Vec2::new(100.0, 200.0),
+            };
+        }).unwrap();
+
+        let bullet = entities::build_net("bullet", 0, &mut world.data);
+        world.with_entity_data(&bullet, |e, c| {
+            c.position[e].p = Vec2::new(101.0, 100.0);
+            c.orientation[e].angle = 0.0;
+            c.linear_velocity[e].v = Vec2::new(-100.0, 0.0);
+        }).unwrap();
+
+        world.flush_queue();
+
+        let system = &world.systems.movement_system;
+        let interaction = ConstWallInteraction(interaction_type);
+        world.data.with_entity_data(&bullet, |e, c| {
+            movement::move_entity(e, Vec2::new(-5.0, 0.0), &interaction, &system.wall_aspect, c);
+            (c.position[e].p, c.linear_velocity[e].v)
+        }).unwrap()
+    }
+
+    #[test]
+    fn slide_in_wall_stays_put() {
+        let (p, v) = move_bullet_in_wall(WallInteractionType::Slide);
+        assert_eq!(p, Vec2::new(101.0, 100.0));
+        assert_eq!(v, Vec2::new(0.0, 0.0));
+    }
+
+    #[test]
+    fn flip_in_wall_stays_put() {
+        let (p, v) = move_bullet_in_wall(WallInteractionType::Flip);
+        assert_eq!(p, Vec2::new(101.0, 100.0));
+        assert_eq!(v, Vec2::new(0.0, 0.0));
+    }
+}
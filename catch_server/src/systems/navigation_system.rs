@@ -0,0 +1,72 @@
+use std::collections::HashMap;
+
+use ecs;
+use ecs::{Aspect, Process, System, EntityData, DataHelper};
+
+use shared::EntityId;
+use shared::navigation::NavGraph;
+
+use components::Components;
+use services::Services;
+
+// Radius of the entities navigating with the graph, slightly larger than that of players
+const NAVIGATION_RADIUS: f32 = 8.0;
+
+/// Keeps a navigation graph up to date with the walls that currently exist
+pub struct NavigationSystem {
+    aspect: Aspect<Components>,
+
+    // Net entity id of every wall in the graph
+    walls: HashMap<ecs::Entity, EntityId>,
+
+    graph: NavGraph,
+}
+
+impl NavigationSystem {
+    pub fn new(aspect: Aspect<Components>) -> NavigationSystem {
+        NavigationSystem {
+            aspect: aspect,
+            walls: HashMap::new(),
+            graph: NavGraph::new(NAVIGATION_RADIUS),
+        }
+    }
+
+    /// Returns the graph, bringing it up to date with the walls added or removed since the
+    /// last call
+    pub fn graph(&mut self) -> &NavGraph {
+        self.graph.update();
+        &self.graph
+    }
+}
+
+impl System for NavigationSystem {
+    type Components = Components;
+    type Services = Services;
+
+    fn activated(&mut self, entity: &EntityData<Components>, components: &Components,
+                 _: &mut Services) {
+        if self.aspect.check(entity, components) {
+            let id = components.net_entity[*entity].id;
+            let wall_position = components.wall_position[*entity].clone();
+
+            self.graph.add_wall(id, wall_position.pos_a, wall_position.pos_b);
+            self.walls.insert(***entity, id);
+        }
+    }
+
+    fn reactivated(&mut self, _: &EntityData<Components>, _: &Components,
+                   _: &mut Services) {
+    }
+
+    fn deactivated(&mut self, entity: &EntityData<Components>, _: &Components,
+                   _: &mut Services) {
+        if let Some(id) = self.walls.remove(&***entity) {
+            self.graph.remove_wall(id);
+        }
+    }
+}
+
+impl Process for NavigationSystem {
+    fn process(&mut self, _: &mut DataHelper<Components, Services>) {
+    }
+}
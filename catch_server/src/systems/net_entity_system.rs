@@ -4,7 +4,7 @@ use ecs::{Aspect, Process, System, EntityData, DataHelper};
 
 use shared;
 use shared::net_components::NetComponents;
-use shared::{EntityTypes, PlayerId, GameEvent, TickState};
+use shared::{EntityId, EntityTypeId, EntityTypes, PlayerId, GameEvent, TickState};
 use shared::util::CachedAspect;
 
 use entities;
@@ -35,6 +35,13 @@ impl NetEntitySystem {
         }
     }
 
+    /// Remove all net entities, e.g. for changing the map
+    pub fn remove_all_entities(&mut self, data: &mut DataHelper<Components, Services>) {
+        for entity in self.aspect.iter() {
+            entities::remove_net(**entity, data);
+        }
+    }
+
     /// Queue up CreateEntity events for a freshly connected player
     pub fn replicate_entities(&self, player_id: PlayerId,
                               data: &mut DataHelper<Components, Services>) {
@@ -47,9 +54,21 @@ impl NetEntitySystem {
         }
     }
 
+    /// Id, type and owner of every net entity, ordered by id
+    pub fn entity_list(&self, data: &DataHelper<Components, Services>)
+                       -> Vec<(EntityId, EntityTypeId, PlayerId)> {
+        let mut entities = self.aspect.iter()
+            .map(|entity| (data.net_entity[entity].id,
+                           data.net_entity[entity].type_id,
+                           data.net_entity[entity].owner))
+            .collect::<Vec<_>>();
+        entities.sort();
+        entities
+    }
+
     /// Write the current state into a TickState
     pub fn store_in_tick_state(&self, player_id: PlayerId, tick_state: &mut TickState,
-                               c: &mut DataHelper<Components, Services>) {
+                               c: &DataHelper<Components, Services>) {
         let mut forced_components = Vec::new();
 
         for e in self.aspect.iter() {
@@ -75,12 +94,18 @@ impl NetEntitySystem {
             for forced_component in &c.server_net_entity[e].forced_components {
                 forced_components.push((net_id, *forced_component));
             }
-            c.server_net_entity[e].forced_components = Vec::new();
         }
         tick_state.sort();
 
         tick_state.forced_components = forced_components;
     }
+
+    /// Forget about forced components once the tick has been stored for everyone
+    pub fn clear_forced_components(&self, data: &mut DataHelper<Components, Services>) {
+        for entity in self.aspect.iter() {
+            data.server_net_entity[entity].forced_components.clear();
+        }
+    }
 }
 
 impl System for NetEntitySystem {
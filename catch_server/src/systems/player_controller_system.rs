@@ -1,4 +1,4 @@
-use ecs::{Aspect, Process, System, DataHelper, EntityData};
+use ecs::{Aspect, Process, System, DataHelper, EntityData, BuildData};
 use na::Vec2;
 
 use shared::{ItemSlot, GameEvent, Item, NUM_ITEM_SLOTS};
@@ -7,29 +7,42 @@ use shared::net::TimedPlayerInput;
 use shared::player::PlayerInputKey;
 use shared::services::HasEvents;
 use shared::util::CachedAspect;
+use shared::spatial_hash::{self, SpatialAspect};
 
-use components::Components;
+use components::{Components, WallPosition, Lifetime};
 use services::Services;
 use entities;
 
-const PROJECTILE_SPEED: f32 = 200.0; 
+// Blocks are placed this far in front of the player, perpendicular to its orientation
+const BLOCK_DISTANCE: f32 = 25.0;
+const BLOCK_LENGTH: f32 = 40.0;
 
 /// System for interpreting player input on the server side
 pub struct PlayerControllerSystem {
     player_aspect: CachedAspect<Components>,
-    wall_aspect: CachedAspect<Components>,
+    wall_aspect: SpatialAspect<Components>,
+
+    // Entities that blocks must not be placed onto
+    shape_aspect: SpatialAspect<Components>,
 }
 
 impl PlayerControllerSystem {
     pub fn new(player_aspect: Aspect<Components>,
-               wall_aspect: Aspect<Components>) -> PlayerControllerSystem {
+               wall_aspect: Aspect<Components>,
+               shape_aspect: Aspect<Components>) -> PlayerControllerSystem {
         PlayerControllerSystem {
             player_aspect: CachedAspect::new(player_aspect),
-            wall_aspect: CachedAspect::new(wall_aspect),
+            wall_aspect: SpatialAspect::new(wall_aspect, spatial_hash::WALL_CELL_SIZE,
+                                            spatial_hash::wall_bounds),
+            shape_aspect: SpatialAspect::new(shape_aspect, spatial_hash::ENTITY_CELL_SIZE,
+                                             spatial_hash::shape_bounds),
         }
     }
 
-    pub fn run_queued_inputs(&self, data: &mut DataHelper<Components, Services>) {
+    pub fn run_queued_inputs(&mut self, data: &mut DataHelper<Components, Services>) {
+        // Entities have moved since the last tick
+        self.shape_aspect.update(&data.components);
+
         for player in self.player_aspect.iter() {
             let inputs = data.player_controller[player].inputs.clone();
             data.player_controller[player].inputs.clear();
@@ -44,6 +57,12 @@ impl PlayerControllerSystem {
         }
     }
 
+    pub fn clear_queued_inputs(&self, data: &mut DataHelper<Components, Services>) {
+        for player in self.player_aspect.iter() {
+            data.player_controller[player].inputs.clear();
+        }
+    }
+
     fn run_item_input(&self, timed_input: &TimedPlayerInput, e: EntityData<Components>,
                       c: &mut DataHelper<Components, Services>) {
         let dur_s = timed_input.duration_s;
@@ -120,6 +139,18 @@ impl PlayerControllerSystem {
         let p = c.position[e].p;
         let angle = c.orientation[e].angle;
         let item = c.player_state[e].get_item(slot).unwrap().item.clone();
+        let def = c.services.item_defs.def(&item).clone();
+
+        // Entities inside of a new block would be stuck in the wall, so the block is not
+        // placed and the charge is kept
+        if let Item::BlockPlacer { .. } = item {
+            if self.overlaps_entity(&block_position(p, angle), c) {
+                debug!("player {} can't place a block onto another entity", player_id);
+                return;
+            }
+        }
+
+        c.services.add_item_use(player_id, item.clone());
 
         let new_item = match item {
             Item::Weapon { charges } => {
@@ -129,8 +160,8 @@ impl PlayerControllerSystem {
                     c.position[projectile_e].p = p;
                     c.orientation[projectile_e].angle = angle;
                     c.linear_velocity[projectile_e].v = Vec2::new(
-                        angle.cos() * PROJECTILE_SPEED,
-                        angle.sin() * PROJECTILE_SPEED
+                        angle.cos() * def.projectile_speed.unwrap(),
+                        angle.sin() * def.projectile_speed.unwrap()
                     );
                 });
 
@@ -147,8 +178,8 @@ impl PlayerControllerSystem {
                     c.position[projectile_e].p = p;
                     c.orientation[projectile_e].angle = angle;
                     c.linear_velocity[projectile_e].v = Vec2::new(
-                        angle.cos() * PROJECTILE_SPEED,
-                        angle.sin() * PROJECTILE_SPEED
+                        angle.cos() * def.projectile_speed.unwrap(),
+                        angle.sin() * def.projectile_speed.unwrap()
                     );
                 });
 
@@ -176,14 +207,34 @@ impl PlayerControllerSystem {
                 c.player_state[e].has_shield = true;
                 None
             }
-            item => panic!("item use not implemented: {:?}", item)
+            Item::SpeedBoost { duration_s } => {
+                c.full_player_state[e].speed_boost_s = Some(duration_s);
+                None
+            }
+            Item::BlockPlacer { charges } => {
+                let wall_position = block_position(p, angle);
+                let wall_entity = entities::build_net_custom("wall_wood", player_id, c,
+                    |wall_e: BuildData<Components>, c: &mut Components| {
+                        c.lifetime.add(&wall_e, Lifetime { time_left_s: def.lifetime_s.unwrap() });
+                    });
+
+                c.with_entity_data(&wall_entity, |wall_e, c| {
+                    c.wall_position[wall_e] = wall_position;
+                });
+
+                if charges > 1 {
+                    Some(Item::BlockPlacer { charges: charges - 1 })
+                } else {
+                    None
+                }
+            }
         };
 
         match &new_item {
             &Some(ref item) => {
                 let equipped_item = c.player_state[e].get_item_mut(slot).unwrap();
                 equipped_item.item = item.clone();
-                equipped_item.cooldown_s = item.cooldown_s();
+                equipped_item.cooldown_s = def.cooldown_s;
             }
             &None => {
                 c.player_state[e].unequip(slot);
@@ -191,6 +242,21 @@ impl PlayerControllerSystem {
         };
     }
 
+    /// Checks if a wall at the given position would touch any entity
+    fn overlaps_entity(&self, wall_position: &WallPosition,
+                       c: &DataHelper<Components, Services>) -> bool {
+        // Players may have moved in this tick already, so they are checked at their current
+        // positions
+        let mut candidates = Vec::new();
+        self.shape_aspect.query_segment(wall_position.pos_a, wall_position.pos_b, 0.0,
+                                        &mut candidates);
+
+        candidates.into_iter().chain(self.player_aspect.iter()).any(|e| {
+            movement::shape_wall_overlap(wall_position, c.position[e].p, &c.shape[e],
+                                         c.orientation[e].angle)
+        })
+    }
+
     fn try_use_item(&self,
                     slot: ItemSlot,
                     e: EntityData<Components>,
@@ -207,9 +273,103 @@ impl PlayerControllerSystem {
     }
 }
 
-impl_cached_system!(Components, Services, PlayerControllerSystem, player_aspect, wall_aspect);
+/// Position of a block placed by a player at `p` facing in direction `angle`
+fn block_position(p: Vec2<f32>, angle: f32) -> WallPosition {
+    let direction = Vec2::new(angle.cos(), angle.sin());
+    let perp = Vec2::new(-direction.y, direction.x);
+    let center = p + direction * BLOCK_DISTANCE;
+
+    WallPosition {
+        pos_a: center - perp * (BLOCK_LENGTH / 2.0),
+        pos_b: center + perp * (BLOCK_LENGTH / 2.0),
+    }
+}
+
+impl_cached_system!(Components, Services, PlayerControllerSystem, player_aspect, wall_aspect,
+                    shape_aspect);
 
 impl Process for PlayerControllerSystem {
     fn process(&mut self, _: &mut DataHelper<Components, Services>) {
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ecs;
+    use ecs::World;
+    use na::Vec2;
+
+    use shared::{Item, PlayerId};
+    use shared::entities::all_entity_types;
+    use shared::movement::{self, WallInteractionType};
+
+    use entities;
+    use item_defs::ItemDefs;
+    use services::Services;
+    use systems::Systems;
+    use systems::wall_interactions::ConstWallInteraction;
+
+    use super::BLOCK_DISTANCE;
+
+    fn create_world() -> World<Systems> {
+        let item_defs = ItemDefs::load(concat!(env!("CARGO_MANIFEST_DIR"),
+                                               "/../data/items.json")).unwrap();
+        World::with_services(Services::new(all_entity_types(), item_defs, 1))
+    }
+
+    fn create_player(world: &mut World<Systems>, player_id: PlayerId, p: Vec2<f32>)
+                     -> ecs::Entity {
+        let entity = entities::build_net("player", player_id, &mut world.data);
+        world.with_entity_data(&entity, |e, c| {
+            c.position[e].p = p;
+            c.orientation[e].angle = 0.0;
+        }).unwrap();
+        world.flush_queue();
+        entity
+    }
+
+    /// Lets the player use a block placer with two charges, returning what is left of it
+    fn use_block_placer(world: &mut World<Systems>, player: ecs::Entity) -> Option<Item> {
+        let item = {
+            let system = &mut world.systems.player_controller_system;
+            let data = &mut world.data;
+
+            system.shape_aspect.update(&data.components);
+            data.with_entity_data(&player, |e, c| {
+                c.player_state[e].equip(0, Item::BlockPlacer { charges: 2 });
+                system.use_item(0, e, c);
+                c.player_state[e].get_item(0).map(|equipped| equipped.item.clone())
+            }).unwrap()
+        };
+        world.flush_queue();
+        item
+    }
+
+    #[test]
+    fn block_in_free_space_is_placed() {
+        let mut world = create_world();
+        let player = create_player(&mut world, 1, Vec2::new(100.0, 100.0));
+
+        assert_eq!(use_block_placer(&mut world, player), Some(Item::BlockPlacer { charges: 1 }));
+        assert_eq!(world.systems.player_controller_system.wall_aspect.iter().count(), 1);
+    }
+
+    #[test]
+    fn block_onto_player_is_refused() {
+        let mut world = create_world();
+        let player = create_player(&mut world, 1, Vec2::new(100.0, 100.0));
+        let other = create_player(&mut world, 2, Vec2::new(100.0 + BLOCK_DISTANCE, 100.0));
+
+        // No wall is built and the charge is kept
+        assert_eq!(use_block_placer(&mut world, player), Some(Item::BlockPlacer { charges: 2 }));
+        assert_eq!(world.systems.player_controller_system.wall_aspect.iter().count(), 0);
+
+        // The other player is not stuck
+        let system = &world.systems.player_controller_system;
+        let interaction = ConstWallInteraction(WallInteractionType::Slide);
+        world.data.with_entity_data(&other, |e, c| {
+            movement::move_entity(e, Vec2::new(0.0, 30.0), &interaction, &system.wall_aspect, c);
+            assert_eq!(c.position[e].p, Vec2::new(100.0 + BLOCK_DISTANCE, 130.0));
+        }).unwrap();
+    }
+}
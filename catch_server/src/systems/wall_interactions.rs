@@ -3,10 +3,15 @@ use na::Vec2;
 
 use shared::movement::{WallInteractionType, WallInteraction};
 
-use components::Components;
+use entities;
+use components::{Components, Projectile, WallType};
 use services::Services;
 use systems::projectile_system;
 
+// Damage dealt to wooden walls by projectiles hitting them
+const FRAG_WALL_DAMAGE: f32 = 3.0;
+const SHRAPNEL_WALL_DAMAGE: f32 = 1.0;
+
 pub struct ConstWallInteraction(pub WallInteractionType);
 impl WallInteraction<Components, Services> for ConstWallInteraction {
     fn apply(&self, _p: Vec2<f32>,
@@ -31,13 +36,32 @@ impl WallInteraction<Components, Services> for BouncyEnemyWallInteraction {
     }
 }
 
-/// Projectile interaction with wall
+/// Projectile interaction with wall. Bullets ricochet off iron walls, while frags and
+/// shrapnel damage wooden walls.
 pub struct ProjectileWallInteraction;
 impl WallInteraction<Components, Services> for ProjectileWallInteraction {
-    fn apply(&self, p: Vec2<f32>,
-             projectile: EntityData<Components>, _wall: EntityData<Components>,
+    fn apply(&self, _p: Vec2<f32>,
+             projectile: EntityData<Components>, wall: EntityData<Components>,
              data: &mut DataHelper<Components, Services>)
              -> WallInteractionType {
+        let projectile_type = data.projectile[projectile].clone();
+
+        match data.wall[wall].wall_type {
+            WallType::Iron => {
+                if projectile_type == Projectile::Bullet {
+                    return WallInteractionType::Flip;
+                }
+            }
+            WallType::Wood => {
+                let damage = match projectile_type {
+                    Projectile::Frag(_) => FRAG_WALL_DAMAGE,
+                    Projectile::Shrapnel => SHRAPNEL_WALL_DAMAGE,
+                    Projectile::Bullet => 0.0,
+                };
+                entities::damage_wall(wall, damage, data);
+            }
+        }
+
         projectile_system::explode(projectile, data);
         WallInteractionType::Stop
     }
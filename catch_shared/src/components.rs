@@ -1,3 +1,5 @@
+use std::f32;
+
 use na::Vec2;
 use ecs::{ComponentManager, ComponentList};
 
@@ -75,6 +77,24 @@ impl Shape {
             Shape::Rect { width, height } => width.max(height) / 2.0,
         }
     }
+
+    /// Width and height of the shape when not rotated
+    pub fn dimensions(&self) -> (f32, f32) {
+        match *self {
+            Shape::Circle { radius } => (radius * 2.0, radius * 2.0),
+            Shape::Square { size } => (size, size),
+            Shape::Rect { width, height } => (width, height),
+        }
+    }
+
+    /// Radius of a circle around the shape that contains it in any orientation
+    pub fn bounding_radius(&self) -> f32 {
+        match *self {
+            Shape::Circle { radius } => radius,
+            Shape::Square { size } => size * f32::consts::SQRT_2 / 2.0,
+            Shape::Rect { width, height } => (width * width + height * height).sqrt() / 2.0,
+        }
+    }
 }
 
 #[derive(PartialEq, Debug, Clone, RustcEncodable, RustcDecodable)]
@@ -94,12 +114,31 @@ impl Projectile {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum WallType {
     Iron,
     Wood
 }
 
+impl WallType {
+    /// Parses the material of a wall, as given in maps
+    pub fn from_material(material: &str) -> Option<WallType> {
+        match material {
+            "iron" => Some(WallType::Iron),
+            "wood" => Some(WallType::Wood),
+            _ => None
+        }
+    }
+
+    /// Name of the entity type for walls of this material
+    pub fn entity_type(&self) -> &'static str {
+        match *self {
+            WallType::Iron => "wall_iron",
+            WallType::Wood => "wall_wood",
+        }
+    }
+}
+
 impl Default for WallType {
     fn default() -> WallType {
         WallType::Iron
@@ -127,6 +166,40 @@ impl Default for WallPosition {
     }
 }
 
+/// State of a zone that players can hold, e.g. in king of the hill
+#[derive(PartialEq, Debug, Clone, RustcEncodable, RustcDecodable)]
+pub struct ZoneState {
+    pub size: Vec2<f32>,
+
+    // Player that is alone in the zone, if any
+    pub owner: Option<PlayerId>,
+
+    // More than one player is in the zone
+    pub contested: bool,
+
+    // Progress of the owner towards their next point, in range [0,1]
+    pub progress: f32,
+}
+
+impl Default for ZoneState {
+    fn default() -> ZoneState {
+        ZoneState {
+            size: Vec2::new(0.0, 0.0),
+            owner: None,
+            contested: false,
+            progress: 0.0,
+        }
+    }
+}
+
+impl ZoneState {
+    /// Is `p` inside of the zone, given that the zone is at `position`?
+    pub fn contains(&self, position: Vec2<f32>, p: Vec2<f32>) -> bool {
+        p.x >= position.x && p.x <= position.x + self.size.x &&
+        p.y >= position.y && p.y <= position.y + self.size.y
+    }
+}
+
 // Some boilerplate code for each net component type follows...
 
 pub trait HasPosition: Sized + ComponentManager {
@@ -169,6 +242,11 @@ pub trait HasWallPosition: Sized + ComponentManager {
     fn wall_position_mut(&mut self) -> &mut ComponentList<Self, WallPosition>;
 }
 
+pub trait HasZoneState: Sized + ComponentManager {
+    fn zone_state(&self) -> &ComponentList<Self, ZoneState>;
+    fn zone_state_mut(&mut self) -> &mut ComponentList<Self, ZoneState>;
+}
+
 pub trait HasWall: Sized + ComponentManager {
     fn wall(&self) -> &ComponentList<Self, Wall>;
     fn wall_mut(&mut self) -> &mut ComponentList<Self, Wall>;
@@ -178,3 +256,36 @@ pub trait HasProjectile: Sized + ComponentManager {
     fn projectile(&self) -> &ComponentList<Self, Projectile>;
     fn projectile_mut(&mut self) -> &mut ComponentList<Self, Projectile>;
 }
+
+#[cfg(test)]
+mod tests {
+    use std::f32;
+
+    use na::{Vec2, Norm};
+
+    use math;
+
+    use super::*;
+
+    #[test]
+    fn bounding_radius() {
+        assert_eq!(Shape::Circle { radius: 3.0 }.bounding_radius(), 3.0);
+        assert!((Shape::Square { size: 2.0 }.bounding_radius() - 2.0f32.sqrt()).abs() < 1.0e-5);
+        assert!((Shape::Rect { width: 6.0, height: 8.0 }.bounding_radius() - 5.0).abs() < 1.0e-5);
+    }
+
+    #[test]
+    fn bounding_radius_contains_rotated_corners() {
+        let p = Vec2::new(1.0, 2.0);
+        let shapes = [Shape::Square { size: 3.0 }, Shape::Rect { width: 10.0, height: 1.0 }];
+        for shape in shapes.iter() {
+            let (w, h) = shape.dimensions();
+            for i in 0..16 {
+                let angle = i as f32 * f32::consts::PI / 8.0;
+                for &corner in math::rect_corners(p, w, h, angle).iter() {
+                    assert!((corner - p).norm() <= shape.bounding_radius() + 1.0e-4);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use std::io::{self, BufRead};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+/// Reads lines from stdin in a background thread, so that they can be polled without blocking
+pub struct Console {
+    receiver: Receiver<String>,
+}
+
+impl Console {
+    pub fn start() -> Console {
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            let stdin = io::stdin();
+            for line in stdin.lock().lines() {
+                match line {
+                    Ok(line) => {
+                        if sender.send(line).is_err() {
+                            // Nobody is listening anymore
+                            return;
+                        }
+                    }
+                    Err(error) => {
+                        warn!("error reading from stdin: {}", error);
+                        return;
+                    }
+                }
+            }
+        });
+
+        Console {
+            receiver: receiver,
+        }
+    }
+
+    /// Returns the next line that was entered, if any
+    pub fn next_line(&mut self) -> Option<String> {
+        match self.receiver.try_recv() {
+            Ok(line) => Some(line),
+            Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => None,
+        }
+    }
+}
@@ -35,6 +35,7 @@ pub fn build_shared<T: ComponentManager +
     } else if type_name == "item" {
         data.shape_mut().add(&entity, Shape::Square { size: 5.0 });
     } else if type_name == "item_spawn" {
+    } else if type_name == "zone" {
     } else if type_name == "bullet" {
         data.shape_mut().add(&entity, Shape::Rect { width: 8.0, height: 4.0 });
         data.projectile_mut().add(&entity, Projectile::Bullet);
@@ -188,5 +189,10 @@ pub fn all_entity_types() -> EntityTypes {
               component_types: vec![ComponentType::WallPosition],
               owner_component_types: vec![],
          }),
+         ("zone".to_string(), EntityType {
+              component_types: vec![ComponentType::Position,
+                                    ComponentType::ZoneState],
+              owner_component_types: vec![],
+         }),
         ]
 }
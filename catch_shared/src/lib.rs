@@ -18,16 +18,21 @@ pub mod entities;
 pub mod movement;
 pub mod services;
 pub mod net_components;
+pub mod console;
+pub mod navigation;
+pub mod spatial_hash;
+pub mod replay;
 
 pub use map::Map;
 pub use tick::{TickState, Tick};
-pub use player::{Item, PlayerInputKey, PlayerInput, PlayerInfo, PlayerStats};
+pub use player::{Item, PlayerInputKey, PlayerInput, PlayerInfo, PlayerStats, LifetimeStats};
 pub use entities::{EntityType, EntityTypes};
 
 pub type EntityId = u32;
 pub type EntityTypeId = u32;
 
 pub type PlayerId = u32;
+pub type TeamId = u32;
 pub type PlayerInputNumber = u32;
 pub type ItemSlot = u32;
 
@@ -51,6 +56,21 @@ pub enum DeathReason {
     BouncyBall,
 }
 
+/// Phases of a match on one map
+#[derive(Debug, PartialEq, Eq, Clone, Copy, RustcEncodable, RustcDecodable)]
+pub enum MatchPhase {
+    // Waiting for enough players to join, scores don't count
+    Warmup,
+
+    // Enough players are here, the match starts soon. Players are frozen on a fresh map.
+    Countdown,
+
+    Playing,
+
+    // The match is over, showing scores before switching to the next map
+    Intermission,
+}
+
 #[derive(Debug, Clone, RustcEncodable, RustcDecodable)]
 pub enum GameEvent {
     // Player list replication
@@ -59,12 +79,34 @@ pub enum GameEvent {
     PlayerLeave(PlayerId),
     UpdatePlayerStats(Vec<(PlayerId, PlayerStats)>),
 
+    // Team replication. Team scores are only sent in modes with teams.
+    PlayerChangeTeam(PlayerId, Option<TeamId>),
+    UpdateTeamScores(Vec<(TeamId, u32)>),
+
+    // Match lifecycle
+    MatchPhaseChange {
+        phase: MatchPhase,
+        // Time until the phase ends, if it has a fixed duration
+        duration_s: Option<f32>,
+    },
+    NextMap(String),
+    ChangeMap(String),
+    ChangeTickRate(u32),
+
     PlayerDied {
         player_id: PlayerId,
         position: na::Vec2<f32>, 
         responsible_player_id: PlayerId,
         reason: DeathReason,
     },
+
+    // Tag rules: the catcher role is passed on by touching
+    PlayerTagged {
+        player_id: PlayerId,
+        position: na::Vec2<f32>,
+        responsible_player_id: PlayerId,
+    },
+    CatcherChange(Option<PlayerId>),
     
     // Entity replication
     CreateEntity(EntityId, EntityTypeId, PlayerId),
@@ -99,4 +141,8 @@ pub enum GameEvent {
         position: na::Vec2<f32>,
         strength: f32,
     },
+    WallBroken {
+        pos_a: na::Vec2<f32>,
+        pos_b: na::Vec2<f32>,
+    },
 }
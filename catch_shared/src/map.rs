@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::fs::File;
 use std::path::Path;
 
@@ -5,6 +6,8 @@ use na::Vec2;
 
 use tiled;
 
+use components::WallType;
+
 #[derive(Copy, Clone)]
 pub struct Tile {
     pub tileset: usize,
@@ -21,7 +24,16 @@ pub struct Map {
     map: tiled::Map,
     //layers: Vec<Layer>,
     pub objects: Vec<MapObject>,
-    pub lines: Vec<(Vec2<f32>, Vec2<f32>)>,
+    pub lines: Vec<MapLine>,
+}
+
+/// A wall segment, created from the polylines of a map
+pub struct MapLine {
+    pub pos_a: Vec2<f32>,
+    pub pos_b: Vec2<f32>,
+
+    // Given by the "material" property of the polyline, iron by default
+    pub wall_type: WallType,
 }
 
 /// Information about an entity on a map
@@ -31,6 +43,14 @@ pub struct MapObject {
     pub width: f32,
     pub height: f32,
     pub type_str: String,
+    pub properties: HashMap<String, String>,
+}
+
+impl MapObject {
+    /// Returns a custom property of the object, as set in Tiled
+    pub fn property(&self, name: &str) -> Option<&str> {
+        self.properties.get(name).map(|value| &value[..])
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -73,6 +93,11 @@ impl Map {
         self.height() * self.tile_height()
     }
     
+    /// Returns a custom property of the map, as set in Tiled
+    pub fn property(&self, name: &str) -> Option<&str> {
+        self.map.properties.get(name).map(|value| &value[..])
+    }
+
     pub fn is_pos_valid(&self, x: usize, y: usize) -> bool {
         x < self.map.width as usize && y < self.map.height as usize
     }
@@ -164,29 +189,44 @@ impl Map {
 
     /// Converts from tiled's MapObject to ours
     fn convert_objects(object_groups: &Vec<tiled::ObjectGroup>) 
-                       -> Result<(Vec<MapObject>, Vec<(Vec2<f32>, Vec2<f32>)>), String> {
+                       -> Result<(Vec<MapObject>, Vec<MapLine>), String> {
         let mut objects = Vec::new();
         let mut lines = Vec::new();
         for object_group in object_groups.iter() {
             for object in object_group.objects.iter() {
                 match object {
                     &tiled::Object::Rect { ref x, ref y, ref width, ref height,
-                                           ref type_str, visible: _ } => {
+                                           ref type_str, ref properties, visible: _ } => {
                         objects.push(MapObject {
                             x: *x as f32,
                             y: *y as f32,
                             width: *width as f32,
                             height: *height as f32,
                             type_str: type_str.clone(),
+                            properties: properties.clone(),
                         });
                     }
-                    &tiled::Object::Polyline { ref x, ref y, ref points, visible: _ } => {
+                    &tiled::Object::Polyline { ref x, ref y, ref points, ref properties,
+                                               visible: _ } => {
+                        let wall_type = match properties.get("material") {
+                            Some(material) => match WallType::from_material(material) {
+                                Some(wall_type) => wall_type,
+                                None => return Err(format!("Unknown wall material {}",
+                                                           material)),
+                            },
+                            None => WallType::default(),
+                        };
+
                         for i in 0..points.len()-1 {
                             let ax = x + points[i].0;
                             let ay = y + points[i].1;
                             let bx = x + points[i+1].0;
                             let by = y + points[i+1].1;
-                            lines.push((Vec2::new(ax, ay), Vec2::new(bx, by)));
+                            lines.push(MapLine {
+                                pos_a: Vec2::new(ax, ay),
+                                pos_b: Vec2::new(bx, by),
+                                wall_type: wall_type,
+                            });
                         }
                     }
                     _ =>
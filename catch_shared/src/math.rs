@@ -1,3 +1,5 @@
+use std::f32;
+
 use na::{Vec2, Mat2, Norm, Dot};
 
 pub const EPSILON: f32 = 10e-12; // TODO: Epsilon
@@ -225,18 +227,37 @@ pub fn point_moving_circle_intersection_time(p: Vec2<f32>, c: Vec2<f32>, d: Vec2
     }
 }
 
+/// If the circle at `a1` with radius `r1` moving by `v1` and the circle at `a2` with radius `r2`
+/// moving by `v2` touch, returns the smallest `t` in [0,1] at which they do
 pub fn moving_circles_intersection_time(a1: Vec2<f32>, v1: Vec2<f32>, r1: f32,
                                         a2: Vec2<f32>, v2: Vec2<f32>, r2: f32) -> Option<f32> {
-    // untested 
+    // Relative to the second circle, the center of the first one moves by v1 - v2
+    point_moving_circle_intersection_time(a2, a1, v1 - v2, r1 + r2)
+}
 
-    let b1 = a1 + v1;
-    let b2 = a2 + v2;
+/// Corners of the rectangle with center position `p` and dimensions [`w`,`h`], rotated by
+/// `angle` radians (clockwise, starting at the upper left corner)
+pub fn rect_corners(p: Vec2<f32>, w: f32, h: f32, angle: f32) -> [Vec2<f32>; 4] {
+    let rot_mat = Mat2::new(angle.cos(), -angle.sin(),
+                            angle.sin(), angle.cos());
+    let u = rot_mat * Vec2::new(0.5, 0.0) * w;
+    let v = rot_mat * Vec2::new(0.0, 0.5) * h;
 
-    let u = b1.dot(&b1) + b2.dot(&b2);
-    let v = 2.0 * (b1.dot(&a1) + b2.dot(&a2) - b2.dot(&a1) - b1.dot(&a2));
-    let w = a1.dot(&a1) + a2.dot(&a2) - (r1 + r2) * (r1 + r2);
+    [p - u - v, p + u - v, p + u + v, p - u + v]
+}
 
-    solve_second_deg(u, v, w).min_in_range(0.0, 1.0)
+/// Transforms `q` into the coordinate system of a rectangle at `p` rotated by `angle` radians
+fn rect_local(p: Vec2<f32>, angle: f32, q: Vec2<f32>) -> Vec2<f32> {
+    let d = q - p;
+    Vec2::new(d.x * angle.cos() + d.y * angle.sin(),
+              -d.x * angle.sin() + d.y * angle.cos())
+}
+
+/// Checks if the point `q` is inside of the rectangle with center position `p` and dimensions
+/// [`w`,`h`], rotated by `angle` radians
+pub fn point_in_rect(q: Vec2<f32>, p: Vec2<f32>, w: f32, h: f32, angle: f32) -> bool {
+    let l = rect_local(p, angle, q);
+    l.x.abs() <= w / 2.0 && l.y.abs() <= h / 2.0
 }
 
 /// Checks if the rectangle with center position `p_rect` and dimensions [`w`,`h`], when rotated by
@@ -244,21 +265,121 @@ pub fn moving_circles_intersection_time(a1: Vec2<f32>, v1: Vec2<f32>, r1: f32,
 pub fn rect_circle_overlap(p_rect: Vec2<f32>, w: f32, h: f32, angle: f32,
                            p_circle: Vec2<f32>, r: f32)
                            -> bool {
-    let rot_mat = Mat2::new(angle.cos(), -angle.sin(),
-                            angle.sin(), angle.cos());
-    let u = rot_mat * Vec2::new(0.5, 0.0) * w;
-    let v = rot_mat * Vec2::new(0.0, 0.5) * h;
+    // Closest point of the rectangle to the circle, in the coordinates of the rectangle. This
+    // also catches circles that are completely inside.
+    let l = rect_local(p_rect, angle, p_circle);
+    let closest = Vec2::new(l.x.max(-w / 2.0).min(w / 2.0),
+                            l.y.max(-h / 2.0).min(h / 2.0));
 
-    // Corners of the rotated rectangle (clockwise)
-    let p1 = p_rect - u - v; // upper left corner
-    let p2 = p_rect + u - v;
-    let p3 = p_rect + u + v;
-    let p4 = p_rect - u + v;
+    (l - closest).sqnorm() <= r * r
+}
+
+/// Projects points onto an axis, returning the covered interval
+fn project_onto_axis(points: &[Vec2<f32>], axis: Vec2<f32>) -> (f32, f32) {
+    points.iter().fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), p| {
+        let x = p.dot(&axis);
+        (min.min(x), max.max(x))
+    })
+}
 
-    line_segment_circle_intersection(p1, p2, p_circle, r).is_some() ||
-    line_segment_circle_intersection(p2, p3, p_circle, r).is_some() ||
-    line_segment_circle_intersection(p3, p4, p_circle, r).is_some() ||
-    line_segment_circle_intersection(p4, p1, p_circle, r).is_some() 
+/// Checks if two rotated rectangles overlap, using the separating axis theorem. Since
+/// rectangles have parallel sides, only the two axes of each rectangle need to be checked.
+pub fn rect_rect_overlap(p_a: Vec2<f32>, w_a: f32, h_a: f32, angle_a: f32,
+                         p_b: Vec2<f32>, w_b: f32, h_b: f32, angle_b: f32)
+                         -> bool {
+    let corners_a = rect_corners(p_a, w_a, h_a, angle_a);
+    let corners_b = rect_corners(p_b, w_b, h_b, angle_b);
+
+    let axes = [Vec2::new(angle_a.cos(), angle_a.sin()),
+                Vec2::new(-angle_a.sin(), angle_a.cos()),
+                Vec2::new(angle_b.cos(), angle_b.sin()),
+                Vec2::new(-angle_b.sin(), angle_b.cos())];
+
+    axes.iter().all(|&axis| {
+        let (min_a, max_a) = project_onto_axis(&corners_a, axis);
+        let (min_b, max_b) = project_onto_axis(&corners_b, axis);
+        min_a <= max_b && min_b <= max_a
+    })
+}
+
+/// Checks if the line segment from `a` to `b` touches the rectangle with center position `p`
+/// and dimensions [`w`,`h`], rotated by `angle` radians
+pub fn line_segment_rect_overlap(a: Vec2<f32>, b: Vec2<f32>,
+                                 p: Vec2<f32>, w: f32, h: f32, angle: f32)
+                                 -> bool {
+    if point_in_rect(a, p, w, h, angle) || point_in_rect(b, p, w, h, angle) {
+        return true;
+    }
+
+    let corners = rect_corners(p, w, h, angle);
+    (0..4).any(|i| {
+        match ray_line_segment_intersection(a, b - a, corners[i], corners[(i + 1) % 4]) {
+            Some((s, _)) => s <= 1.0,
+            None => false
+        }
+    })
+}
+
+/// If the rectangle with center position `p` and dimensions [`w`,`h`], rotated by `angle`
+/// radians, touches the line segment from `a` to `b` while moving to `p + d`, returns the
+/// smallest `t` in [0,1] such that the rectangle at `p + d*t` touches the segment
+pub fn line_segment_moving_rect_intersection_time(a: Vec2<f32>, b: Vec2<f32>,
+                                                  p: Vec2<f32>, w: f32, h: f32, angle: f32,
+                                                  d: Vec2<f32>)
+                                                  -> Option<f32> {
+    if line_segment_rect_overlap(a, b, p, w, h, angle) {
+        return Some(0.0);
+    }
+
+    // The first contact is either a corner of the rectangle hitting the segment, or an end
+    // point of the segment hitting an edge of the rectangle. In the frame of the rectangle,
+    // the end points move by -d.
+    let in_range = |i: Option<(f32, f32)>| {
+        match i {
+            Some((t, _)) if t <= 1.0 => Some(t),
+            _ => None
+        }
+    };
+
+    let corners = rect_corners(p, w, h, angle);
+    let mut t = None;
+    for i in 0..4 {
+        let (c, e) = (corners[i], corners[(i + 1) % 4]);
+
+        t = min_option(t, in_range(ray_line_segment_intersection(c, d, a, b)));
+        t = min_option(t, in_range(ray_line_segment_intersection(a, -d, c, e)));
+        t = min_option(t, in_range(ray_line_segment_intersection(b, -d, c, e)));
+    }
+
+    t
+}
+
+/// Checks if the line segment from `a` to `b` touches the axis-aligned box from `min` to `max`
+pub fn line_segment_aabb_overlap(a: Vec2<f32>, b: Vec2<f32>, min: Vec2<f32>, max: Vec2<f32>)
+                                 -> bool {
+    let d = b - a;
+    let mut s_min = 0.0f32;
+    let mut s_max = 1.0f32;
+
+    // Clip the segment against the slab of each axis
+    for i in 0..2 {
+        if d[i] == 0.0 {
+            if a[i] < min[i] || a[i] > max[i] {
+                return false;
+            }
+        } else {
+            let s1 = (min[i] - a[i]) / d[i];
+            let s2 = (max[i] - a[i]) / d[i];
+            s_min = s_min.max(s1.min(s2));
+            s_max = s_max.min(s1.max(s2));
+
+            if s_min > s_max {
+                return false;
+            }
+        }
+    }
+
+    true
 }
 
 pub fn min_option<T: PartialOrd>(a: Option<T>, b: Option<T>) -> Option<T> {
@@ -270,3 +391,202 @@ pub fn min_option<T: PartialOrd>(a: Option<T>, b: Option<T>) -> Option<T> {
         (None, None) => None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::f32;
+
+    use na::Vec2;
+
+    use super::*;
+
+    fn approx_eq(a: Option<f32>, b: f32) -> bool {
+        match a {
+            Some(a) => (a - b).abs() < 1.0e-4,
+            None => false
+        }
+    }
+
+    #[test]
+    fn point_moving_circle_hit() {
+        let t = point_moving_circle_intersection_time(Vec2::new(10.0, 0.0), Vec2::new(0.0, 0.0),
+                                                      Vec2::new(20.0, 0.0), 5.0);
+        assert!(approx_eq(t, 0.25), "{:?}", t);
+    }
+
+    #[test]
+    fn point_moving_circle_inside() {
+        let t = point_moving_circle_intersection_time(Vec2::new(1.0, 0.0), Vec2::new(0.0, 0.0),
+                                                      Vec2::new(20.0, 0.0), 5.0);
+        assert_eq!(t, Some(0.0));
+    }
+
+    #[test]
+    fn point_moving_circle_miss() {
+        // Passes by the point
+        let t = point_moving_circle_intersection_time(Vec2::new(10.0, 10.0), Vec2::new(0.0, 0.0),
+                                                      Vec2::new(20.0, 0.0), 5.0);
+        assert_eq!(t, None);
+
+        // Stops before reaching the point
+        let t = point_moving_circle_intersection_time(Vec2::new(10.0, 0.0), Vec2::new(0.0, 0.0),
+                                                      Vec2::new(4.0, 0.0), 5.0);
+        assert_eq!(t, None);
+
+        // Moves away from the point
+        let t = point_moving_circle_intersection_time(Vec2::new(10.0, 0.0), Vec2::new(0.0, 0.0),
+                                                      Vec2::new(-20.0, 0.0), 5.0);
+        assert_eq!(t, None);
+    }
+
+    #[test]
+    fn moving_rect_hits_segment_with_edge() {
+        let t = line_segment_moving_rect_intersection_time(Vec2::new(10.0, -10.0),
+                                                           Vec2::new(10.0, 10.0),
+                                                           Vec2::new(0.0, 0.0), 4.0, 4.0, 0.0,
+                                                           Vec2::new(20.0, 0.0));
+        assert!(approx_eq(t, 0.4), "{:?}", t);
+    }
+
+    #[test]
+    fn moving_rect_hits_segment_end_point() {
+        let t = line_segment_moving_rect_intersection_time(Vec2::new(10.0, 0.0),
+                                                           Vec2::new(20.0, 0.0),
+                                                           Vec2::new(0.0, 0.0), 4.0, 4.0, 0.0,
+                                                           Vec2::new(20.0, 0.0));
+        assert!(approx_eq(t, 0.4), "{:?}", t);
+    }
+
+    #[test]
+    fn moving_rotated_rect_hits_segment_with_corner() {
+        let t = line_segment_moving_rect_intersection_time(Vec2::new(10.0, -10.0),
+                                                           Vec2::new(10.0, 10.0),
+                                                           Vec2::new(0.0, 0.0), 2.0, 2.0,
+                                                           f32::consts::PI / 4.0,
+                                                           Vec2::new(20.0, 0.0));
+        assert!(approx_eq(t, (10.0 - 2.0f32.sqrt()) / 20.0), "{:?}", t);
+    }
+
+    #[test]
+    fn moving_rect_already_touching_segment() {
+        let t = line_segment_moving_rect_intersection_time(Vec2::new(1.0, -10.0),
+                                                           Vec2::new(1.0, 10.0),
+                                                           Vec2::new(0.0, 0.0), 4.0, 4.0, 0.0,
+                                                           Vec2::new(20.0, 0.0));
+        assert_eq!(t, Some(0.0));
+    }
+
+    #[test]
+    fn moving_rect_misses_segment() {
+        // Moves away
+        let t = line_segment_moving_rect_intersection_time(Vec2::new(10.0, -10.0),
+                                                           Vec2::new(10.0, 10.0),
+                                                           Vec2::new(0.0, 0.0), 4.0, 4.0, 0.0,
+                                                           Vec2::new(-20.0, 0.0));
+        assert_eq!(t, None);
+
+        // Stops short
+        let t = line_segment_moving_rect_intersection_time(Vec2::new(10.0, -10.0),
+                                                           Vec2::new(10.0, 10.0),
+                                                           Vec2::new(0.0, 0.0), 4.0, 4.0, 0.0,
+                                                           Vec2::new(5.0, 0.0));
+        assert_eq!(t, None);
+    }
+
+    #[test]
+    fn rect_rect_separated_and_touching() {
+        let p = Vec2::new(0.0, 0.0);
+        assert!(!rect_rect_overlap(p, 2.0, 2.0, 0.0, Vec2::new(3.0, 0.0), 2.0, 2.0, 0.0));
+        assert!(rect_rect_overlap(p, 2.0, 2.0, 0.0, Vec2::new(2.0, 0.0), 2.0, 2.0, 0.0));
+        assert!(rect_rect_overlap(p, 2.0, 2.0, 0.0, Vec2::new(0.0, -2.0), 2.0, 2.0, 0.0));
+    }
+
+    #[test]
+    fn rect_rect_containment() {
+        let p = Vec2::new(0.0, 0.0);
+        let q = Vec2::new(1.0, 1.0);
+        assert!(rect_rect_overlap(p, 10.0, 10.0, 0.0, q, 1.0, 1.0, 0.3));
+        assert!(rect_rect_overlap(q, 1.0, 1.0, 0.3, p, 10.0, 10.0, 0.0));
+    }
+
+    #[test]
+    fn rect_rect_rotated() {
+        let p = Vec2::new(0.0, 0.0);
+        let quarter = f32::consts::PI / 2.0;
+        let eighth = f32::consts::PI / 4.0;
+
+        // A corner of the rotated square reaches into the other one
+        assert!(rect_rect_overlap(p, 2.0, 2.0, 0.0, Vec2::new(2.3, 0.0), 2.0, 2.0, eighth));
+        assert!(!rect_rect_overlap(p, 2.0, 2.0, 0.0, Vec2::new(2.5, 0.0), 2.0, 2.0, eighth));
+
+        // The bounding boxes of these diamonds overlap, but they don't
+        assert!(!rect_rect_overlap(p, 2.0, 2.0, eighth, Vec2::new(2.0, 2.0), 2.0, 2.0, eighth));
+
+        // A long rectangle standing upright
+        assert!(rect_rect_overlap(p, 10.0, 1.0, quarter, Vec2::new(0.0, 4.0), 1.0, 1.0, 0.0));
+        assert!(!rect_rect_overlap(p, 10.0, 1.0, quarter, Vec2::new(4.0, 0.0), 1.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn rect_circle() {
+        let p = Vec2::new(0.0, 0.0);
+
+        // Touching an edge
+        assert!(rect_circle_overlap(p, 2.0, 2.0, 0.0, Vec2::new(2.0, 0.0), 1.0));
+        assert!(!rect_circle_overlap(p, 2.0, 2.0, 0.0, Vec2::new(2.1, 0.0), 1.0));
+
+        // Close to a corner, but not touching it
+        assert!(!rect_circle_overlap(p, 2.0, 2.0, 0.0, Vec2::new(1.6, 1.6), 0.8));
+        assert!(rect_circle_overlap(p, 2.0, 2.0, 0.0, Vec2::new(1.5, 1.5), 0.8));
+
+        // Containment in both directions
+        assert!(rect_circle_overlap(p, 2.0, 2.0, 0.0, Vec2::new(0.2, 0.2), 0.1));
+        assert!(rect_circle_overlap(p, 2.0, 2.0, 0.0, p, 10.0));
+    }
+
+    #[test]
+    fn rotated_rect_circle() {
+        let p = Vec2::new(0.0, 0.0);
+        let quarter = f32::consts::PI / 2.0;
+
+        assert!(rect_circle_overlap(p, 4.0, 1.0, quarter, Vec2::new(0.0, 2.3), 0.5));
+        assert!(!rect_circle_overlap(p, 4.0, 1.0, quarter, Vec2::new(2.3, 0.0), 0.5));
+    }
+
+    #[test]
+    fn line_segment_rect() {
+        let p = Vec2::new(0.0, 0.0);
+
+        // Crossing, inside, ending on an edge and passing by
+        assert!(line_segment_rect_overlap(Vec2::new(-5.0, 0.0), Vec2::new(5.0, 0.0),
+                                          p, 2.0, 2.0, 0.0));
+        assert!(line_segment_rect_overlap(Vec2::new(-0.5, 0.0), Vec2::new(0.5, 0.0),
+                                          p, 2.0, 2.0, 0.0));
+        assert!(line_segment_rect_overlap(Vec2::new(1.0, 0.0), Vec2::new(5.0, 0.0),
+                                          p, 2.0, 2.0, 0.0));
+        assert!(!line_segment_rect_overlap(Vec2::new(-5.0, 2.0), Vec2::new(5.0, 2.0),
+                                           p, 2.0, 2.0, 0.0));
+
+        // Stops before the rectangle
+        assert!(!line_segment_rect_overlap(Vec2::new(-5.0, 0.0), Vec2::new(-1.5, 0.0),
+                                           p, 2.0, 2.0, 0.0));
+    }
+
+    #[test]
+    fn line_segment_rotated_rect() {
+        let p = Vec2::new(0.0, 0.0);
+        let eighth = f32::consts::PI / 4.0;
+
+        // The corners of the diamond stick out further than the edges of the square
+        assert!(line_segment_rect_overlap(Vec2::new(1.2, -5.0), Vec2::new(1.2, 5.0),
+                                          p, 2.0, 2.0, eighth));
+        assert!(!line_segment_rect_overlap(Vec2::new(1.2, -5.0), Vec2::new(1.2, 5.0),
+                                           p, 2.0, 2.0, 0.0));
+
+        // ... but its sides are cut off
+        assert!(!line_segment_rect_overlap(Vec2::new(0.9, 0.9), Vec2::new(2.0, 2.0),
+                                           p, 2.0, 2.0, eighth));
+        assert!(line_segment_rect_overlap(Vec2::new(0.9, 0.9), Vec2::new(2.0, 2.0),
+                                          p, 2.0, 2.0, 0.0));
+    }
+}
@@ -4,7 +4,7 @@ use ecs::{EntityData, DataHelper, ComponentManager, ServiceManager};
 use na::{Vec2, Norm, Dot};
 
 use super::{math, GameEvent, PlayerId};
-use util::CachedAspect;
+use spatial_hash::SpatialAspect;
 use net::TimedPlayerInput;
 use player::PlayerInputKey;
 use services::HasEvents;
@@ -57,6 +57,15 @@ fn stepback(t: f32, r: f32) -> f32 {
     }
 }
 
+/// Checks that the shape at `a` does not run into the wall right away when moving by `delta`
+fn can_move(p: &WallPosition, a: Vec2<f32>, delta: Vec2<f32>, shape: &Shape, angle: f32)
+            -> bool {
+    match moving_shape_wall_intersection_time(p, a, delta, shape, angle) {
+        Some(t) => t > 0.0,
+        None => true
+    }
+}
+
 /// Moves an entity while checking for intersections with walls.
 /// If there is an intersection, the given `interaction` is called.
 pub fn move_entity<Components: ComponentManager,
@@ -64,7 +73,7 @@ pub fn move_entity<Components: ComponentManager,
                   (e: EntityData<Components>,
                    delta: Vec2<f32>,
                    interaction: &WallInteraction<Components, Services>,
-                   wall_aspect: &CachedAspect<Components>,
+                   wall_aspect: &SpatialAspect<Components>,
                    c: &mut DataHelper<Components, Services>)
         where Components: HasPosition + HasLinearVelocity + HasShape +
                           HasOrientation + HasWallPosition {
@@ -74,12 +83,17 @@ pub fn move_entity<Components: ComponentManager,
 
     let shape = c.shape()[e].clone();
     let a = c.position()[e].p;
+    let angle = c.orientation()[e].angle;
     let r = shape.radius();
 
     //println!("moving a {:?} by {:?}", shape, delta);
 
-    let intersection = moving_shape_walls_intersection_time(a, delta, &shape, wall_aspect, c);
-    c.position_mut()[e].p = match intersection {
+    // Reused for every query of walls
+    let mut walls = Vec::new();
+
+    let intersection = moving_shape_walls_intersection_time(a, delta, &shape, angle,
+                                                            wall_aspect, &mut walls, c);
+    let new_a = match intersection {
         Some((t, _, wall)) if t <= 1.0 => {
             // We hit a wall, ask `interaction` what to do
             match interaction.apply(a + delta * t, e, wall, c) {
@@ -91,43 +105,40 @@ pub fn move_entity<Components: ComponentManager,
                     let v = delta - u;
 
                     // Move into parallel and orthogonal directions individually
-                    let intersection = moving_shape_walls_intersection_time(a, u, &shape,
-                                                                            wall_aspect, c);
+                    let intersection = moving_shape_walls_intersection_time(a, u, &shape, angle,
+                                                                            wall_aspect,
+                                                                            &mut walls, c);
                     let new_a = match intersection {
                         Some((t, _, wall)) if t <= 1.0 => {
                             let xx = a + u * stepback(t, r);
-                            let p = c.wall_position()[wall].clone();
-                            let i = math::line_segment_moving_circle_intersection_time(p.pos_a, p.pos_b,
-                                                                                       xx, u,
-                                                                                       shape.radius());
-                            if !(i.is_none() || i.unwrap() > 0.0) {
-                                println!("ERROR! shape: {:?}, pos_a: {:?}, pos_b: {:?}, a: {:?}, delta: {:?}, t: {}", shape, p.pos_a, p.pos_b, a, u, t);
-                                assert!(false);
+                            if can_move(&c.wall_position()[wall], xx, u, &shape, angle) {
+                                Some(xx)
+                            } else {
+                                None
                             }
-                            xx
                         }
-                        _ => a + u
+                        _ => Some(a + u)
                     };
 
-                    let intersection = moving_shape_walls_intersection_time(new_a, v, &shape,
-                                                                            wall_aspect, c);
-                    let new_a = match intersection {
-                        Some((t, _, wall)) if t <= 1.0 => {
-                            let xx = new_a + v * stepback(t, r);
-                            let p = c.wall_position()[wall].clone();
-                            let i = math::line_segment_moving_circle_intersection_time(p.pos_a, p.pos_b,
-                                                                                       xx, v,
-                                                                                       shape.radius());
-                            //assert!(i.is_none() || i.unwrap() > 0.0);
-                            if !(i.is_none() || i.unwrap() > 0.0) {
-                                println!("ERROR! shape: {:?}, pos_a: {:?}, pos_b: {:?}, a: {:?}, delta: {:?}, t: {}", shape, p.pos_a, p.pos_b, a, v, t);
-                                assert!(false);
+                    match new_a {
+                        Some(new_a) => {
+                            let intersection =
+                                moving_shape_walls_intersection_time(new_a, v, &shape, angle,
+                                                                     wall_aspect, &mut walls, c);
+                            match intersection {
+                                Some((t, _, wall)) if t <= 1.0 => {
+                                    let xx = new_a + v * stepback(t, r);
+                                    if can_move(&c.wall_position()[wall], xx, v, &shape, angle) {
+                                        Some(xx)
+                                    } else {
+                                        None
+                                    }
+                                }
+                                _ => Some(new_a + v)
                             }
-                            xx
                         }
-                        _ => new_a + v
-                    };
-                    new_a
+                        None => None
+                    }
                 }
                 WallInteractionType::Flip => {
                     let n_angle = wall_orientation(&c.wall_position()[wall]);
@@ -145,17 +156,12 @@ pub fn move_entity<Components: ComponentManager,
                     );
 
                     let xx = a + delta * stepback(t, r);
-                    let p = c.wall_position()[wall].clone();
-                    let i = math::line_segment_moving_circle_intersection_time(p.pos_a, p.pos_b,
-                                                                               xx, delta,
-                                                                               shape.radius());
-                    if !(i.is_none() || i.unwrap() > 0.0) {
-                        println!("ERROR! shape: {:?}, pos_a: {:?}, pos_b: {:?}, a: {:?}, delta: {:?}, t: {}", shape, p.pos_a, p.pos_b, a, delta, t);
-                        assert!(false);
+                    if can_move(&c.wall_position()[wall], xx, delta, &shape, angle) {
+                        Some(xx)
+                    } else {
+                        None
                     }
 
-                    xx
-
                     // TODO: Actually at this point we still might have some 't' left to walk
                 }
                 WallInteractionType::Stop => {
@@ -164,21 +170,24 @@ pub fn move_entity<Components: ComponentManager,
                     //println!("delta: {:?}, moving t: {:.32}, a: {:?} -> p: {:?}", delta, t, a, xx);
 
                     c.linear_velocity_mut()[e].v = Vec2::new(0.0, 0.0);
-                    let p = c.wall_position()[wall].clone();
-                    let delta = delta;
-                    /*let i = math::line_segment_moving_circle_intersection_time(p.pos_a, p.pos_b,
-                                                                               xx, delta,
-                                                                               shape.radius());
-                    println!("new intersection: {:?}", i);
-                    assert!(i.is_none() || i.unwrap() > 0.0);*/
-
-                    xx
+
+                    Some(xx)
                 }
             }
         }
-        _ => a + delta
+        _ => Some(a + delta)
     };
-    //println!("------------------------------------");
+
+    match new_a {
+        Some(new_a) => c.position_mut()[e].p = new_a,
+        None => {
+            // The shape already touched the wall before moving, e.g. because a wall was built
+            // on top of it. Stay put instead of moving into the wall.
+            warn!("{:?} at {:?} is stuck in a wall, not moving it by {:?}", shape, a, delta);
+            c.position_mut()[e].p = a;
+            c.linear_velocity_mut()[e].v = Vec2::new(0.0, 0.0);
+        }
+    }
 }
 
 /// Player interaction with wall
@@ -228,7 +237,7 @@ pub fn run_player_movement_input<Components: ComponentManager,
                                 (e: EntityData<Components>,
                                  owner: PlayerId,
                                  timed_input: &TimedPlayerInput,
-                                 wall_aspect: &CachedAspect<Components>,
+                                 wall_aspect: &SpatialAspect<Components>,
                                  c: &mut DataHelper<Components, Services>) 
     where Components: HasPosition + HasLinearVelocity + 
                       HasOrientation + HasAngularVelocity + 
@@ -244,6 +253,7 @@ pub fn run_player_movement_input<Components: ComponentManager,
     const MIN_SPEED: f32 = 5.0;
     const DASH_SPEED: f32 = 600.0;
     const DASH_DURATION_S: f32 = 0.3;
+    const SPEED_BOOST_FACTOR: f32 = 1.6;
 
     let dur_s = timed_input.duration_s;
     let input = &timed_input.input;
@@ -255,6 +265,12 @@ pub fn run_player_movement_input<Components: ComponentManager,
             if dash_cooldown_s <= 0.0 { None }
             else { Some(dash_cooldown_s) };
     }
+    if let Some(speed_boost_s) = c.full_player_state()[e].speed_boost_s {
+        let speed_boost_s = speed_boost_s - dur_s;
+        c.full_player_state_mut()[e].speed_boost_s =
+            if speed_boost_s <= 0.0 { None }
+            else { Some(speed_boost_s) };
+    }
     if let Some(inv_s) = c.player_state()[e].invulnerable_s {
         let inv_s = inv_s - dur_s;
         c.player_state_mut()[e].invulnerable_s =
@@ -290,14 +306,21 @@ pub fn run_player_movement_input<Components: ComponentManager,
 
         let mut accel = c.linear_velocity_mut()[e].v * -MOVE_FRICTION;
 
+        // Speed boosts scale the acceleration, so that the top speed grows as well
+        let boost = if c.full_player_state()[e].speed_boost_s.is_some() {
+            SPEED_BOOST_FACTOR
+        } else {
+            1.0
+        };
+
         if input.has(PlayerInputKey::StrafeLeft) {
             c.angular_velocity_mut()[e].v = 0.0;
             let strafe_direction = Vec2::new(direction[1], -direction[0]);
-            accel = -strafe_direction * STRAFE_ACCEL + accel;
+            accel = -strafe_direction * STRAFE_ACCEL * boost + accel;
         } else if input.has(PlayerInputKey::StrafeRight) {
             c.angular_velocity_mut()[e].v = 0.0;
             let strafe_direction = Vec2::new(direction[1], -direction[0]);
-            accel = strafe_direction * STRAFE_ACCEL + accel;
+            accel = strafe_direction * STRAFE_ACCEL * boost + accel;
         } else {
             // Turn left/right
             let mut ang_accel = c.angular_velocity()[e].v * -TURN_FRICTION;
@@ -314,10 +337,10 @@ pub fn run_player_movement_input<Components: ComponentManager,
 
         // Move forward/backward
         if input.has(PlayerInputKey::Forward) {
-            accel = direction * MOVE_ACCEL + accel;
+            accel = direction * MOVE_ACCEL * boost + accel;
         }
         if input.has(PlayerInputKey::Back) {
-            accel = -direction * BACK_ACCEL + accel;
+            accel = -direction * BACK_ACCEL * boost + accel;
         }
 
         c.linear_velocity_mut()[e].v = c.linear_velocity()[e].v + accel * dur_s;
@@ -344,52 +367,78 @@ pub fn run_player_movement_input<Components: ComponentManager,
     }
 }
 
+/// Checks if the shape at `a`, rotated by `angle` radians, touches the wall
+pub fn shape_wall_overlap(p: &WallPosition, a: Vec2<f32>, shape: &Shape, angle: f32) -> bool {
+    match *shape {
+        Shape::Circle { radius } =>
+            math::point_line_segment_distance(a, p.pos_a, p.pos_b) <= radius,
+        Shape::Square { size } =>
+            math::line_segment_rect_overlap(p.pos_a, p.pos_b, a, size, size, angle),
+        Shape::Rect { width, height } =>
+            math::line_segment_rect_overlap(p.pos_a, p.pos_b, a, width, height, angle),
+    }
+}
+
+/// If the shape rotated by `angle` radians touches the wall while moving from `a` to
+/// `a + delta`, returns the smallest `t` in [0,1] at which it touches
+pub fn moving_shape_wall_intersection_time(p: &WallPosition, a: Vec2<f32>, delta: Vec2<f32>,
+                                           shape: &Shape, angle: f32)
+                                           -> Option<f32> {
+    match *shape {
+        Shape::Circle { radius } =>
+            math::line_segment_moving_circle_intersection_time(p.pos_a, p.pos_b,
+                                                               a, delta, radius),
+        Shape::Square { size } =>
+            math::line_segment_moving_rect_intersection_time(p.pos_a, p.pos_b,
+                                                             a, size, size, angle, delta),
+        Shape::Rect { width, height } =>
+            math::line_segment_moving_rect_intersection_time(p.pos_a, p.pos_b,
+                                                             a, width, height, angle, delta),
+    }
+}
+
 pub fn moving_shape_walls_intersection_time<'a,
                                             Components: ComponentManager,
                                             Services: ServiceManager>
                                            (a: Vec2<f32>, delta: Vec2<f32>, shape: &Shape,
-                                            wall_aspect: &'a CachedAspect<Components>,
+                                            angle: f32,
+                                            wall_aspect: &'a SpatialAspect<Components>,
+                                            walls: &mut Vec<EntityData<'a, Components>>,
                                             data: &DataHelper<Components, Services>)
                                            -> Option<(f32, f32, EntityData<'a, Components>)>
     where Components: HasWallPosition {
-    match *shape {
-        Shape::Circle { radius } => {
-            let mut closest_i = None;
-
-            for wall in wall_aspect.iter::<'a>() {
-                let p = data.wall_position()[wall].clone();
-                let i = math::line_segment_moving_circle_intersection_time(p.pos_a, p.pos_b,
-                                                                           a, delta, radius);
-                if let Some((t, s)) = i.map(|t| (t, 0.0) /* TODO */) {
-                    closest_i = if let Some((closest_t, _, _)) = closest_i {
-                        if t < closest_t { Some((t, s, wall)) }
-                        else { closest_i }
-                    } else {
-                        Some((t, s, wall))
-                    };
-                }
-            }
+    let mut closest_i = None;
 
-            closest_i
-        }
-        _ => {
-            // TODO
-            ray_walls_intersection(a, delta, wall_aspect, data)
+    wall_aspect.query_segment(a, a + delta, shape.bounding_radius(), walls);
+    for &wall in walls.iter() {
+        let p = data.wall_position()[wall].clone();
+        let i = moving_shape_wall_intersection_time(&p, a, delta, shape, angle);
+        if let Some((t, s)) = i.map(|t| (t, 0.0) /* TODO */) {
+            closest_i = if let Some((closest_t, _, _)) = closest_i {
+                if t < closest_t { Some((t, s, wall)) }
+                else { closest_i }
+            } else {
+                Some((t, s, wall))
+            };
         }
     }
+
+    closest_i
 }
 
 pub fn ray_walls_intersection<'a,
                               Components: ComponentManager,
                               Services: ServiceManager>
                              (a: Vec2<f32>, delta: Vec2<f32>,
-                              wall_aspect: &'a CachedAspect<Components>,
+                              wall_aspect: &'a SpatialAspect<Components>,
+                              walls: &mut Vec<EntityData<'a, Components>>,
                               data: &DataHelper<Components, Services>)
                               -> Option<(f32, f32, EntityData<'a, Components>)>
     where Components: HasWallPosition {
     let mut closest_i = None;
 
-    for wall in wall_aspect.iter::<'a>() {
+    wall_aspect.query_segment(a, a + delta, 0.0, walls);
+    for &wall in walls.iter() {
         let p = data.wall_position()[wall].clone();
         let i = math::ray_line_segment_intersection(a, delta, p.pos_a, p.pos_b);
 
@@ -0,0 +1,487 @@
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet, BinaryHeap};
+use std::mem;
+
+use na::{Vec2, Norm, Dot};
+
+use math;
+use util::{FixedHashMap, FixedHashSet};
+use EntityId;
+
+// Nodes are placed this much further out than the corners of the inflated walls, so that edges
+// starting at a corner don't touch the wall itself
+const NODE_MARGIN: f32 = 1.05;
+
+pub type NodeId = u32;
+
+/// A wall segment grown by the radius of the entities that navigate around it, giving a
+/// rectangle
+struct InflatedWall {
+    a: Vec2<f32>,
+    length: f32,
+
+    // Unit vectors along and perpendicular to the wall
+    d: Vec2<f32>,
+    n: Vec2<f32>,
+
+    radius: f32,
+}
+
+impl InflatedWall {
+    fn new(a: Vec2<f32>, b: Vec2<f32>, radius: f32) -> InflatedWall {
+        let length = (b - a).norm();
+        let d = if length > 0.0 { (b - a) / length } else { Vec2::new(1.0, 0.0) };
+
+        InflatedWall {
+            a: a,
+            length: length,
+            d: d,
+            n: Vec2::new(-d.y, d.x),
+            radius: radius,
+        }
+    }
+
+    /// Corners of the rectangle, each scaled by `scale` away from the wall
+    fn corners(&self, scale: f32) -> [Vec2<f32>; 4] {
+        let r = self.radius * scale;
+        let b = self.a + self.d * self.length;
+
+        [self.a - self.d * r - self.n * r,
+         b + self.d * r - self.n * r,
+         b + self.d * r + self.n * r,
+         self.a - self.d * r + self.n * r]
+    }
+
+    fn contains(&self, p: Vec2<f32>) -> bool {
+        let along = (p - self.a).dot(&self.d);
+        let across = (p - self.a).dot(&self.n);
+
+        along > -self.radius && along < self.length + self.radius &&
+        across.abs() < self.radius
+    }
+
+    /// Does the line segment from `p` to `q` cross the rectangle?
+    fn blocks(&self, p: Vec2<f32>, q: Vec2<f32>) -> bool {
+        let corners = self.corners(1.0);
+
+        (0..4).any(|i| {
+            match math::ray_line_segment_intersection(p, q - p, corners[i], corners[(i + 1) % 4]) {
+                Some((s, _)) => s <= 1.0,
+                None => false
+            }
+        })
+    }
+}
+
+struct Node {
+    position: Vec2<f32>,
+
+    // Nodes inside of other walls can't be reached
+    active: bool,
+}
+
+// Entry of the open list in A*, ordered such that the `BinaryHeap` pops the lowest estimate first
+#[derive(PartialEq)]
+struct OpenNode {
+    estimate: f32,
+    node: NodeId,
+}
+
+impl Eq for OpenNode {}
+
+impl PartialOrd for OpenNode {
+    fn partial_cmp(&self, other: &OpenNode) -> Option<Ordering> {
+        other.estimate.partial_cmp(&self.estimate)
+    }
+}
+
+impl Ord for OpenNode {
+    fn cmp(&self, other: &OpenNode) -> Ordering {
+        self.partial_cmp(other).unwrap_or(Ordering::Equal)
+    }
+}
+
+/// Visibility graph for finding paths around walls. Nodes are placed at the corners of every
+/// wall, inflated by the radius of the navigating entity, and connected if there is a line of
+/// sight between them. Walls can be added and removed at any time. The changes are applied
+/// together in `update`, so that loading a whole map only builds the graph once.
+pub struct NavGraph {
+    radius: f32,
+
+    walls: FixedHashMap<EntityId, InflatedWall>,
+
+    // Changes to the walls that are not in the graph yet
+    added_walls: Vec<(EntityId, InflatedWall)>,
+    removed_walls: Vec<EntityId>,
+
+    // Nodes of each wall
+    wall_nodes: FixedHashMap<EntityId, Vec<NodeId>>,
+
+    nodes: FixedHashMap<NodeId, Node>,
+    edges: FixedHashMap<NodeId, FixedHashSet<NodeId>>,
+
+    node_id_counter: NodeId,
+}
+
+impl NavGraph {
+    /// Creates an empty graph for entities with the given radius
+    pub fn new(radius: f32) -> NavGraph {
+        NavGraph {
+            radius: radius,
+            walls: FixedHashMap::default(),
+            added_walls: Vec::new(),
+            removed_walls: Vec::new(),
+            wall_nodes: FixedHashMap::default(),
+            nodes: FixedHashMap::default(),
+            edges: FixedHashMap::default(),
+            node_id_counter: 0,
+        }
+    }
+
+    pub fn num_nodes(&self) -> usize {
+        self.nodes.values().filter(|node| node.active).count()
+    }
+
+    /// Is there a straight path from `p` to `q`? Walls containing either point are ignored, so
+    /// that entities touching a wall can still move away from it. Wall changes since the last
+    /// `update` are not considered.
+    pub fn line_of_sight(&self, p: Vec2<f32>, q: Vec2<f32>) -> bool {
+        self.walls.values().all(|wall| {
+            wall.contains(p) || wall.contains(q) || !wall.blocks(p, q)
+        })
+    }
+
+    /// Queues a wall to be added to the graph in the next `update`
+    pub fn add_wall(&mut self, id: EntityId, a: Vec2<f32>, b: Vec2<f32>) {
+        assert!(self.walls.get(&id).is_none() || self.removed_walls.contains(&id),
+                "wall {} is already in the graph", id);
+        assert!(self.added_walls.iter().all(|&(added_id, _)| added_id != id),
+                "wall {} is already being added to the graph", id);
+
+        let wall = InflatedWall::new(a, b, self.radius);
+        self.added_walls.push((id, wall));
+    }
+
+    /// Queues a wall to be removed from the graph in the next `update`
+    pub fn remove_wall(&mut self, id: EntityId) {
+        if let Some(index) = self.added_walls.iter().position(|&(added_id, _)| added_id == id) {
+            self.added_walls.remove(index);
+        } else if self.walls.get(&id).is_some() && !self.removed_walls.contains(&id) {
+            self.removed_walls.push(id);
+        }
+    }
+
+    /// Applies the queued wall changes to the graph
+    pub fn update(&mut self) {
+        let removed_walls = mem::replace(&mut self.removed_walls, Vec::new());
+        if !removed_walls.is_empty() {
+            self.apply_removed_walls(removed_walls);
+        }
+
+        let added_walls = mem::replace(&mut self.added_walls, Vec::new());
+        if !added_walls.is_empty() {
+            self.apply_added_walls(added_walls);
+        }
+    }
+
+    /// Finds a short path from `start` to `goal` around the walls. The returned waypoints
+    /// exclude `start` and end with `goal`.
+    pub fn find_path(&self, start: Vec2<f32>, goal: Vec2<f32>) -> Option<Vec<Vec2<f32>>> {
+        if self.line_of_sight(start, goal) {
+            return Some(vec![goal]);
+        }
+
+        // The start and goal are not part of the graph, so we search from the nodes visible
+        // from the start, and finish at nodes visible from the goal
+        let active = self.active_nodes();
+        let goal_nodes = active.iter().cloned()
+                               .filter(|id| self.line_of_sight(self.nodes[id].position, goal))
+                               .collect::<HashSet<_>>();
+        if goal_nodes.is_empty() {
+            return None;
+        }
+
+        let mut open = BinaryHeap::new();
+        let mut costs: HashMap<NodeId, f32> = HashMap::new();
+        let mut came_from: HashMap<NodeId, NodeId> = HashMap::new();
+        let mut closed = HashSet::new();
+
+        for &id in active.iter() {
+            let p = self.nodes[&id].position;
+            if self.line_of_sight(start, p) {
+                let cost = (p - start).norm();
+                costs.insert(id, cost);
+                open.push(OpenNode {
+                    estimate: cost + (goal - p).norm(),
+                    node: id,
+                });
+            }
+        }
+
+        while let Some(OpenNode { node, .. }) = open.pop() {
+            if closed.contains(&node) {
+                continue;
+            }
+            closed.insert(node);
+
+            let p = self.nodes[&node].position;
+
+            if goal_nodes.contains(&node) {
+                let mut path = vec![goal, p];
+                let mut current = node;
+                while let Some(&previous) = came_from.get(&current) {
+                    path.push(self.nodes[&previous].position);
+                    current = previous;
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            for &neighbor in self.edges[&node].iter() {
+                let q = self.nodes[&neighbor].position;
+                let cost = costs[&node] + (q - p).norm();
+
+                if costs.get(&neighbor).map_or(true, |&c| cost < c) {
+                    costs.insert(neighbor, cost);
+                    came_from.insert(neighbor, node);
+                    open.push(OpenNode {
+                        estimate: cost + (goal - q).norm(),
+                        node: neighbor,
+                    });
+                }
+            }
+        }
+
+        None
+    }
+
+    fn apply_added_walls(&mut self, added_walls: Vec<(EntityId, InflatedWall)>) {
+        // Nodes inside of the new walls are no longer reachable, and edges crossing them are
+        // no longer valid
+        let inside = self.nodes.iter()
+                         .filter(|&(_, node)| {
+                             node.active &&
+                             added_walls.iter().any(|&(_, ref wall)| wall.contains(node.position))
+                         })
+                         .map(|(&id, _)| id)
+                         .collect::<Vec<_>>();
+        for node_id in inside {
+            self.deactivate(node_id);
+        }
+
+        let mut blocked = Vec::new();
+        for (&u, neighbors) in self.edges.iter() {
+            for &v in neighbors.iter() {
+                let p = self.nodes[&u].position;
+                let q = self.nodes[&v].position;
+
+                if u < v && added_walls.iter().any(|&(_, ref wall)| wall.blocks(p, q)) {
+                    blocked.push((u, v));
+                }
+            }
+        }
+        for (u, v) in blocked {
+            self.edges.get_mut(&u).unwrap().remove(&v);
+            self.edges.get_mut(&v).unwrap().remove(&u);
+        }
+
+        let mut new_nodes = Vec::new();
+        for (id, wall) in added_walls {
+            let corners = wall.corners(NODE_MARGIN);
+            self.walls.insert(id, wall);
+
+            let mut node_ids = Vec::new();
+            for &corner in corners.iter() {
+                self.node_id_counter += 1;
+                let node_id = self.node_id_counter;
+
+                self.nodes.insert(node_id, Node {
+                    position: corner,
+                    active: false,
+                });
+                self.edges.insert(node_id, FixedHashSet::default());
+                node_ids.push(node_id);
+            }
+
+            new_nodes.extend(node_ids.iter().cloned());
+            self.wall_nodes.insert(id, node_ids);
+        }
+
+        // Only now that all the walls are in place, connect the new nodes
+        self.activate(new_nodes);
+    }
+
+    fn apply_removed_walls(&mut self, removed_walls: Vec<EntityId>) {
+        let mut walls = Vec::new();
+        for id in removed_walls {
+            walls.push(self.walls.remove(&id).unwrap());
+
+            for node_id in self.wall_nodes.remove(&id).unwrap() {
+                self.deactivate(node_id);
+                self.nodes.remove(&node_id);
+                self.edges.remove(&node_id);
+            }
+        }
+
+        // Nodes that were inside of the walls may be reachable now
+        let inside = self.nodes.iter()
+                         .filter(|&(_, node)| {
+                             !node.active &&
+                             walls.iter().any(|wall| wall.contains(node.position))
+                         })
+                         .map(|(&id, _)| id)
+                         .collect::<Vec<_>>();
+        self.activate(inside);
+
+        // Pairs of nodes that were separated by one of these walls may see each other now
+        let active = self.active_nodes();
+        for (i, &u) in active.iter().enumerate() {
+            for &v in active[i+1..].iter() {
+                let p = self.nodes[&u].position;
+                let q = self.nodes[&v].position;
+
+                if !self.edges[&u].contains(&v) && walls.iter().any(|wall| wall.blocks(p, q)) &&
+                   self.line_of_sight(p, q) {
+                    self.connect(u, v);
+                }
+            }
+        }
+    }
+
+    fn active_nodes(&self) -> Vec<NodeId> {
+        self.nodes.iter()
+            .filter(|&(_, node)| node.active)
+            .map(|(&id, _)| id)
+            .collect()
+    }
+
+    fn connect(&mut self, u: NodeId, v: NodeId) {
+        self.edges.get_mut(&u).unwrap().insert(v);
+        self.edges.get_mut(&v).unwrap().insert(u);
+    }
+
+    fn deactivate(&mut self, node_id: NodeId) {
+        self.nodes.get_mut(&node_id).unwrap().active = false;
+
+        let neighbors = self.edges.get_mut(&node_id).unwrap().drain().collect::<Vec<_>>();
+        for neighbor in neighbors {
+            self.edges.get_mut(&neighbor).unwrap().remove(&node_id);
+        }
+    }
+
+    /// Activates the nodes that are outside of all walls and connects them to the visible nodes
+    fn activate(&mut self, node_ids: Vec<NodeId>) {
+        let mut activated = Vec::new();
+        for node_id in node_ids {
+            let p = self.nodes[&node_id].position;
+            if !self.walls.values().any(|wall| wall.contains(p)) {
+                self.nodes.get_mut(&node_id).unwrap().active = true;
+                activated.push(node_id);
+            }
+        }
+
+        let active = self.active_nodes();
+        for &u in activated.iter() {
+            for &v in active.iter() {
+                if u != v && !self.edges[&u].contains(&v) &&
+                   self.line_of_sight(self.nodes[&u].position, self.nodes[&v].position) {
+                    self.connect(u, v);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use na::Vec2;
+
+    use EntityId;
+    use super::*;
+
+    fn add_box(graph: &mut NavGraph, half_size: f32) {
+        let corners = [Vec2::new(-half_size, -half_size), Vec2::new(half_size, -half_size),
+                       Vec2::new(half_size, half_size), Vec2::new(-half_size, half_size)];
+        for i in 0..4 {
+            graph.add_wall(i as EntityId, corners[i], corners[(i + 1) % 4]);
+        }
+    }
+
+    fn assert_walkable(graph: &NavGraph, start: Vec2<f32>, path: &[Vec2<f32>]) {
+        let mut p = start;
+        for &q in path.iter() {
+            assert!(graph.line_of_sight(p, q), "no line of sight from {:?} to {:?}", p, q);
+            p = q;
+        }
+    }
+
+    #[test]
+    fn straight_path_without_walls() {
+        let mut graph = NavGraph::new(8.0);
+        graph.update();
+
+        let goal = Vec2::new(100.0, 50.0);
+        assert_eq!(graph.find_path(Vec2::new(0.0, 0.0), goal), Some(vec![goal]));
+    }
+
+    #[test]
+    fn path_around_wall() {
+        let mut graph = NavGraph::new(8.0);
+        graph.add_wall(1, Vec2::new(0.0, -50.0), Vec2::new(0.0, 50.0));
+        graph.update();
+        assert_eq!(graph.num_nodes(), 4);
+
+        let start = Vec2::new(-30.0, 0.0);
+        let goal = Vec2::new(30.0, 0.0);
+        assert!(!graph.line_of_sight(start, goal));
+
+        let path = graph.find_path(start, goal).unwrap();
+        assert!(path.len() > 1);
+        assert_eq!(*path.last().unwrap(), goal);
+        assert_walkable(&graph, start, &path);
+    }
+
+    #[test]
+    fn no_path_into_closed_box() {
+        let mut graph = NavGraph::new(8.0);
+        add_box(&mut graph, 20.0);
+        graph.update();
+
+        assert_eq!(graph.find_path(Vec2::new(100.0, 0.0), Vec2::new(0.0, 0.0)), None);
+    }
+
+    #[test]
+    fn removed_wall_opens_path() {
+        let mut graph = NavGraph::new(8.0);
+        add_box(&mut graph, 20.0);
+        graph.update();
+
+        // Open the bottom side, then walk in from below the box
+        graph.remove_wall(0);
+        graph.update();
+        assert_eq!(graph.num_nodes(), 12);
+
+        let start = Vec2::new(100.0, 0.0);
+        let goal = Vec2::new(0.0, 0.0);
+        let path = graph.find_path(start, goal).unwrap();
+        assert_eq!(*path.last().unwrap(), goal);
+        assert_walkable(&graph, start, &path);
+    }
+
+    #[test]
+    fn queued_walls_are_applied_on_update() {
+        let mut graph = NavGraph::new(8.0);
+        graph.add_wall(1, Vec2::new(0.0, -50.0), Vec2::new(0.0, 50.0));
+        graph.add_wall(2, Vec2::new(100.0, -50.0), Vec2::new(100.0, 50.0));
+        graph.remove_wall(2);
+
+        // Nothing is in the graph before the update
+        assert_eq!(graph.num_nodes(), 0);
+        assert!(graph.line_of_sight(Vec2::new(-30.0, 0.0), Vec2::new(30.0, 0.0)));
+
+        graph.update();
+        assert_eq!(graph.num_nodes(), 4);
+        assert!(!graph.line_of_sight(Vec2::new(-30.0, 0.0), Vec2::new(30.0, 0.0)));
+        assert!(graph.line_of_sight(Vec2::new(70.0, 0.0), Vec2::new(130.0, 0.0)));
+    }
+}
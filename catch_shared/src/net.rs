@@ -1,4 +1,4 @@
-use super::{PlayerInput, TickNumber, PlayerId, GameInfo};
+use super::{PlayerInput, TickNumber, PlayerId, GameInfo, LifetimeStats};
 
 #[derive(Debug, Clone)]
 pub enum Channel {
@@ -22,7 +22,11 @@ pub enum ClientMessage {
     PlayerInput(TimedPlayerInput),
     StartingTick {
         tick: TickNumber,
-    }
+    },
+    AdminCommand {
+        password: String,
+        command: String,
+    },
 }
 
 #[derive(Debug, Clone, RustcEncodable, RustcDecodable)]
@@ -32,4 +36,11 @@ pub enum ServerMessage {
         your_id: PlayerId,
         game_info: GameInfo,
     },
+    AdminReply(String),
+    Kicked {
+        reason: String,
+    },
+
+    // Best players by name over all matches on the server, best first
+    Leaderboard(Vec<(String, LifetimeStats)>),
 }
@@ -187,6 +187,7 @@ net_components! {
         player_state, player_state_mut: PlayerState, HasPlayerState,
         full_player_state, full_player_state_mut: FullPlayerState, HasFullPlayerState,
         wall_position, wall_position_mut: WallPosition, HasWallPosition,
+        zone_state, zone_state_mut: ZoneState, HasZoneState,
     }
     enum ComponentType;
     const COMPONENT_TYPES;
@@ -1,6 +1,7 @@
 use std::fmt;
+use std::collections::BTreeMap;
 
-use super::{ItemSlot, NUM_ITEM_SLOTS};
+use super::{ItemSlot, NUM_ITEM_SLOTS, TeamId};
 
 #[derive(PartialEq, Clone, Debug, RustcEncodable, RustcDecodable)]
 pub enum Item {
@@ -23,14 +24,15 @@ pub enum Item {
 }
 
 impl Item {
-    pub fn cooldown_s(&self) -> Option<f32> {
+    /// Name of the kind of item, as used in item definition files
+    pub fn name(&self) -> &'static str {
         match *self {
-            Item::Weapon { charges: _ } => Some(0.5),
-            Item::FragWeapon { charges: _ } => Some(1.5),
-            Item::SpeedBoost { duration_s: _ } => None,
-            Item::BlockPlacer { charges: _ } => Some(5.0),
-            Item::BallSpawner { charges: _ } => Some(2.5),
-            Item::Shield => None,
+            Item::Weapon { .. } => "weapon",
+            Item::FragWeapon { .. } => "frag_weapon",
+            Item::SpeedBoost { .. } => "speed_boost",
+            Item::BlockPlacer { .. } => "block_placer",
+            Item::BallSpawner { .. } => "ball_spawner",
+            Item::Shield => "shield",
         }
     }
 }
@@ -46,6 +48,9 @@ pub struct FullPlayerState {
 
     // Flip at walls?
     pub wall_flip: bool,
+
+    // Time left of a speed boost
+    pub speed_boost_s: Option<f32>,
 }
 
 #[derive(PartialEq, Clone, RustcEncodable, RustcDecodable)]
@@ -170,6 +175,7 @@ impl PlayerInput {
 #[derive(Debug, Clone, RustcEncodable, RustcDecodable)]
 pub struct PlayerInfo {
     pub name: String,
+    pub team: Option<TeamId>,
     pub stats: PlayerStats,
 }
 
@@ -180,10 +186,65 @@ pub struct PlayerStats {
     pub ping_ms: Option<u32>,
 }
 
+/// Statistics of a player name over all matches played on a server
+#[derive(Debug, Clone, Default, RustcEncodable, RustcDecodable)]
+pub struct LifetimeStats {
+    // Other players killed, by the reason of their death. Catches are counted separately.
+    pub kills: BTreeMap<String, u32>,
+
+    pub catches: u32,
+    pub deaths: u32,
+    pub catcher_time_s: f32,
+
+    // Number of times each item has been used, by item name
+    pub items_used: BTreeMap<String, u32>,
+
+    pub matches_played: u32,
+}
+
+impl LifetimeStats {
+    pub fn total_kills(&self) -> u32 {
+        self.kills.values().fold(0, |a, b| a + b)
+    }
+}
+
+/// Colors of the teams in `PlayerState::color`, as 0xRRGGBB
+pub const TEAM_COLORS: [u32; 4] = [0xe03c31, 0x2f6fdf, 0xe8c547, 0x3fb950];
+
+pub fn team_color(team: TeamId) -> u32 {
+    TEAM_COLORS[team as usize % TEAM_COLORS.len()]
+}
+
+/// Splits a color given as 0xRRGGBB into components in range [0,1]
+pub fn color_to_rgb(color: u32) -> (f32, f32, f32) {
+    (((color >> 16) & 0xff) as f32 / 255.0,
+     ((color >> 8) & 0xff) as f32 / 255.0,
+     (color & 0xff) as f32 / 255.0)
+}
+
+pub const MAX_NAME_LENGTH: usize = 16;
+
+/// Checks if `name` can be used as a player name. Names have to be non-empty, at most
+/// `MAX_NAME_LENGTH` characters long and may only contain alphanumeric characters, '-' or '_'.
+pub fn validate_name(name: &str) -> Result<(), String> {
+    if name.is_empty() {
+        return Err("Name must not be empty".to_string());
+    }
+    if name.chars().count() > MAX_NAME_LENGTH {
+        return Err(format!("Name must not be longer than {} characters", MAX_NAME_LENGTH));
+    }
+    if let Some(c) = name.chars().find(|&c| !(c.is_alphanumeric() || c == '-' || c == '_')) {
+        return Err(format!("Name contains invalid character '{}'", c));
+    }
+
+    Ok(())
+}
+
 impl PlayerInfo {
     pub fn new(name: String) -> PlayerInfo {
         PlayerInfo {
             name: name,
+            team: None,
             stats: PlayerStats::default(),
         }
     }
@@ -0,0 +1,430 @@
+use std::fs::File;
+use std::io::{self, Read, Write, Seek, SeekFrom, BufReader};
+use std::path::Path;
+
+use bincode::SizeLimit;
+use bincode::rustc_serialize::{encode, encode_into, decode, decode_from};
+
+use tick::DeltaEncodeTick;
+use super::{EntityId, EntityTypeId, PlayerId, PlayerInfo, TickNumber, GameInfo, MatchPhase,
+            Tick};
+
+// Replay files start with this, followed by the version and the `ReplayHeader`
+pub const REPLAY_MAGIC: &'static [u8; 8] = b"CATCHREP";
+
+// Files that were finished properly end with the offset of the index record and this
+pub const REPLAY_END_MAGIC: &'static [u8; 8] = b"CATCHEND";
+
+/// Needs to be increased whenever the format of replays or anything contained in them changes
+pub const REPLAY_VERSION: u32 = 1;
+
+pub const DEFAULT_KEYFRAME_INTERVAL: TickNumber = 150;
+
+// Encoded size of `RecordHeader`
+const RECORD_HEADER_SIZE: usize = 12;
+
+// Encoded size of the index offset and the end magic
+const FOOTER_SIZE: usize = 16;
+
+/// Stored once at the start of a replay
+#[derive(Debug, Clone, RustcEncodable, RustcDecodable)]
+pub struct ReplayHeader {
+    // Game info at the start of the recording. Later changes of the map or tick rate are
+    // contained in the events of the ticks.
+    pub game_info: GameInfo,
+
+    // Seed of the server's random number generator
+    pub seed: u32,
+
+    pub keyframe_interval: TickNumber,
+}
+
+/// A full tick together with everything that is needed to start playback from it, i.e.
+/// the state that a freshly connected client would be sent
+#[derive(Clone, RustcEncodable, RustcDecodable)]
+pub struct ReplayKeyframe {
+    pub map_name: String,
+    pub ticks_per_second: u32,
+    pub phase: MatchPhase,
+    pub phase_duration_s: Option<f32>,
+
+    // Players and net entities as of the end of `tick`, so the replication events contained
+    // in the tick have already been applied to these
+    pub players: Vec<(PlayerId, PlayerInfo)>,
+    pub entities: Vec<(EntityId, EntityTypeId, PlayerId)>,
+
+    pub tick: Tick,
+}
+
+/// Location of a keyframe in the file
+#[derive(Debug, Clone, Copy, RustcEncodable, RustcDecodable)]
+pub struct ReplayIndexEntry {
+    pub tick_number: TickNumber,
+    pub offset: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, RustcEncodable, RustcDecodable)]
+enum RecordKind {
+    Keyframe,
+    Delta,
+    Index,
+}
+
+/// Precedes the payload of each record, so that files can be scanned without decoding ticks
+#[derive(Debug, Clone, Copy, RustcEncodable, RustcDecodable)]
+struct RecordHeader {
+    kind: RecordKind,
+    tick_number: TickNumber,
+    len: u32,
+}
+
+#[derive(Clone)]
+pub enum ReplayRecord {
+    Keyframe(ReplayKeyframe),
+    Tick(Tick),
+}
+
+impl ReplayRecord {
+    pub fn tick(&self) -> &Tick {
+        match *self {
+            ReplayRecord::Keyframe(ref keyframe) => &keyframe.tick,
+            ReplayRecord::Tick(ref tick) => tick,
+        }
+    }
+}
+
+/// Writes a replay record by record. Ticks are delta encoded against the previous tick,
+/// except for a keyframe every `keyframe_interval` ticks. The index of keyframes is written
+/// by `finish`, which is also called on drop.
+pub struct ReplayWriter<W: Write> {
+    writer: Option<W>,
+    keyframe_interval: TickNumber,
+
+    // Number of bytes written so far
+    offset: u64,
+
+    index: Vec<ReplayIndexEntry>,
+    last_tick: Option<Tick>,
+    ticks_since_keyframe: TickNumber,
+}
+
+impl<W: Write> ReplayWriter<W> {
+    pub fn new(mut writer: W, header: &ReplayHeader) -> Result<ReplayWriter<W>, String> {
+        assert!(header.keyframe_interval > 0);
+
+        let header_data = try!(encode(header, SizeLimit::Infinite).map_err(|e| e.to_string()));
+        let version_data = try!(encode(&REPLAY_VERSION, SizeLimit::Infinite)
+                                    .map_err(|e| e.to_string()));
+
+        try!(writer.write_all(REPLAY_MAGIC).map_err(|e| e.to_string()));
+        try!(writer.write_all(&version_data).map_err(|e| e.to_string()));
+        try!(writer.write_all(&header_data).map_err(|e| e.to_string()));
+
+        Ok(ReplayWriter {
+            writer: Some(writer),
+            keyframe_interval: header.keyframe_interval,
+            offset: (REPLAY_MAGIC.len() + version_data.len() + header_data.len()) as u64,
+            index: Vec::new(),
+            last_tick: None,
+            ticks_since_keyframe: 0,
+        })
+    }
+
+    /// Does the next tick have to be written with `write_keyframe`?
+    pub fn needs_keyframe(&self) -> bool {
+        self.last_tick.is_none() || self.ticks_since_keyframe >= self.keyframe_interval
+    }
+
+    pub fn write_keyframe(&mut self, keyframe: &ReplayKeyframe) -> Result<(), String> {
+        let data = try!(encode(keyframe, SizeLimit::Infinite).map_err(|e| e.to_string()));
+
+        self.index.push(ReplayIndexEntry {
+            tick_number: keyframe.tick.tick_number,
+            offset: self.offset,
+        });
+        try!(self.write_record(RecordKind::Keyframe, keyframe.tick.tick_number, &data));
+
+        self.last_tick = Some(keyframe.tick.clone());
+        self.ticks_since_keyframe = 1;
+
+        // Make sure that at most the ticks since the last keyframe are lost if the server
+        // goes down without finishing the replay
+        self.writer.as_mut().unwrap().flush().map_err(|e| e.to_string())
+    }
+
+    pub fn write_tick(&mut self, tick: &Tick) -> Result<(), String> {
+        assert!(!self.needs_keyframe());
+
+        let data = {
+            let delta_encode_tick = DeltaEncodeTick {
+                last_tick: self.last_tick.as_ref().unwrap(),
+                tick: tick,
+            };
+            try!(encode(&delta_encode_tick, SizeLimit::Infinite).map_err(|e| e.to_string()))
+        };
+        try!(self.write_record(RecordKind::Delta, tick.tick_number, &data));
+
+        self.last_tick = Some(tick.clone());
+        self.ticks_since_keyframe += 1;
+        Ok(())
+    }
+
+    /// Writes the index and flushes. No more ticks can be written afterwards.
+    pub fn finish(&mut self) -> Result<(), String> {
+        if self.writer.is_none() {
+            return Ok(());
+        }
+
+        let index_offset = self.offset;
+        let data = try!(encode(&self.index, SizeLimit::Infinite).map_err(|e| e.to_string()));
+        let tick_number = self.last_tick.as_ref().map_or(0, |tick| tick.tick_number);
+        try!(self.write_record(RecordKind::Index, tick_number, &data));
+
+        let mut writer = self.writer.take().unwrap();
+        try!(encode_into(&index_offset, &mut writer, SizeLimit::Infinite)
+                 .map_err(|e| e.to_string()));
+        try!(writer.write_all(REPLAY_END_MAGIC).map_err(|e| e.to_string()));
+        writer.flush().map_err(|e| e.to_string())
+    }
+
+    fn write_record(&mut self, kind: RecordKind, tick_number: TickNumber, data: &[u8])
+                    -> Result<(), String> {
+        let header = RecordHeader {
+            kind: kind,
+            tick_number: tick_number,
+            len: data.len() as u32,
+        };
+
+        let writer = match self.writer.as_mut() {
+            Some(writer) => writer,
+            None => return Err("Replay has already been finished".to_string()),
+        };
+        try!(encode_into(&header, writer, SizeLimit::Infinite).map_err(|e| e.to_string()));
+        try!(writer.write_all(data).map_err(|e| e.to_string()));
+
+        self.offset += (RECORD_HEADER_SIZE + data.len()) as u64;
+        Ok(())
+    }
+}
+
+impl<W: Write> Drop for ReplayWriter<W> {
+    fn drop(&mut self) {
+        if let Err(error) = self.finish() {
+            warn!("couldn't finish replay: {}", error);
+        }
+    }
+}
+
+/// Reads fully into `buf`. Returns false if the end of the file is reached before that.
+fn read_all<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<bool> {
+    let mut pos = 0;
+    while pos < buf.len() {
+        match reader.read(&mut buf[pos..]) {
+            Ok(0) => return Ok(false),
+            Ok(n) => pos += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+/// Reads replays written by `ReplayWriter`. Files that were not finished, e.g. because the
+/// server crashed, can be read up to their last complete record.
+pub struct ReplayReader<R: Read + Seek> {
+    reader: R,
+    header: ReplayHeader,
+
+    // Offset of the first record
+    start_offset: u64,
+
+    index: Vec<ReplayIndexEntry>,
+
+    // Was the file finished properly?
+    complete: bool,
+
+    // The tick of the last record that was read, needed for decoding deltas
+    last_tick: Option<Tick>,
+}
+
+impl ReplayReader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<ReplayReader<BufReader<File>>, String> {
+        let file = try!(File::open(path).map_err(|e| e.to_string()));
+        ReplayReader::new(BufReader::new(file))
+    }
+}
+
+impl<R: Read + Seek> ReplayReader<R> {
+    pub fn new(mut reader: R) -> Result<ReplayReader<R>, String> {
+        let mut magic = [0; 8];
+        if !try!(read_all(&mut reader, &mut magic).map_err(|e| e.to_string())) ||
+           &magic != REPLAY_MAGIC {
+            return Err("Not a replay file".to_string());
+        }
+
+        let version: u32 = try!(decode_from(&mut reader, SizeLimit::Infinite)
+                                    .map_err(|e| e.to_string()));
+        if version != REPLAY_VERSION {
+            return Err(format!("Unsupported replay version {} (expected {})",
+                               version, REPLAY_VERSION));
+        }
+
+        let header: ReplayHeader = try!(decode_from(&mut reader, SizeLimit::Infinite)
+                                            .map_err(|e| e.to_string()));
+        let start_offset = try!(reader.seek(SeekFrom::Current(0)).map_err(|e| e.to_string()));
+
+        let mut replay = ReplayReader {
+            reader: reader,
+            header: header,
+            start_offset: start_offset,
+            index: Vec::new(),
+            complete: false,
+            last_tick: None,
+        };
+
+        match try!(replay.read_index()) {
+            Some(index) => {
+                replay.index = index;
+                replay.complete = true;
+            }
+            None => {
+                warn!("replay was not finished properly, scanning for keyframes");
+                replay.index = try!(replay.scan_index());
+            }
+        }
+
+        try!(replay.reader.seek(SeekFrom::Start(start_offset)).map_err(|e| e.to_string()));
+        Ok(replay)
+    }
+
+    pub fn header(&self) -> &ReplayHeader {
+        &self.header
+    }
+
+    /// Keyframes in the order of their tick numbers
+    pub fn index(&self) -> &[ReplayIndexEntry] {
+        &self.index
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// Continues reading at the last keyframe not after `tick_number`, or at the first
+    /// keyframe if there is none
+    pub fn seek(&mut self, tick_number: TickNumber) -> Result<(), String> {
+        let offset = match self.index.iter().rev()
+                               .find(|entry| entry.tick_number <= tick_number)
+                               .or(self.index.first()) {
+            Some(entry) => entry.offset,
+            None => self.start_offset,
+        };
+
+        self.last_tick = None;
+        try!(self.reader.seek(SeekFrom::Start(offset)).map_err(|e| e.to_string()));
+        Ok(())
+    }
+
+    /// Reads the next tick. Returns `None` at the end of the replay.
+    pub fn next_record(&mut self) -> Result<Option<ReplayRecord>, String> {
+        let (header, data) = match try!(self.read_record()) {
+            Some(record) => record,
+            None => return Ok(None),
+        };
+
+        match header.kind {
+            RecordKind::Keyframe => {
+                let keyframe: ReplayKeyframe = try!(decode(&data).map_err(|e| e.to_string()));
+                self.last_tick = Some(keyframe.tick.clone());
+                Ok(Some(ReplayRecord::Keyframe(keyframe)))
+            }
+            RecordKind::Delta => {
+                let delta: Tick = try!(decode(&data).map_err(|e| e.to_string()));
+                match self.last_tick.as_mut() {
+                    Some(last_tick) => {
+                        last_tick.load_delta(&delta);
+                        Ok(Some(ReplayRecord::Tick(last_tick.clone())))
+                    }
+                    None => Err("Replay delta without preceding keyframe".to_string()),
+                }
+            }
+            RecordKind::Index => Ok(None),
+        }
+    }
+
+    /// Reads the header and payload of the record at the current position. Returns `None` at
+    /// the end of the file, including when the last record is incomplete.
+    fn read_record(&mut self) -> Result<Option<(RecordHeader, Vec<u8>)>, String> {
+        let mut header_data = [0; RECORD_HEADER_SIZE];
+        if !try!(read_all(&mut self.reader, &mut header_data).map_err(|e| e.to_string())) {
+            return Ok(None);
+        }
+
+        let header: RecordHeader = try!(decode(&header_data).map_err(|e| e.to_string()));
+        let mut data = vec![0; header.len as usize];
+        if !try!(read_all(&mut self.reader, &mut data).map_err(|e| e.to_string())) {
+            warn!("replay ends in the middle of tick {}", header.tick_number);
+            return Ok(None);
+        }
+
+        Ok(Some((header, data)))
+    }
+
+    /// Reads the index from the end of the file, if the file was finished
+    fn read_index(&mut self) -> Result<Option<Vec<ReplayIndexEntry>>, String> {
+        let end = try!(self.reader.seek(SeekFrom::End(0)).map_err(|e| e.to_string()));
+        if end < self.start_offset + FOOTER_SIZE as u64 {
+            return Ok(None);
+        }
+
+        try!(self.reader.seek(SeekFrom::Start(end - FOOTER_SIZE as u64))
+                 .map_err(|e| e.to_string()));
+        let mut footer = [0; FOOTER_SIZE];
+        if !try!(read_all(&mut self.reader, &mut footer).map_err(|e| e.to_string())) ||
+           &footer[8..] != &REPLAY_END_MAGIC[..] {
+            return Ok(None);
+        }
+
+        let index_offset: u64 = try!(decode(&footer[..8]).map_err(|e| e.to_string()));
+        try!(self.reader.seek(SeekFrom::Start(index_offset)).map_err(|e| e.to_string()));
+        match try!(self.read_record()) {
+            Some((ref header, ref data)) if header.kind == RecordKind::Index =>
+                Ok(Some(try!(decode(data).map_err(|e| e.to_string())))),
+            _ => Ok(None),
+        }
+    }
+
+    /// Builds the index by going through all the records
+    fn scan_index(&mut self) -> Result<Vec<ReplayIndexEntry>, String> {
+        let end = try!(self.reader.seek(SeekFrom::End(0)).map_err(|e| e.to_string()));
+        let mut index = Vec::new();
+        let mut offset = self.start_offset;
+        try!(self.reader.seek(SeekFrom::Start(offset)).map_err(|e| e.to_string()));
+
+        loop {
+            let mut header_data = [0; RECORD_HEADER_SIZE];
+            if !try!(read_all(&mut self.reader, &mut header_data).map_err(|e| e.to_string())) {
+                break;
+            }
+            let header: RecordHeader = try!(decode(&header_data).map_err(|e| e.to_string()));
+
+            // The last record may have been cut off
+            let next_offset = offset + (RECORD_HEADER_SIZE + header.len as usize) as u64;
+            if next_offset > end {
+                break;
+            }
+
+            if header.kind == RecordKind::Keyframe {
+                index.push(ReplayIndexEntry {
+                    tick_number: header.tick_number,
+                    offset: offset,
+                });
+            }
+
+            offset = next_offset;
+            try!(self.reader.seek(SeekFrom::Start(offset)).map_err(|e| e.to_string()));
+        }
+
+        Ok(index)
+    }
+}
@@ -0,0 +1,421 @@
+use std::collections::HashMap;
+
+use ecs;
+use ecs::entity::IndexedEntity;
+use ecs::{Aspect, EntityData, EntityIter, ComponentManager};
+use na::Vec2;
+
+use math;
+use util::{CachedAspect, FixedHashMap};
+use components::{HasPosition, HasShape, HasWallPosition};
+
+// Cell sizes for walls, which can be long, and for moving entities, which are small
+pub const WALL_CELL_SIZE: f32 = 64.0;
+pub const ENTITY_CELL_SIZE: f32 = 32.0;
+
+pub type Cell = (i32, i32);
+
+/// Computes the axis-aligned bounding box of an entity, given as minimum and maximum corner
+pub type BoundsFn<T> = fn(EntityData<T>, &T) -> (Vec2<f32>, Vec2<f32>);
+
+/// Uniform grid broadphase. Every entity is stored in each of the cells that its bounding box
+/// touches, so that queries only need to look at entities in nearby cells.
+pub struct SpatialHash<T: ComponentManager> {
+    cell_size: f32,
+
+    cells: FixedHashMap<Cell, FixedHashMap<ecs::Entity, IndexedEntity<T>>>,
+
+    // Range of cells (inclusive) that each entity is stored in
+    entity_cells: HashMap<ecs::Entity, (Cell, Cell)>,
+}
+
+impl<T: ComponentManager> SpatialHash<T> {
+    pub fn new(cell_size: f32) -> SpatialHash<T> {
+        assert!(cell_size > 0.0);
+
+        SpatialHash {
+            cell_size: cell_size,
+            cells: FixedHashMap::default(),
+            entity_cells: HashMap::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.clear();
+        self.entity_cells.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entity_cells.len()
+    }
+
+    /// Inserts an entity with the given bounding box, or moves it if it is already stored.
+    /// Moving within the same cells is cheap.
+    pub fn insert(&mut self, entity: &EntityData<T>, min: Vec2<f32>, max: Vec2<f32>) {
+        let range = self.cell_range(min, max);
+
+        match self.entity_cells.get(&***entity) {
+            Some(&old_range) if old_range == range => return,
+            Some(_) => self.remove(&***entity),
+            None => {}
+        }
+
+        let ((x_min, y_min), (x_max, y_max)) = range;
+        for x in x_min..x_max+1 {
+            for y in y_min..y_max+1 {
+                self.cells.entry((x, y))
+                    .or_insert_with(FixedHashMap::default)
+                    .insert(***entity, (**entity).__clone());
+            }
+        }
+        self.entity_cells.insert(***entity, range);
+    }
+
+    pub fn remove(&mut self, entity: &ecs::Entity) {
+        let ((x_min, y_min), (x_max, y_max)) = match self.entity_cells.remove(entity) {
+            Some(range) => range,
+            None => return,
+        };
+
+        for x in x_min..x_max+1 {
+            for y in y_min..y_max+1 {
+                let empty = match self.cells.get_mut(&(x, y)) {
+                    Some(cell) => {
+                        cell.remove(entity);
+                        cell.is_empty()
+                    }
+                    None => false
+                };
+                if empty {
+                    self.cells.remove(&(x, y));
+                }
+            }
+        }
+    }
+
+    /// Replaces the contents of `result` by each entity whose cells touch the box from `min`
+    /// to `max`. The entities do not necessarily overlap with the box, so an exact check is
+    /// still needed.
+    pub fn query<'a>(&'a self, min: Vec2<f32>, max: Vec2<f32>,
+                     result: &mut Vec<EntityData<'a, T>>) {
+        self.collect(self.cell_range(min, max), |_| true, result)
+    }
+
+    /// Replaces the contents of `result` by each entity whose cells touch the line segment
+    /// from `a` to `b`, grown by `margin` in every direction
+    pub fn query_segment<'a>(&'a self, a: Vec2<f32>, b: Vec2<f32>, margin: f32,
+                             result: &mut Vec<EntityData<'a, T>>) {
+        let min = Vec2::new(a.x.min(b.x), a.y.min(b.y)) - Vec2::new(margin, margin);
+        let max = Vec2::new(a.x.max(b.x), a.y.max(b.y)) + Vec2::new(margin, margin);
+        let cell_size = self.cell_size;
+
+        // Long diagonal segments have a large bounding box, so skip the cells that the
+        // segment does not pass through
+        self.collect(self.cell_range(min, max), |(x, y)| {
+            let cell_min = Vec2::new(x as f32, y as f32) * cell_size -
+                           Vec2::new(margin, margin);
+            let cell_max = Vec2::new((x + 1) as f32, (y + 1) as f32) * cell_size +
+                           Vec2::new(margin, margin);
+            math::line_segment_aabb_overlap(a, b, cell_min, cell_max)
+        }, result)
+    }
+
+    fn cell(&self, p: Vec2<f32>) -> Cell {
+        ((p.x / self.cell_size).floor() as i32,
+         (p.y / self.cell_size).floor() as i32)
+    }
+
+    fn cell_range(&self, min: Vec2<f32>, max: Vec2<f32>) -> (Cell, Cell) {
+        (self.cell(min), self.cell(max))
+    }
+
+    fn collect<'a, F>(&'a self, range: (Cell, Cell), filter: F,
+                      result: &mut Vec<EntityData<'a, T>>)
+        where F: Fn(Cell) -> bool {
+        result.clear();
+
+        let ((x_min, y_min), (x_max, y_max)) = range;
+
+        // Huge queries, e.g. long rays, may cover more cells than there are occupied ones
+        let num_range_cells = (x_max - x_min + 1) as usize * (y_max - y_min + 1) as usize;
+        if num_range_cells <= self.cells.len() {
+            for x in x_min..x_max+1 {
+                for y in y_min..y_max+1 {
+                    if let Some(entities) = self.cells.get(&(x, y)) {
+                        if filter((x, y)) {
+                            self.collect_cell(entities, result);
+                        }
+                    }
+                }
+            }
+        } else {
+            for (&(x, y), entities) in self.cells.iter() {
+                if x >= x_min && x <= x_max && y >= y_min && y <= y_max && filter((x, y)) {
+                    self.collect_cell(entities, result);
+                }
+            }
+        }
+    }
+
+    fn collect_cell<'a>(&'a self, entities: &'a FixedHashMap<ecs::Entity, IndexedEntity<T>>,
+                        result: &mut Vec<EntityData<'a, T>>) {
+        for entity in EntityIter::Map(entities.values()) {
+            // Only entities that are stored in multiple cells can be found twice. There are
+            // few of them in a query, so a linear search is cheap.
+            let (min, max) = self.entity_cells[&**entity];
+            if min == max || !result.iter().any(|other| **other == **entity) {
+                result.push(entity);
+            }
+        }
+    }
+}
+
+/// Bounding box of a wall segment
+pub fn wall_bounds<T: HasWallPosition>(entity: EntityData<T>, components: &T)
+                                       -> (Vec2<f32>, Vec2<f32>) {
+    let p = &components.wall_position()[entity];
+    (Vec2::new(p.pos_a.x.min(p.pos_b.x), p.pos_a.y.min(p.pos_b.y)),
+     Vec2::new(p.pos_a.x.max(p.pos_b.x), p.pos_a.y.max(p.pos_b.y)))
+}
+
+/// Bounding box of a shape in any orientation
+pub fn shape_bounds<T: HasPosition + HasShape>(entity: EntityData<T>, components: &T)
+                                               -> (Vec2<f32>, Vec2<f32>) {
+    let p = components.position()[entity].p;
+    let r = components.shape()[entity].bounding_radius();
+    (p - Vec2::new(r, r), p + Vec2::new(r, r))
+}
+
+/// A `CachedAspect` whose entities are additionally kept in a `SpatialHash`. Entities are
+/// inserted with their bounds on activation; entities that move need `update` to be called
+/// before querying.
+pub struct SpatialAspect<T: ComponentManager> {
+    aspect: CachedAspect<T>,
+    hash: SpatialHash<T>,
+    bounds: BoundsFn<T>,
+}
+
+impl<T: ComponentManager> SpatialAspect<T> {
+    pub fn new(aspect: Aspect<T>, cell_size: f32, bounds: BoundsFn<T>) -> SpatialAspect<T> {
+        SpatialAspect {
+            aspect: CachedAspect::new(aspect),
+            hash: SpatialHash::new(cell_size),
+            bounds: bounds,
+        }
+    }
+
+    pub fn check(&self, entity: &EntityData<T>, components: &T) -> bool {
+        self.aspect.check(entity, components)
+    }
+
+    pub fn activated(&mut self, entity: &EntityData<T>, components: &T) {
+        self.aspect.activated(entity, components);
+        self.sync(entity, components);
+    }
+
+    pub fn reactivated(&mut self, entity: &EntityData<T>, components: &T) {
+        self.aspect.reactivated(entity, components);
+        self.sync(entity, components);
+    }
+
+    pub fn deactivated(&mut self, entity: &EntityData<T>, components: &T) {
+        self.aspect.deactivated(entity, components);
+        self.hash.remove(&***entity);
+    }
+
+    /// Moves every entity to the cells of its current bounds
+    pub fn update(&mut self, components: &T) {
+        for entity in self.aspect.iter() {
+            let (min, max) = (self.bounds)(entity, components);
+            self.hash.insert(&entity, min, max);
+        }
+    }
+
+    /// Like `update`, but with custom bounds, e.g. to cover the movement of entities
+    pub fn update_with<F>(&mut self, bounds: F)
+        where F: Fn(EntityData<T>) -> (Vec2<f32>, Vec2<f32>) {
+        for entity in self.aspect.iter() {
+            let (min, max) = bounds(entity);
+            self.hash.insert(&entity, min, max);
+        }
+    }
+
+    pub fn iter<'a>(&'a self) -> EntityIter<'a, T> {
+        self.aspect.iter()
+    }
+
+    /// Replaces the contents of `result` by the entities that might overlap with the box from
+    /// `min` to `max`
+    pub fn query<'a>(&'a self, min: Vec2<f32>, max: Vec2<f32>,
+                     result: &mut Vec<EntityData<'a, T>>) {
+        self.hash.query(min, max, result)
+    }
+
+    /// Replaces the contents of `result` by the entities that might be touched by a shape with
+    /// radius `margin` moving from `a` to `b`
+    pub fn query_segment<'a>(&'a self, a: Vec2<f32>, b: Vec2<f32>, margin: f32,
+                             result: &mut Vec<EntityData<'a, T>>) {
+        self.hash.query_segment(a, b, margin, result)
+    }
+
+    fn sync(&mut self, entity: &EntityData<T>, components: &T) {
+        if self.aspect.contains(&***entity) {
+            let (min, max) = (self.bounds)(*entity, components);
+            self.hash.insert(entity, min, max);
+        } else {
+            self.hash.remove(&***entity);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ecs;
+    use ecs::{BuildData, World};
+    use na::Vec2;
+
+    use components::Position;
+
+    use super::*;
+
+    components! {
+        struct TestComponents {
+            #[hot] position: Position,
+        }
+    }
+
+    systems! {
+        struct TestSystems<TestComponents, ()> {
+        }
+    }
+
+    fn create_entity(world: &mut World<TestSystems>) -> ecs::Entity {
+        let entity = world.create_entity(|e: BuildData<TestComponents>, c: &mut TestComponents| {
+            c.position.add(&e, Position { p: Vec2::new(0.0, 0.0) });
+        });
+        world.flush_queue();
+        entity
+    }
+
+    fn insert(hash: &mut SpatialHash<TestComponents>, world: &mut World<TestSystems>,
+              entity: ecs::Entity, min: (f32, f32), max: (f32, f32)) {
+        world.with_entity_data(&entity, |e, _| {
+            hash.insert(&e, Vec2::new(min.0, min.1), Vec2::new(max.0, max.1));
+        }).unwrap();
+    }
+
+    fn query(hash: &SpatialHash<TestComponents>, min: (f32, f32), max: (f32, f32))
+             -> Vec<ecs::Entity> {
+        let mut result = Vec::new();
+        hash.query(Vec2::new(min.0, min.1), Vec2::new(max.0, max.1), &mut result);
+        result.iter().map(|e| **e).collect()
+    }
+
+    #[test]
+    fn insert_and_query() {
+        let mut world = World::<TestSystems>::new();
+        let mut hash = SpatialHash::new(32.0);
+        let a = create_entity(&mut world);
+        let b = create_entity(&mut world);
+
+        insert(&mut hash, &mut world, a, (1.0, 1.0), (5.0, 5.0));
+        insert(&mut hash, &mut world, b, (100.0, 100.0), (105.0, 105.0));
+        assert_eq!(hash.len(), 2);
+
+        assert_eq!(query(&hash, (10.0, 10.0), (12.0, 12.0)), vec![a]);
+        assert_eq!(query(&hash, (99.0, 99.0), (101.0, 101.0)), vec![b]);
+        assert!(query(&hash, (200.0, 200.0), (210.0, 210.0)).is_empty());
+    }
+
+    #[test]
+    fn entity_across_cell_borders_is_found_once() {
+        let mut world = World::<TestSystems>::new();
+        let mut hash = SpatialHash::new(32.0);
+        let a = create_entity(&mut world);
+
+        // Touches the four cells around (32, 32)
+        insert(&mut hash, &mut world, a, (30.0, 30.0), (34.0, 34.0));
+
+        assert_eq!(query(&hash, (0.0, 0.0), (100.0, 100.0)), vec![a]);
+        assert_eq!(query(&hash, (1.0, 1.0), (2.0, 2.0)), vec![a]);
+        assert_eq!(query(&hash, (40.0, 1.0), (41.0, 2.0)), vec![a]);
+        assert_eq!(query(&hash, (1.0, 40.0), (2.0, 41.0)), vec![a]);
+        assert_eq!(query(&hash, (40.0, 40.0), (41.0, 41.0)), vec![a]);
+        assert!(query(&hash, (70.0, 70.0), (71.0, 71.0)).is_empty());
+    }
+
+    #[test]
+    fn move_across_cell_borders() {
+        let mut world = World::<TestSystems>::new();
+        let mut hash = SpatialHash::new(32.0);
+        let a = create_entity(&mut world);
+
+        insert(&mut hash, &mut world, a, (1.0, 1.0), (5.0, 5.0));
+        insert(&mut hash, &mut world, a, (28.0, 1.0), (36.0, 5.0));
+        assert_eq!(query(&hash, (1.0, 1.0), (2.0, 2.0)), vec![a]);
+        assert_eq!(query(&hash, (40.0, 1.0), (41.0, 2.0)), vec![a]);
+
+        insert(&mut hash, &mut world, a, (40.0, 1.0), (45.0, 5.0));
+        assert!(query(&hash, (1.0, 1.0), (2.0, 2.0)).is_empty());
+        assert_eq!(query(&hash, (40.0, 1.0), (41.0, 2.0)), vec![a]);
+        assert_eq!(hash.len(), 1);
+    }
+
+    #[test]
+    fn remove_across_cell_borders() {
+        let mut world = World::<TestSystems>::new();
+        let mut hash = SpatialHash::new(32.0);
+        let a = create_entity(&mut world);
+        let b = create_entity(&mut world);
+
+        insert(&mut hash, &mut world, a, (30.0, 30.0), (34.0, 34.0));
+        insert(&mut hash, &mut world, b, (33.0, 33.0), (35.0, 35.0));
+
+        hash.remove(&a);
+        assert_eq!(hash.len(), 1);
+        assert_eq!(query(&hash, (0.0, 0.0), (100.0, 100.0)), vec![b]);
+        assert!(query(&hash, (1.0, 1.0), (2.0, 2.0)).is_empty());
+
+        // Removing twice does nothing
+        hash.remove(&a);
+        assert_eq!(hash.len(), 1);
+    }
+
+    #[test]
+    fn negative_coordinates() {
+        let mut world = World::<TestSystems>::new();
+        let mut hash = SpatialHash::new(32.0);
+        let a = create_entity(&mut world);
+        let b = create_entity(&mut world);
+
+        // Cells are rounded down, so these are in the cell left of and above the origin
+        insert(&mut hash, &mut world, a, (-1.0, -1.0), (-0.5, -0.5));
+        insert(&mut hash, &mut world, b, (-40.0, 1.0), (-35.0, 5.0));
+
+        assert_eq!(query(&hash, (-30.0, -30.0), (-20.0, -20.0)), vec![a]);
+        assert!(query(&hash, (0.5, 0.5), (1.0, 1.0)).is_empty());
+        assert_eq!(query(&hash, (-50.0, 2.0), (-45.0, 3.0)), vec![b]);
+        assert!(query(&hash, (-20.0, 2.0), (-10.0, 3.0)).is_empty());
+
+        let mut found = query(&hash, (-100.0, -100.0), (-0.1, 100.0));
+        found.sort_by_key(|&e| if e == a { 0 } else { 1 });
+        assert_eq!(found, vec![a, b]);
+    }
+
+    #[test]
+    fn query_segment_skips_unvisited_cells() {
+        let mut world = World::<TestSystems>::new();
+        let mut hash = SpatialHash::new(32.0);
+        let a = create_entity(&mut world);
+        let b = create_entity(&mut world);
+
+        // On the diagonal, and in a corner of the segment's bounding box
+        insert(&mut hash, &mut world, a, (48.0, 48.0), (50.0, 50.0));
+        insert(&mut hash, &mut world, b, (120.0, 2.0), (122.0, 4.0));
+
+        let mut result = Vec::new();
+        hash.query_segment(Vec2::new(0.0, 0.0), Vec2::new(127.0, 127.0), 0.0, &mut result);
+        let found = result.iter().map(|e| **e).collect::<Vec<_>>();
+        assert_eq!(found, vec![a]);
+    }
+}
@@ -1,4 +1,5 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hasher, BuildHasherDefault};
 
 use ecs;
 use ecs::entity::IndexedEntity;
@@ -39,22 +40,54 @@ impl PeriodicTimer {
         }
     }
 
+    // Time left until the next period
+    pub fn remaining_s(&self) -> f32 {
+        (self.period_s - self.accum_s).max(0.0)
+    }
+
     // Percentual progress until next period
     pub fn progress(&self) -> f32 {
         self.accum_s / self.period_s
     }
 }
 
+/// FNV-1a hash function. Unlike the default hasher of `HashMap`, it is not seeded randomly.
+pub struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> FnvHasher {
+        FnvHasher(0xcbf29ce484222325)
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+/// Hash map that is iterated in the same order in every run, given the same sequence of
+/// operations. Needed wherever iteration order can affect the simulation.
+pub type FixedHashMap<K, V> = HashMap<K, V, BuildHasherDefault<FnvHasher>>;
+pub type FixedHashSet<K> = HashSet<K, BuildHasherDefault<FnvHasher>>;
+
 pub struct CachedAspect<T: ComponentManager> {
     pub aspect: Aspect<T>,
-    interested: HashMap<ecs::Entity, ecs::IndexedEntity<T>>,
+    interested: FixedHashMap<ecs::Entity, ecs::IndexedEntity<T>>,
 }
 
 impl<T: ComponentManager> CachedAspect<T> {
     pub fn new(aspect: Aspect<T>) -> CachedAspect<T> {
         CachedAspect {
             aspect: aspect,
-            interested: HashMap::new(),
+            interested: FixedHashMap::default(),
         }
     }
 
@@ -83,8 +116,12 @@ impl<T: ComponentManager> CachedAspect<T> {
         self.interested.remove(entity);
     }
 
+    pub fn contains(&self, entity: &ecs::Entity) -> bool {
+        self.interested.contains_key(entity)
+    }
+
     pub fn iter<'a>(&'a self) -> EntityIter<'a, T> {
-        EntityIter::Map(self.interested.values()) 
+        EntityIter::Map(self.interested.values())
     }
 }
 
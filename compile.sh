@@ -1,3 +1,4 @@
 #!/bin/sh
 cargo build "$@" --manifest-path=catch_server/Cargo.toml &&
-cargo build "$@" --manifest-path=catch_client/Cargo.toml
+cargo build "$@" --manifest-path=catch_client/Cargo.toml &&
+cargo build "$@" --manifest-path=catch_analysis/Cargo.toml